@@ -1,13 +1,23 @@
 use color_print::cprintln;
 use failure::{Error, ResultExt};
+use regex::Regex;
+use rustpython_parser::ast::fold::{self, Fold};
 use rustpython_parser::ast::{
-    Arg, ArgWithDefault, Expr, Ranged, Stmt, StmtAnnAssign, StmtAssign, StmtClassDef,
-    StmtFunctionDef,
+    Arg, ArgWithDefault, Arguments, ExceptHandlerExceptHandler, Expr, ExprContext, ExprLambda,
+    ExprName, Ranged, Stmt, StmtAnnAssign, StmtAssign, StmtAsyncFunctionDef, StmtClassDef,
+    StmtFunctionDef, StmtGlobal, StmtImport, StmtImportFrom, StmtNonlocal, StmtTypeAlias,
 };
+use rustpython_parser::text_size::TextRange;
 use rustpython_parser::{ast, Parse};
-use std::{io::Read, path::PathBuf};
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    io::{ErrorKind, Read},
+    ops::Range,
+    path::PathBuf,
+};
 
-use crate::python_def::{ArgType, Attribute, Class, Method};
+use crate::python_def::{ArgType, Attribute, Class, Comment, Import, Method, TypeAlias};
 
 pub fn parse_ast(
     path: &PathBuf,
@@ -19,12 +29,35 @@ pub fn parse_ast(
         .read_to_string(&mut contents)
         .with_context(|_| format!("Could not read file {:?}", path))?;
 
-    let source_path = source_path.unwrap_or(String::from("./"));
+    parse_contents(contents, source_path.or(Some(path.display().to_string())))
+}
 
-    let ast = ast::Suite::parse(&contents, &source_path)
-        .with_context(|_| format!("Could not parse file {:?}", path));
+/// The source in a node's byte `range`. A range that does not fall on
+/// character boundaries, which should not happen but would otherwise panic
+/// on non-ASCII source, is widened to the nearest ones.
+fn source_text(original_code: &str, range: impl Into<Range<usize>>) -> &str {
+    let range = range.into();
+    let mut start = range.start.min(original_code.len());
+    let mut end = range.end.clamp(start, original_code.len());
+
+    while !original_code.is_char_boundary(start) {
+        start -= 1;
+    }
+    while !original_code.is_char_boundary(end) {
+        end += 1;
+    }
+
+    &original_code[start..end]
+}
+
+/// Parses Python source that is already in memory, e.g. read from stdin.
+pub fn parse_contents(
+    contents: String,
+    source_path: Option<String>,
+) -> Result<(Vec<Stmt>, String), Error> {
+    let source_path = source_path.unwrap_or(String::from("./"));
 
-    return match ast {
+    return match try_parse_contents(&contents, &source_path) {
         Ok(ast) => Ok((ast, contents)),
         Err(e) => {
             cprintln!("<R> Error: {}</R>", e);
@@ -33,14 +66,26 @@ pub fn parse_ast(
     };
 }
 
+/// Parses Python source, failing with `ErrorKind::InvalidData` on syntax
+/// errors instead of returning no statements like `parse_contents`.
+pub fn try_parse_contents(contents: &str, source_path: &str) -> Result<Vec<Stmt>, Error> {
+    ast::Suite::parse(contents, source_path).map_err(|e| {
+        Error::from(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Could not parse {}: {}", source_path, e),
+        ))
+    })
+}
+
 pub fn parse_root_ast(
     ast: Vec<Stmt>,
     original_code: &String,
     path: &String,
-) -> Result<(Vec<Class>, Vec<Method>, Vec<Attribute>), Error> {
+) -> Result<(Vec<Class>, Vec<Method>, Vec<Attribute>, Vec<TypeAlias>), Error> {
     let mut classes = Vec::new();
     let mut functions = Vec::new();
     let mut attributes = Vec::new();
+    let mut type_aliases = Vec::new();
 
     for stmt in ast {
         match stmt {
@@ -64,19 +109,177 @@ pub fn parse_root_ast(
                 functions.push(function)
             }
             Stmt::Assign(a) => attributes.extend(parse_assign(&a, original_code, path)?),
-            Stmt::AnnAssign(a) => {
-                let attribute = parse_ann_assign(&a, original_code, path)
-                    .with_context(|e| format!("Error parsing attribute: {}", e))?;
+            Stmt::AnnAssign(a) => match parse_ann_type_alias(&a, original_code, path)? {
+                Some(type_alias) => type_aliases.push(type_alias),
+                None => {
+                    let attribute = parse_ann_assign(&a, original_code, path)
+                        .with_context(|e| format!("Error parsing attribute: {}", e))?;
 
-                if attribute.is_some() {
-                    attributes.push(attribute.unwrap());
+                    if attribute.is_some() {
+                        attributes.push(attribute.unwrap());
+                    }
                 }
-            }
+            },
+            Stmt::TypeAlias(t) => type_aliases.push(parse_type_alias(&t, original_code, path)?),
             _ => {}
         }
     }
 
-    return Ok((classes, functions, attributes));
+    return Ok((classes, group_overloads(functions), attributes, type_aliases));
+}
+
+/// The classes and functions defined under top-level `if` statements, e.g.
+/// `if sys.platform == "win32": def foo(): ...`, from every branch and
+/// nested `if`. They are tagged as conditional.
+pub fn parse_conditional_defs(
+    ast: &[Stmt],
+    original_code: &String,
+    path: &String,
+) -> Result<(Vec<Class>, Vec<Method>), Error> {
+    let mut statements = Vec::new();
+    conditional_statements(ast, &mut statements);
+
+    let (mut classes, mut functions, _, _) = parse_root_ast(statements, original_code, path)?;
+    for class in &mut classes {
+        class.set_conditional(true);
+    }
+    for function in &mut functions {
+        function.set_conditional(true);
+    }
+
+    Ok((classes, functions))
+}
+
+/// The statements of the branches of the `if` statements among `stmts`,
+/// descending into the nested ones.
+fn conditional_statements(stmts: &[Stmt], found: &mut Vec<Stmt>) {
+    for stmt in stmts {
+        if let Stmt::If(i) = stmt {
+            for branch in [&i.body, &i.orelse] {
+                found.extend(branch.iter().filter(|s| !matches!(s, Stmt::If(_))).cloned());
+                conditional_statements(branch, found);
+            }
+        }
+    }
+}
+
+/// The names removed by top-level `del` statements, with the line of each.
+pub fn parse_deletions(ast: &[Stmt], original_code: &String) -> Vec<(String, usize)> {
+    let mut deletions = Vec::new();
+    for stmt in ast {
+        if let Stmt::Delete(d) = stmt {
+            let line = line_of(original_code, usize::from(d.range.start()));
+            for target in &d.targets {
+                if let Expr::Name(n) = target {
+                    deletions.push((n.id.to_string(), line));
+                }
+            }
+        }
+    }
+
+    deletions
+}
+
+/// Moves `@overload` signatures under the implementation that follows them.
+/// Overloads without an implementation, as in stubs, are kept as they are.
+fn group_overloads(methods: Vec<Method>) -> Vec<Method> {
+    let mut grouped = Vec::new();
+    let mut overloads: Vec<Method> = Vec::new();
+
+    for mut method in methods {
+        if overloads.first().map_or(false, |o| o.name != method.name) {
+            grouped.append(&mut overloads);
+        }
+
+        if method.is_overload() {
+            overloads.push(method);
+        } else {
+            method.set_overloads(std::mem::take(&mut overloads));
+            grouped.push(method);
+        }
+    }
+
+    grouped.append(&mut overloads);
+    grouped
+}
+
+pub fn parse_imports(ast: &Vec<Stmt>, original_code: &String, path: &String) -> Vec<Import> {
+    let mut imports = Vec::new();
+
+    for stmt in ast {
+        let (names, range) = match stmt {
+            Stmt::Import(i) => (parse_import(i, path), i.range),
+            Stmt::ImportFrom(i) => (parse_import_from(i, path), i.range),
+            _ => continue,
+        };
+
+        let span = (usize::from(range.start()), usize::from(range.end()));
+        for mut import in names {
+            import.set_line(line_of(original_code, span.0));
+            import.set_span(span);
+            imports.push(import);
+        }
+    }
+
+    imports
+}
+
+fn parse_import(import: &StmtImport, path: &String) -> Vec<Import> {
+    import
+        .names
+        .iter()
+        .map(|a| {
+            Import::new(
+                path.to_string(),
+                None,
+                a.name.to_string(),
+                a.asname.as_ref().map(|n| n.to_string()),
+                0,
+            )
+        })
+        .collect()
+}
+
+fn parse_import_from(import_from: &StmtImportFrom, path: &String) -> Vec<Import> {
+    let module = import_from.module.as_ref().map(|m| m.to_string());
+    let level = match &import_from.level {
+        Some(l) => l.to_u32(),
+        None => 0,
+    };
+
+    import_from
+        .names
+        .iter()
+        .map(|a| {
+            Import::new(
+                path.to_string(),
+                module.clone(),
+                a.name.to_string(),
+                a.asname.as_ref().map(|n| n.to_string()),
+                level,
+            )
+        })
+        .collect()
+}
+
+/// Collects the names bound by an assignment target, descending into tuple
+/// and list unpacking. The name under a `*` is marked as starred.
+fn assign_targets(
+    target: &Expr,
+    starred: bool,
+    names: &mut Vec<(String, (usize, usize), bool)>,
+) {
+    match target {
+        Expr::Name(n) => names.push((
+            n.id.to_string(),
+            (usize::from(n.range.start()), usize::from(n.range.end())),
+            starred,
+        )),
+        Expr::Tuple(t) => t.elts.iter().for_each(|e| assign_targets(e, false, names)),
+        Expr::List(l) => l.elts.iter().for_each(|e| assign_targets(e, false, names)),
+        Expr::Starred(s) => assign_targets(&s.value, true, names),
+        _ => {}
+    }
 }
 
 fn parse_assign(
@@ -84,31 +287,33 @@ fn parse_assign(
     original_code: &String,
     path: &String,
 ) -> Result<Vec<Attribute>, Error> {
-    let names = assign
-        .targets
-        .iter()
-        .filter(|e| match e {
-            Expr::Name(_) => true,
-            _ => false,
-        })
-        .map(|n| match n {
-            Expr::Name(n) => n.id.to_string(),
-            _ => panic!("This should never happen"),
-        });
+    let mut names = Vec::new();
+    for target in &assign.targets {
+        assign_targets(target, false, &mut names);
+    }
 
-    let value_range = assign.value.range();
-    let value = original_code[value_range].to_string();
+    let value = source_text(original_code, assign.value.range()).to_string();
 
+    let line = line_of(original_code, usize::from(assign.range.start()));
     let mut attributes = Vec::new();
 
-    for name in names {
-        attributes.push(Attribute::new(
+    for (name, name_span, starred) in names {
+        let mut attribute = Attribute::new(
             path.to_string(),
             name,
             None,
             Some(value.clone()),
             ArgType::Not,
-        ));
+        );
+        attribute.set_line(line);
+        // `a = b = 1` and `a, b = f()` cannot be annotated in place, so only
+        // single plain targets get a span.
+        if assign.targets.len() == 1 && matches!(assign.targets[0], Expr::Name(_)) {
+            attribute.set_name_span(name_span);
+        }
+        attribute.set_constant(Attribute::is_constant_name(&attribute.name));
+        attribute.set_starred(starred);
+        attributes.push(attribute);
     }
 
     Ok(attributes)
@@ -119,30 +324,75 @@ fn parse_ann_assign(
     original_code: &String,
     path: &String,
 ) -> Result<Option<Attribute>, Error> {
-    let name = match *ann_assign.target.clone() {
-        Expr::Name(n) => Some(n.id.to_string()),
-        _ => None,
+    let (name, name_span) = match *ann_assign.target.clone() {
+        Expr::Name(n) => (
+            n.id.to_string(),
+            (usize::from(n.range.start()), usize::from(n.range.end())),
+        ),
+        _ => return Ok(None),
     };
 
-    if name.is_none() {
-        return Ok(None);
-    }
-
-    let type_range = ann_assign.annotation.range();
-    let type_ = Some(original_code[type_range].to_string());
+    let type_ = Some(source_text(original_code, ann_assign.annotation.range()).to_string());
 
     let value = match &ann_assign.value {
-        Some(v) => Some(original_code[v.range()].to_string()),
+        Some(v) => Some(source_text(original_code, v.range()).to_string()),
         None => None,
     };
 
-    Ok(Some(Attribute::new(
+    let mut attribute = Attribute::new(
         path.to_string(),
-        name.unwrap(),
+        name,
         type_,
         value,
         ArgType::Not,
-    )))
+    );
+    attribute.set_line(line_of(original_code, usize::from(ann_assign.range.start())));
+    attribute.set_constant(Attribute::is_constant_name(&attribute.name));
+    attribute.set_name_span(name_span);
+
+    Ok(Some(attribute))
+}
+
+/// Parses `Name: TypeAlias = value`, returning None for any other annotation.
+fn parse_ann_type_alias(
+    ann_assign: &StmtAnnAssign,
+    original_code: &String,
+    path: &String,
+) -> Result<Option<TypeAlias>, Error> {
+    let annotation = source_text(original_code, ann_assign.annotation.range());
+    if annotation != "TypeAlias" && !annotation.ends_with(".TypeAlias") {
+        return Ok(None);
+    }
+
+    let name = match *ann_assign.target.clone() {
+        Expr::Name(n) => n.id.to_string(),
+        _ => return Ok(None),
+    };
+
+    let value = match &ann_assign.value {
+        Some(v) => source_text(original_code, v.range()).to_string(),
+        None => return Ok(None),
+    };
+
+    let mut type_alias = TypeAlias::new(path.to_string(), name, value);
+    type_alias.set_line(line_of(original_code, usize::from(ann_assign.range.start())));
+
+    Ok(Some(type_alias))
+}
+
+/// Parses a PEP 695 `type Name = value` statement.
+fn parse_type_alias(
+    type_alias: &StmtTypeAlias,
+    original_code: &String,
+    path: &String,
+) -> Result<TypeAlias, Error> {
+    let name = source_text(original_code, type_alias.name.range()).to_string();
+    let value = source_text(original_code, type_alias.value.range()).to_string();
+
+    let mut alias = TypeAlias::new(path.to_string(), name, value);
+    alias.set_line(line_of(original_code, usize::from(type_alias.range.start())));
+
+    Ok(alias)
 }
 
 fn parse_function_def(
@@ -152,6 +402,18 @@ fn parse_function_def(
 ) -> Result<Method, Error> {
     let name = function_def.name.to_string();
 
+    let positional_only = function_def
+        .args
+        .posonlyargs
+        .iter()
+        .map(|a| {
+            match parse_arg_with_default(a, &original_code, ArgType::PositionalOnly, path) {
+                Ok(a) => a,
+                Err(e) => panic!("Error parsing argument: {}", e),
+            }
+        })
+        .collect::<Vec<Attribute>>();
+
     let args = function_def
         .args
         .args
@@ -194,7 +456,7 @@ fn parse_function_def(
 
     let return_type = match &function_def.returns {
         Some(r) => Some(
-            original_code[r.range()]
+            source_text(original_code, r.range())
                 .trim()
                 .trim_end_matches(":")
                 .to_string(),
@@ -202,7 +464,8 @@ fn parse_function_def(
         None => None,
     };
 
-    let mut arguments = args;
+    let mut arguments = positional_only;
+    arguments.extend(args);
     if var_arg.is_some() {
         arguments.push(var_arg.unwrap());
     }
@@ -211,7 +474,119 @@ fn parse_function_def(
         arguments.push(kw_arg.unwrap());
     }
 
-    Ok(Method::new(path.to_string(), name, return_type, arguments))
+    let decorators = function_def
+        .decorator_list
+        .iter()
+        .map(|d| source_text(original_code, d.range()).to_string())
+        .collect::<Vec<String>>();
+
+    let mut method = Method::new(path.to_string(), name, return_type, arguments);
+    method.set_decorators(decorators);
+    method.set_generator(is_generator(&function_def.body, original_code));
+    method.set_stub(is_stub(&function_def.body));
+    method.set_line(line_of(original_code, usize::from(function_def.range.start())));
+    method.set_span(definition_span(
+        original_code,
+        (usize::from(function_def.range.start()), usize::from(function_def.range.end())),
+        &function_def.decorator_list,
+    ));
+
+    Ok(method)
+}
+
+/// Collects the ranges of the functions and classes defined in `body`,
+/// looking inside compound statements but not inside those definitions.
+fn nested_scopes(body: &[Stmt], scopes: &mut Vec<Range<usize>>) {
+    for stmt in body {
+        match stmt {
+            Stmt::FunctionDef(_) | Stmt::AsyncFunctionDef(_) | Stmt::ClassDef(_) => {
+                scopes.push(usize::from(stmt.start())..usize::from(stmt.end()))
+            }
+            Stmt::If(s) => {
+                nested_scopes(&s.body, scopes);
+                nested_scopes(&s.orelse, scopes);
+            }
+            Stmt::For(s) => {
+                nested_scopes(&s.body, scopes);
+                nested_scopes(&s.orelse, scopes);
+            }
+            Stmt::AsyncFor(s) => {
+                nested_scopes(&s.body, scopes);
+                nested_scopes(&s.orelse, scopes);
+            }
+            Stmt::While(s) => {
+                nested_scopes(&s.body, scopes);
+                nested_scopes(&s.orelse, scopes);
+            }
+            Stmt::With(s) => nested_scopes(&s.body, scopes),
+            Stmt::AsyncWith(s) => nested_scopes(&s.body, scopes),
+            Stmt::Try(s) => {
+                nested_scopes(&s.body, scopes);
+                for ast::ExceptHandler::ExceptHandler(handler) in &s.handlers {
+                    nested_scopes(&handler.body, scopes);
+                }
+                nested_scopes(&s.orelse, scopes);
+                nested_scopes(&s.finalbody, scopes);
+            }
+            Stmt::TryStar(s) => {
+                nested_scopes(&s.body, scopes);
+                for ast::ExceptHandler::ExceptHandler(handler) in &s.handlers {
+                    nested_scopes(&handler.body, scopes);
+                }
+                nested_scopes(&s.orelse, scopes);
+                nested_scopes(&s.finalbody, scopes);
+            }
+            Stmt::Match(s) => {
+                for case in &s.cases {
+                    nested_scopes(&case.body, scopes);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Does a function body `yield`? Nested functions and classes have their own
+/// scope and are skipped, as are strings and comments.
+fn is_generator(body: &[Stmt], original_code: &String) -> bool {
+    let (first, last) = match (body.first(), body.last()) {
+        (Some(first), Some(last)) => (usize::from(first.start()), usize::from(last.end())),
+        _ => return false,
+    };
+    let code = source_text(original_code, first..last);
+
+    let mut skipped = Vec::new();
+    nested_scopes(body, &mut skipped);
+    let skipped = skipped
+        .into_iter()
+        .map(|r| r.start - first..r.end - first)
+        .collect::<Vec<Range<usize>>>();
+    let (strings, comments) = literal_ranges(code);
+
+    let yield_re = Regex::new(r"\byield\b").unwrap();
+    yield_re.find_iter(code).any(|m| {
+        !skipped
+            .iter()
+            .chain(strings.iter())
+            .chain(comments.iter())
+            .any(|r| r.contains(&m.start()))
+    })
+}
+
+/// Is a function body nothing but `...`, `pass` or a docstring, as in stub
+/// files, protocols and abstract methods?
+fn is_stub(body: &[Stmt]) -> bool {
+    !body.is_empty()
+        && body.iter().all(|stmt| match stmt {
+            Stmt::Pass(_) => true,
+            Stmt::Expr(e) => match e.value.as_ref() {
+                Expr::Constant(c) => {
+                    matches!(c.value, ast::Constant::Ellipsis | ast::Constant::Str(_))
+                }
+                _ => false,
+            },
+            _ => false,
+        })
 }
 
 fn parse_arg_with_default(
@@ -222,12 +597,12 @@ fn parse_arg_with_default(
 ) -> Result<Attribute, Error> {
     let def = arg.def.clone();
     let name = def.arg.to_string();
-    let type_ = def.annotation.map(|a| original_code[a.range()].to_string());
+    let type_ = def.annotation.map(|a| source_text(original_code, a.range()).to_string());
 
     let default = arg.default.clone();
 
     let default_value = match default {
-        Some(v) => Some(original_code[v.range()].to_string()),
+        Some(v) => Some(source_text(original_code, v.range()).to_string()),
         None => None,
     };
 
@@ -250,7 +625,7 @@ fn parse_arg(
     let type_ = arg
         .annotation
         .clone()
-        .map(|a| original_code[a.range()].to_string());
+        .map(|a| source_text(original_code, a.range()).to_string());
 
     Ok(Attribute::new(
         path.to_string(),
@@ -261,6 +636,29 @@ fn parse_arg(
     ))
 }
 
+/// The kind of a method made by calling `staticmethod`, `classmethod` or
+/// `property`, and the name of the wrapped function when it is a plain name.
+fn method_wrapper(value: &Expr) -> Option<(&str, Option<&str>)> {
+    let call = match value {
+        Expr::Call(call) => call,
+        _ => return None,
+    };
+
+    let kind = match &*call.func {
+        Expr::Name(n) if ["staticmethod", "classmethod", "property"].contains(&n.id.as_str()) => {
+            n.id.as_str()
+        }
+        _ => return None,
+    };
+
+    let wrapped = match call.args.first() {
+        Some(Expr::Name(n)) => Some(n.id.as_str()),
+        _ => None,
+    };
+
+    Some((kind, wrapped))
+}
+
 fn parse_class_def(
     class_def: &StmtClassDef,
     original_code: &String,
@@ -271,10 +669,12 @@ fn parse_class_def(
     let bases = class_def
         .bases
         .iter()
-        .map(|b| original_code[b.range()].to_string())
+        .map(|b| source_text(original_code, b.range()).to_string())
         .collect::<Vec<String>>();
 
     let mut methods = Vec::new();
+    let mut attributes = Vec::new();
+    let mut assigned = Vec::new();
 
     for stmt in &class_def.body {
         match stmt {
@@ -300,9 +700,841 @@ fn parse_class_def(
 
                 methods.push(method)
             }
+            Stmt::AnnAssign(a) => match (&*a.target, &a.value) {
+                (Expr::Name(target), Some(value)) if method_wrapper(value).is_some() => {
+                    assigned.push((target.id.to_string(), *value.clone(), a.range.start()))
+                }
+                _ => attributes.extend(
+                    parse_ann_assign(a, original_code, path)
+                        .with_context(|e| format!("Error parsing class attribute: {}", e))?,
+                ),
+            },
+            Stmt::Assign(a) => match a.targets.as_slice() {
+                [Expr::Name(target)] if method_wrapper(&a.value).is_some() => {
+                    assigned.push((target.id.to_string(), *a.value.clone(), a.range.start()))
+                }
+                _ => attributes.extend(
+                    parse_assign(a, original_code, path)
+                        .with_context(|e| format!("Error parsing class attribute: {}", e))?,
+                ),
+            },
             _ => {}
         }
     }
 
-    Ok(Class::new(path.to_string(), name, methods, bases))
+    // `bar = staticmethod(_bar)` gets the signature of `_bar` when the class defines it.
+    for (target, value, start) in assigned {
+        let (kind, wrapped) = method_wrapper(&value).unwrap();
+        let mut method = match methods.iter().find(|m| Some(m.name.as_str()) == wrapped) {
+            Some(m) => Method::new(
+                path.to_string(),
+                target,
+                m.return_type.clone(),
+                m.arguments.clone(),
+            ),
+            None => Method::new(path.to_string(), target, None, Vec::new()),
+        };
+        method.set_decorators(vec![kind.to_string()]);
+        method.set_line(line_of(original_code, usize::from(start)));
+        methods.push(method);
+    }
+
+    let mut class = Class::new(path.to_string(), name, group_overloads(methods), bases);
+    class.set_attributes(attributes);
+    class.set_decorators(
+        class_def
+            .decorator_list
+            .iter()
+            .map(|d| source_text(original_code, d.range()).to_string())
+            .collect(),
+    );
+    class.set_line(line_of(original_code, usize::from(class_def.range.start())));
+    class.set_span(definition_span(
+        original_code,
+        (usize::from(class_def.range.start()), usize::from(class_def.range.end())),
+        &class_def.decorator_list,
+    ));
+
+    Ok(class)
+}
+
+/// The byte range of a function or class, starting at the `@` of its first
+/// decorator when it has one.
+fn definition_span(
+    original_code: &String,
+    (start, end): (usize, usize),
+    decorators: &[Expr],
+) -> (usize, usize) {
+    let start = decorators
+        .iter()
+        .map(|d| usize::from(d.range().start()))
+        .fold(start, usize::min);
+    let start = match decorators.is_empty() {
+        true => start,
+        false => source_text(original_code, 0..start).rfind('@').unwrap_or(start),
+    };
+
+    (start, end)
+}
+
+/// The byte ranges of the references to the module-level name `name` in
+/// `contents`: the name of its top-level `def` or `class`, and its uses at
+/// module scope and in the functions that do not rebind it, f-strings
+/// included. Attributes (`obj.name`), keyword arguments, methods and the
+/// names bound in a class body are other names and are left out.
+pub fn name_references(contents: &str, name: &str) -> Result<Vec<Range<usize>>, Error> {
+    let ast = try_parse_contents(contents, "<rename>")?;
+
+    let definition = Regex::new(&format!(r"\b(?:def|class)\s+(?P<name>{})\b", regex::escape(name)))
+        .with_context(|e| format!("Could not create regex: {}", e))?;
+    let mut references = ast
+        .iter()
+        .filter(|stmt| match stmt {
+            Stmt::FunctionDef(f) => f.name.as_str() == name,
+            Stmt::AsyncFunctionDef(f) => f.name.as_str() == name,
+            Stmt::ClassDef(c) => c.name.as_str() == name,
+            _ => false,
+        })
+        .filter_map(|stmt| {
+            let start = usize::from(stmt.start());
+            let caps = definition.captures(source_text(contents, stmt.range()))?;
+            let found = caps.name("name")?;
+            Some(start + found.start()..start + found.end())
+        })
+        .collect::<Vec<Range<usize>>>();
+
+    let mut finder = NameReferences {
+        name,
+        references: Vec::new(),
+        shadowed: false,
+    };
+    let Ok(_) = finder.fold(ast);
+    // A node whose range does not cover the name, as could happen in an
+    // f-string, is left alone rather than corrupting the file.
+    references.extend(
+        finder
+            .references
+            .into_iter()
+            .filter(|r| contents.get(r.clone()) == Some(name)),
+    );
+    references.sort_by_key(|r| r.start);
+    references.dedup();
+
+    Ok(references)
+}
+
+/// Walks the AST collecting the `Name` nodes of a module-level name, see
+/// `name_references`. Folds every node back unchanged.
+struct NameReferences<'a> {
+    name: &'a str,
+    references: Vec<Range<usize>>,
+    /// Is the name bound to something else in the scope being walked?
+    shadowed: bool,
+}
+
+impl NameReferences<'_> {
+    /// Walks the body of a function, unless the function rebinds the name.
+    fn function_body(
+        &mut self,
+        args: &Arguments,
+        body: Vec<Stmt>,
+    ) -> Result<Vec<Stmt>, Infallible> {
+        if rebinds(self.name, args, &body) {
+            return Ok(body);
+        }
+
+        // Functions skip the scope of an enclosing class body.
+        let shadowed = std::mem::replace(&mut self.shadowed, false);
+        let body = self.fold(body);
+        self.shadowed = shadowed;
+        body
+    }
+}
+
+impl Fold<TextRange> for NameReferences<'_> {
+    type TargetU = TextRange;
+    type Error = Infallible;
+    type UserContext = ();
+
+    fn will_map_user(&mut self, _user: &TextRange) -> Self::UserContext {}
+
+    fn map_user(&mut self, user: TextRange, _context: ()) -> Result<TextRange, Infallible> {
+        Ok(user)
+    }
+
+    fn fold_expr_name(&mut self, node: ExprName) -> Result<ExprName, Infallible> {
+        if !self.shadowed && node.id.as_str() == self.name {
+            self.references.push(usize::from(node.range.start())..usize::from(node.range.end()));
+        }
+        Ok(node)
+    }
+
+    // Decorators, defaults and annotations are evaluated in the enclosing scope.
+    fn fold_stmt_function_def(
+        &mut self,
+        node: StmtFunctionDef,
+    ) -> Result<StmtFunctionDef, Infallible> {
+        let body = self.function_body(&node.args, node.body)?;
+        Ok(StmtFunctionDef {
+            decorator_list: self.fold(node.decorator_list)?,
+            args: self.fold(node.args)?,
+            returns: self.fold(node.returns)?,
+            body,
+            ..node
+        })
+    }
+
+    fn fold_stmt_async_function_def(
+        &mut self,
+        node: StmtAsyncFunctionDef,
+    ) -> Result<StmtAsyncFunctionDef, Infallible> {
+        let body = self.function_body(&node.args, node.body)?;
+        Ok(StmtAsyncFunctionDef {
+            decorator_list: self.fold(node.decorator_list)?,
+            args: self.fold(node.args)?,
+            returns: self.fold(node.returns)?,
+            body,
+            ..node
+        })
+    }
+
+    // A name bound in a class body is a class attribute, but the methods
+    // still see the module-level name.
+    fn fold_stmt_class_def(&mut self, node: StmtClassDef) -> Result<StmtClassDef, Infallible> {
+        let decorator_list = self.fold(node.decorator_list)?;
+        let bases = self.fold(node.bases)?;
+        let keywords = self.fold(node.keywords)?;
+
+        let shadowed = self.shadowed || scope_binds(self.name, &node.body);
+        let shadowed = std::mem::replace(&mut self.shadowed, shadowed);
+        let body = self.fold(node.body)?;
+        self.shadowed = shadowed;
+
+        Ok(StmtClassDef {
+            decorator_list,
+            bases,
+            keywords,
+            body,
+            ..node
+        })
+    }
+
+    fn fold_expr_lambda(&mut self, node: ExprLambda) -> Result<ExprLambda, Infallible> {
+        if rebinds(self.name, &node.args, &[]) {
+            return Ok(node);
+        }
+        fold::fold_expr_lambda(self, node)
+    }
+}
+
+/// Does a function with these parameters and body bind `name` locally?
+fn rebinds(name: &str, args: &Arguments, body: &[Stmt]) -> bool {
+    let mut parameters = args
+        .posonlyargs
+        .iter()
+        .chain(&args.args)
+        .chain(&args.kwonlyargs)
+        .map(|a| &a.def)
+        .chain(args.vararg.as_deref())
+        .chain(args.kwarg.as_deref());
+
+    parameters.any(|a| a.arg.as_str() == name) || scope_binds(name, body)
+}
+
+/// Do the statements of a scope bind `name`, by assignment, `def`, `class`,
+/// import, `for`, `with`, `except ... as` or `del`, without declaring it
+/// `global` or `nonlocal`?
+fn scope_binds(name: &str, body: &[Stmt]) -> bool {
+    let mut bindings = Bindings::default();
+    let Ok(_) = bindings.fold(body.to_vec());
+    bindings.bound.contains(name) && !bindings.declared.contains(name)
+}
+
+/// The names bound in a scope, without looking into nested scopes.
+#[derive(Default)]
+struct Bindings {
+    bound: HashSet<String>,
+    /// Declared `global` or `nonlocal`.
+    declared: HashSet<String>,
+}
+
+impl Fold<TextRange> for Bindings {
+    type TargetU = TextRange;
+    type Error = Infallible;
+    type UserContext = ();
+
+    fn will_map_user(&mut self, _user: &TextRange) -> Self::UserContext {}
+
+    fn map_user(&mut self, user: TextRange, _context: ()) -> Result<TextRange, Infallible> {
+        Ok(user)
+    }
+
+    fn fold_expr_name(&mut self, node: ExprName) -> Result<ExprName, Infallible> {
+        if node.ctx != ExprContext::Load {
+            self.bound.insert(node.id.to_string());
+        }
+        Ok(node)
+    }
+
+    fn fold_stmt_function_def(
+        &mut self,
+        node: StmtFunctionDef,
+    ) -> Result<StmtFunctionDef, Infallible> {
+        self.bound.insert(node.name.to_string());
+        Ok(node)
+    }
+
+    fn fold_stmt_async_function_def(
+        &mut self,
+        node: StmtAsyncFunctionDef,
+    ) -> Result<StmtAsyncFunctionDef, Infallible> {
+        self.bound.insert(node.name.to_string());
+        Ok(node)
+    }
+
+    fn fold_stmt_class_def(&mut self, node: StmtClassDef) -> Result<StmtClassDef, Infallible> {
+        self.bound.insert(node.name.to_string());
+        Ok(node)
+    }
+
+    fn fold_expr_lambda(&mut self, node: ExprLambda) -> Result<ExprLambda, Infallible> {
+        Ok(node)
+    }
+
+    // The targets of a comprehension are local to it.
+    fn fold_comprehension(
+        &mut self,
+        node: ast::Comprehension,
+    ) -> Result<ast::Comprehension, Infallible> {
+        Ok(node)
+    }
+
+    fn fold_alias(&mut self, node: ast::Alias) -> Result<ast::Alias, Infallible> {
+        // `import a.b` binds `a`.
+        let bound = match &node.asname {
+            Some(asname) => asname.as_str(),
+            None => node.name.as_str().split('.').next().unwrap(),
+        };
+        self.bound.insert(bound.to_string());
+        Ok(node)
+    }
+
+    fn fold_excepthandler_except_handler(
+        &mut self,
+        node: ExceptHandlerExceptHandler,
+    ) -> Result<ExceptHandlerExceptHandler, Infallible> {
+        if let Some(name) = &node.name {
+            self.bound.insert(name.to_string());
+        }
+        fold::fold_excepthandler_except_handler(self, node)
+    }
+
+    fn fold_stmt_global(&mut self, node: StmtGlobal) -> Result<StmtGlobal, Infallible> {
+        self.declared.extend(node.names.iter().map(|n| n.to_string()));
+        Ok(node)
+    }
+
+    fn fold_stmt_nonlocal(&mut self, node: StmtNonlocal) -> Result<StmtNonlocal, Infallible> {
+        self.declared.extend(node.names.iter().map(|n| n.to_string()));
+        Ok(node)
+    }
+}
+
+/// Finds the byte ranges of the string literals and `#` comments of Python
+/// source with a scan of the raw text, so it also works on files that do not
+/// parse. Returns `(strings, comments)`.
+pub fn literal_ranges(code: &str) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let bytes = code.as_bytes();
+    let mut strings = Vec::new();
+    let mut comments = Vec::new();
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'#' => {
+                let end = code[i..].find('\n').map_or(bytes.len(), |n| i + n);
+                comments.push(i..end);
+                i = end;
+            }
+            quote @ (b'\'' | b'"') => {
+                let start = i;
+                let triple = bytes[i..].starts_with(&[quote; 3]);
+                i += if triple { 3 } else { 1 };
+
+                while i < bytes.len() {
+                    if bytes[i] == b'\\' {
+                        i += 2;
+                    } else if triple && bytes[i..].starts_with(&[quote; 3]) {
+                        i += 3;
+                        break;
+                    } else if !triple && bytes[i] == quote {
+                        i += 1;
+                        break;
+                    } else if !triple && bytes[i] == b'\n' {
+                        // Unterminated string, stop at the end of the line.
+                        break;
+                    } else {
+                        i += 1;
+                    }
+                }
+
+                i = i.min(bytes.len());
+                strings.push(start..i);
+            }
+            _ => i += 1,
+        }
+    }
+
+    (strings, comments)
+}
+
+/// Is this comment a shebang (`#!/usr/bin/env python`) on the first line, or
+/// a PEP 263 encoding declaration (`# -*- coding: utf-8 -*-`) on one of the
+/// first two lines? These are directives for the interpreter, not comments.
+pub fn is_magic_comment(line: usize, text: &str) -> bool {
+    let coding = Regex::new(r"^#.*?coding[:=][ \t]*[-_.a-zA-Z0-9]+").unwrap();
+
+    (line == 1 && text.starts_with("#!")) || (line <= 2 && coding.is_match(text))
+}
+
+/// Collects the `#` comments of Python source. Works on the raw text, so
+/// comments are found even in files that do not parse.
+/// Shebang and encoding lines are left out, see `is_magic_comment`.
+pub fn parse_comments(original_code: &String, path: &String) -> Vec<Comment> {
+    let (_, comments) = literal_ranges(original_code);
+
+    comments
+        .into_iter()
+        .map(|range| {
+            Comment::new(
+                path.to_string(),
+                source_text(original_code, range.clone()).trim_end().to_string(),
+                line_of(original_code, range.start),
+            )
+        })
+        .filter(|comment| !is_magic_comment(comment.line, &comment.text))
+        .collect()
+}
+
+/// Returns the 1-based line number of a byte offset in the source code.
+pub fn line_of(original_code: &String, offset: usize) -> usize {
+    match original_code.get(..offset) {
+        Some(before) => before.matches('\n').count() + 1,
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::python_def::PythonDef;
+    use color_print::cformat;
+
+    fn parse_source(code: &str) -> (Vec<Class>, Vec<Method>, Vec<Attribute>, Vec<TypeAlias>) {
+        let ast = ast::Suite::parse(code, "<test>").unwrap();
+        parse_root_ast(ast, &code.to_string(), &String::from("test.py")).unwrap()
+    }
+
+    #[test]
+    fn test_defaults_with_commas() {
+        let (_, functions, _, _) = parse_source("def f(x=(1, 2), y=dict(a=1)):\n    pass\n");
+
+        let function = &functions[0];
+        assert_eq!(function.arguments.len(), 2);
+        assert_eq!(function.arguments[0].default, Some(String::from("(1, 2)")));
+        assert_eq!(function.arguments[1].default, Some(String::from("dict(a=1)")));
+        assert_eq!(
+            function.definition_code,
+            "def f(x = (1, 2), y = dict(a=1)):\n"
+        );
+    }
+
+    #[test]
+    fn test_constants() {
+        let (_, _, vars, _) = parse_source("MAX_SIZE = 10\ncounter = 0\nHTTP_2: int = 2\n");
+
+        let constants = vars
+            .iter()
+            .map(|v| (v.name.as_str(), v.is_constant))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            constants,
+            vec![("MAX_SIZE", true), ("counter", false), ("HTTP_2", true)]
+        );
+    }
+
+    #[test]
+    fn test_starred_targets() {
+        let (_, _, vars, _) = parse_source("a, *b = func()\n[c, (d, *e)] = pairs\n");
+
+        let names = vars
+            .iter()
+            .map(|v| (v.name.as_str(), v.is_starred, v.name_span.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("a", false, false),
+                ("b", true, false),
+                ("c", false, false),
+                ("d", false, false),
+                ("e", true, false),
+            ]
+        );
+        assert_eq!(vars[1].default, Some(String::from("func()")));
+        assert_eq!(vars[1].definition_code, "*b = func()");
+    }
+
+    #[test]
+    fn test_dataclass_fields() {
+        let (classes, _, _, _) = parse_source(
+            "@dataclass(frozen=True)\nclass Config:\n    name: str\n    \
+             tags: list = field(default_factory=list)\n\n    def size(self):\n        pass\n",
+        );
+
+        let class = &classes[0];
+        assert!(class.is_dataclass);
+        assert_eq!(class.attributes.len(), 2);
+        assert_eq!(
+            class.attributes[1].default,
+            Some(String::from("field(default_factory=list)"))
+        );
+        assert!(class
+            .definition_code
+            .contains("    name: str\n    tags: list = field(default_factory=list)\n    def size"));
+
+        let found = class.without_decorators().find("", None, None);
+        assert!(found.contains(&cformat!(" <cyan>[dataclass]</cyan>")));
+        assert!(found.contains("    tags: list = field(default_factory=list)\n"));
+    }
+
+    #[test]
+    fn test_assigned_methods() {
+        let (classes, _, _, _) = parse_source(
+            "class A:\n    def _bar(x, y):\n        pass\n\n    \
+             bar = staticmethod(_bar)\n    size = property(lambda self: 1)\n    limit = 3\n",
+        );
+
+        let class = &classes[0];
+        let bar = class.methods.iter().find(|m| m.name == "bar").unwrap();
+        assert_eq!(bar.decorators, vec!["staticmethod"]);
+        assert_eq!(bar.definition_code, "def bar(x, y):\n");
+        assert_eq!(bar.line, 5);
+
+        let size = class.methods.iter().find(|m| m.name == "size").unwrap();
+        assert_eq!(size.decorators, vec!["property"]);
+        assert!(size.arguments.is_empty());
+
+        assert_eq!(class.attributes.len(), 1);
+        assert_eq!(class.attributes[0].name, "limit");
+        assert!(class.find("bar", None, None).contains(&cformat!("<red>@</red>staticmethod\n")));
+    }
+
+    #[test]
+    fn test_argument_colors() {
+        let (_, functions, _, _) =
+            parse_source("def f(self, pattern: str = \"*.py\", *args, **kwargs):\n    pass\n");
+
+        let found = functions[0].find("", None, None);
+        assert!(found.contains(&cformat!("<red>self</red>, ")));
+        assert!(found.contains(&cformat!("pattern: <blue>str</blue> = <yellow>\"*.py\"</yellow>")));
+        assert!(found.contains(&cformat!("<red>*</red>args, <red>**</red>kwargs")));
+    }
+
+    #[test]
+    fn test_overloads() {
+        let (classes, functions, _, _) = parse_source(
+            "@overload\ndef f(x: int) -> int: ...\n@overload\ndef f(x: str) -> str: ...\n\
+             def f(x):\n    return x\n\
+             class A:\n    @typing.overload\n    def g(self) -> None: ...\n    def g(self, *a): ...\n",
+        );
+
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].overloads.len(), 2);
+        assert_eq!(classes[0].methods.len(), 1);
+        assert_eq!(classes[0].methods[0].overloads.len(), 1);
+
+        let found = functions[0].find("", None, None);
+        assert_eq!(found.matches("[overload]").count(), 2);
+        assert!(found.contains(&cformat!("<magenta>f</magenta>(x: <blue>str</blue>) -> str: <cyan>[overload]</cyan>")));
+    }
+
+    #[test]
+    fn test_generators() {
+        let (_, functions, _, _) = parse_source(
+            "def gen():\n    if True:\n        yield from range(3)\n\n\
+             async def agen():\n    x = yield 1\n\n\
+             def plain():\n    def inner():\n        yield 1\n    return 'yield'  # yield\n",
+        );
+
+        let generators = functions
+            .iter()
+            .map(|f| (f.name.as_str(), f.is_generator))
+            .collect::<Vec<_>>();
+        assert_eq!(generators, vec![("gen", true), ("agen", true), ("plain", false)]);
+
+        assert!(functions[0].find("", None, None).contains("[generator]"));
+        assert!(functions[1].find("", None, None).contains("[async generator]"));
+        assert!(!functions[2].find("", None, None).contains("generator]"));
+    }
+
+    #[test]
+    fn test_equal_definitions() {
+        let (_, functions, _, _) = parse_source(
+            "def f(x: int = 1) -> int:\n    return x\n\n\
+             def f(x: int = 1) -> int:\n    return x + 1\n\n\
+             def f(x: int = 2) -> int:\n    return x\n",
+        );
+
+        let unique = functions.iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(functions.len(), 3);
+        assert_eq!(unique.len(), 2);
+        assert!(functions[0] == functions[1]);
+        assert!(functions[0] != functions[2]);
+    }
+
+    #[test]
+    fn test_non_ascii_defaults() {
+        let (_, functions, _, _) = parse_source(
+            "def f(cb: Callable[[int], str], name=\"héllo 🐍\", tag=f\"ü{1}\") -> dict[str, 'Ω']:\n    pass\n",
+        );
+
+        let arguments = &functions[0].arguments;
+        assert_eq!(arguments[0].type_, Some(String::from("Callable[[int], str]")));
+        assert_eq!(arguments[1].default, Some(String::from("\"héllo 🐍\"")));
+        assert_eq!(arguments[2].default, Some(String::from("f\"ü{1}\"")));
+        assert_eq!(functions[0].return_type, Some(String::from("dict[str, 'Ω']")));
+
+        // Offsets inside a character are widened instead of panicking.
+        assert_eq!(source_text("aé", 2..3), "é");
+        assert_eq!(source_text("aé", 1..10), "é");
+    }
+
+    #[test]
+    fn test_argument_markers() {
+        let (classes, _, _, _) =
+            parse_source("class A:\n    def __init__(self, a, /, b, *, c):\n        pass\n");
+
+        let class = &classes[0];
+        assert!(class
+            .definition_code
+            .contains("    def __init__(self, a, /, b, *, c):\n"));
+
+        let found = class.find("", None, None);
+        assert!(found.contains(&cformat!("a, <red>/</red>, b, <red>*</red>, c)")));
+    }
+
+    #[test]
+    fn test_name_references() {
+        let source = "def old(x):\n    return x\n\n\n\
+                      def shadowing(x, old=1):\n    return old\n\n\n\
+                      def local():\n    old = 2\n    return old\n\n\n\
+                      def uses():\n    return old(1)\n\n\n\
+                      class A:\n    def old(self):\n        return old(self.old())\n\n\n\
+                      message = f\"{old(1)}!\"\n";
+        let references = name_references(source, "old").unwrap();
+        let lines = references
+            .iter()
+            .map(|r| line_of(&source.to_string(), r.start))
+            .collect::<Vec<usize>>();
+
+        // The def, the use in `uses`, the call in the method and the f-string.
+        assert_eq!(lines, vec![1, 15, 20, 23]);
+        assert!(references.iter().all(|r| &source[r.clone()] == "old"));
+    }
+
+    #[test]
+    fn test_stub_bodies() {
+        let (classes, functions, _, _) = parse_source(
+            "def ellipsis(x: int) -> int: ...\n\n\n\
+             def documented():\n    \"\"\"Only a docstring.\"\"\"\n\n\n\
+             def implemented():\n    return 1\n\n\n\
+             class Shape(ABC):\n    @abstractmethod\n    def area(self):\n        return 0\n\n\
+             \x20   def name(self):\n        pass\n",
+        );
+
+        let stubs = functions.iter().map(|f| f.is_stub).collect::<Vec<_>>();
+        assert_eq!(stubs, vec![true, true, false]);
+        assert!(functions[0].find("", None, None).contains(&cformat!("<cyan>[stub]</cyan>")));
+        assert!(!functions[0].find("", None, None).contains("[abstract]"));
+        assert!(!functions[2].find("", None, None).contains("[stub]"));
+
+        let (area, name) = (&classes[0].methods[0], &classes[0].methods[1]);
+        assert!(!area.is_stub && area.is_abstract());
+        assert!(name.is_stub && !name.is_abstract());
+        assert!(area.find("", None, None).contains(&cformat!("<cyan>[abstract]</cyan>")));
+        assert!(name.find("", None, None).contains(&cformat!("<cyan>[stub]</cyan>")));
+    }
+
+    #[test]
+    fn test_wrapped_signature() {
+        let (classes, functions, _, _) = parse_source(
+            "def render(template_name: str, context: dict, request: Request, \
+             status: int = 200, headers: dict = None, using: str = None) -> Response:\n    pass\n\n\n\
+             def fits(alpha: int, beta: int, gamma: int, delta: int, epsilon: int):\n    pass\n\n\n\
+             class View:\n    def render(self, template_name: str, context: dict, request: Request, \
+             status: int = 200, using: str = None):\n        pass\n",
+        );
+
+        // The code stays on one line, only the display is wrapped.
+        assert_eq!(
+            functions[0].definition_code,
+            "def render(template_name: str, context: dict, request: Request, \
+             status: int = 200, headers: dict = None, using: str = None) -> Response:\n"
+        );
+        assert!(classes[0]
+            .definition_code
+            .contains("    def render(self, template_name: str, context: dict, request: Request, "));
+
+        let found = functions[0].find("", None, Some(&String::from("│  ")));
+        assert!(found.contains("\n│      "));
+        assert!(found.contains("\n│  ) -> Response:"));
+
+        // 81 characters on its own, too long once indented.
+        let top_level = functions[1].find("", None, Some(&String::new()));
+        let nested = functions[1].find("", None, Some(&String::from("│  │  │  ")));
+        assert_eq!(top_level.lines().count(), 1);
+        assert!(nested.lines().count() > 1);
+
+        let class_found = classes[0].find("", None, Some(&String::new()));
+        assert!(class_found.contains("\n        using"));
+        assert!(class_found.contains(",\n    ):"));
+    }
+
+    #[test]
+    fn test_without_private_args() {
+        let (classes, functions, _, _) = parse_source(
+            "def f(a, _hidden=None):\n    pass\n\n\n\
+             class A:\n    def g(self, _cache=None, *, b):\n        pass\n",
+        );
+
+        let function = functions[0].without_private_args();
+        assert_eq!(function.definition_code, "def f(a):\n");
+        assert_eq!(functions[0].arguments.len(), 2);
+
+        let class = classes[0].without_private_args();
+        assert!(class.definition_code.contains("    def g(self, *, b):\n"));
+    }
+
+    #[test]
+    fn test_find_decorator() {
+        let (classes, functions, _, _) = parse_source(
+            "@app.route(\"/health\")\ndef health():\n    pass\n\n\n\
+             def other():\n    pass\n\n\n\
+             class A:\n    @property\n    def size(self):\n        pass\n",
+        );
+        let prefix = String::new();
+
+        let found = functions[0].find_decorator("app.route", &prefix).unwrap();
+        assert!(found.starts_with(&cformat!(
+            "<red>@</red><bg:green>app.route</bg:green>(\"/health\")\n"
+        )));
+        assert!(found.contains("health"));
+        assert!(functions[1].find_decorator("app.route", &prefix).is_none());
+        assert!(functions[0].find_decorator("/health", &prefix).is_none());
+
+        let found = classes[0].find_decorator("property", &prefix).unwrap();
+        assert!(found.contains(&cformat!("    <red>@</red><bg:green>property</bg:green>\n")));
+        assert!(classes[0].find_decorator("app.route", &prefix).is_none());
+    }
+
+    #[test]
+    fn test_find_signature() {
+        let (classes, functions, _, _) = parse_source(
+            "def index(request: Request, page=1):\n    pass\n\n\n\
+             def other(request):\n    pass\n\n\n\
+             class View:\n    def get(self, request: Request) -> Response:\n        pass\n",
+        );
+        let pattern = Regex::new("request: Request").unwrap();
+        let prefix = String::new();
+
+        assert_eq!(
+            functions[0].find_signature(&pattern, &prefix).unwrap(),
+            cformat!("def index(<bg:green>request: Request</bg:green>, page=1):\n")
+        );
+        assert!(functions[1].find_signature(&pattern, &prefix).is_none());
+
+        let found = classes[0].find_signature(&pattern, &prefix).unwrap();
+        assert!(found.contains(&cformat!(
+            "    def get(self, <bg:green>request: Request</bg:green>) -> Response:\n"
+        )));
+
+        let pattern = Regex::new(r"-> \w+:").unwrap();
+        assert!(classes[0].find_signature(&pattern, &prefix).is_some());
+        assert!(functions[0].find_signature(&pattern, &prefix).is_none());
+    }
+
+    #[test]
+    fn test_conditional_defs() {
+        let code = String::from(
+            "import sys\n\n\
+             if sys.platform == \"win32\":\n    def clear():\n        pass\n\
+             elif sys.platform == \"darwin\":\n    class Mac:\n        pass\n\
+             else:\n    def clear():\n        pass\n\n\
+             def always():\n    pass\n\n\
+             helper = 1\ndel helper\n",
+        );
+        let ast = ast::Suite::parse(&code, "<test>").unwrap();
+        let path = String::from("test.py");
+
+        let (classes, functions) = parse_conditional_defs(&ast, &code, &path).unwrap();
+        assert_eq!(classes.len(), 1);
+        assert_eq!(classes[0].name, "Mac");
+        assert_eq!(functions.len(), 2);
+        assert!(functions.iter().all(|f| f.name == "clear" && f.is_conditional));
+        assert!(functions[0]
+            .find("", None, None)
+            .contains(&cformat!("<cyan>[conditional]</cyan>")));
+
+        let (_, functions, _, _) = parse_root_ast(ast.clone(), &code, &path).unwrap();
+        assert_eq!(functions.len(), 1);
+        assert!(!functions[0].is_conditional);
+
+        assert_eq!(parse_deletions(&ast, &code), vec![(String::from("helper"), 17)]);
+    }
+
+    #[test]
+    fn test_literal_ranges() {
+        let code = "x = 'a # b'  # real\ny = \"\"\"q\n'\"\"\" + \"\\\"\"\n";
+        let (strings, comments) = literal_ranges(code);
+
+        let strings = strings.into_iter().map(|r| &code[r]).collect::<Vec<_>>();
+        let comments = comments.into_iter().map(|r| &code[r]).collect::<Vec<_>>();
+        assert_eq!(strings, vec!["'a # b'", "\"\"\"q\n'\"\"\"", "\"\\\"\""]);
+        assert_eq!(comments, vec!["# real"]);
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let code = String::from("x = '# TODO: not a comment'\n\ndef f():\n    pass  # TODO: fix\n");
+        let comments = parse_comments(&code, &String::from("test.py"));
+
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].text, "# TODO: fix");
+        assert_eq!(comments[0].line, 4);
+        assert!(comments[0].find("TODO", None, None).contains(&cformat!("<yellow>4</yellow>: ")));
+        assert_eq!(comments[0].find("FIXME", None, None), "");
+    }
+
+    #[test]
+    fn test_magic_comments() {
+        let code = String::from(
+            "#!/usr/bin/env python\n# -*- coding: utf-8 -*-\n# TODO: coding: style\nx = 1\n",
+        );
+        let comments = parse_comments(&code, &String::from("test.py"));
+
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].text, "# TODO: coding: style");
+        assert!(!is_magic_comment(3, "# vim: set fileencoding=utf-8 :"));
+        assert!(is_magic_comment(1, "# vim: set fileencoding=utf-8 :"));
+    }
+
+    #[test]
+    fn test_find_highlights_name_only() {
+        let (_, functions, _, _) = parse_source("def f1(x=f1):\n    pass\n");
+
+        let found = functions[0].find("f1", None, None);
+        assert_eq!(found.matches("f1").count(), 2);
+        assert!(found.contains(&cformat!("x = <yellow>f1</yellow>")));
+    }
 }
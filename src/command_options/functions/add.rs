@@ -1,29 +1,190 @@
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use color_print::cprintln;
 use failure::ResultExt;
+use crate::exit_code::OrExit;
 
-use crate::module_manager::{ModuleManager, ModuleType};
+use crate::config::Config;
+use crate::module_manager::{ModuleManager, ModuleType, ProjectSettings};
 use crate::command_options::options::AddOptions;
 
 
-pub fn add(options: &AddOptions) {
+/// Prints what `add` would create, without creating anything.
+fn dry_run(options: &AddOptions, module_type: ModuleType, settings: &ProjectSettings) {
+    let module = options.module.as_ref().unwrap();
+
+    let mut module_manager = ModuleManager::new_with_settings(module, module_type, false, settings)
+        .with_context(|e| {
+            format!(
+                "Failed to create module manager for module {}: {}",
+                module, e
+            )
+        })
+        .or_exit();
+
+    if let Some(template_dir) = &options.template_dir {
+        check_template(&module_manager, template_dir);
+    }
+    if options.force && module_manager.path().is_file() {
+        cprintln!("<y>Would reset {}</y>", module_manager.path().display());
+    }
+
+    for sub_module in options.contains.iter().flatten() {
+        module_manager
+            .add_sub_module(sub_module, ModuleType::File, false)
+            .with_context(|e| format!("Failed to add sub module {}: {}", sub_module, e))
+            .or_exit();
+    }
+
+    let paths = module_manager.planned_paths();
+    if paths.is_empty() {
+        cprintln!("<y>Nothing to create, {} already exists</y>", module);
+        return;
+    }
+
+    cprintln!("<y>Would create:</y>");
+    for path in paths {
+        println!("│――{}", path.display());
+    }
+
+    if options.export && options.contains.is_some() {
+        cprintln!(
+            "<y>Would re-export {} from {}</y>",
+            options.contains.as_ref().unwrap().join(", "),
+            module_manager.path().display()
+        );
+    }
+}
+
+/// Exits when `template_dir` cannot be applied to the module, before
+/// anything is reset or created.
+fn check_template(module_manager: &ModuleManager, template_dir: &Path) {
+    module_manager
+        .check_template(template_dir)
+        .with_context(|e| {
+            format!(
+                "Failed to apply template {} to module {}: {}",
+                template_dir.display(),
+                module_manager.module(),
+                e
+            )
+        })
+        .or_exit();
+}
+
+/// Creates the module tree described in `spec`, reporting which modules
+/// were created and which already existed.
+fn add_from_spec(spec: &Path, settings: &ProjectSettings) {
+    let contents = read_to_string(spec)
+        .with_context(|e| format!("Failed to read {}: {}", spec.display(), e))
+        .or_exit();
+
+    let modules = ModuleManager::parse_spec(&contents)
+        .with_context(|e| format!("Failed to parse {}: {}", spec.display(), e))
+        .or_exit();
+
+    let (created, existing) = ModuleManager::create_tree(&modules, settings)
+        .with_context(|e| format!("Failed to create the modules of {}: {}", spec.display(), e))
+        .or_exit();
+
+    for path in &created {
+        cprintln!("<g>Created {}</g>", path.display());
+    }
+    for path in &existing {
+        cprintln!("<y>Already exists {}</y>", path.display());
+    }
+    cprintln!("<b>Created {} of {} modules</b>", created.len(), modules.len());
+}
+
+/// Creates the test file of the new module, see `--with-test`.
+fn add_test_file(options: &AddOptions, module_manager: &ModuleManager) {
+    let tests_dir = match &options.tests_dir {
+        Some(tests_dir) => tests_dir.clone(),
+        None => {
+            let config = Config::load()
+                .with_context(|e| format!("Failed to load the project settings: {}", e))
+                .or_exit();
+            PathBuf::from(config.tests_dir.unwrap_or(String::from("tests")))
+        }
+    };
+
+    let created = module_manager
+        .create_test_file(&tests_dir)
+        .with_context(|e| format!("Failed to create the test file: {}", e))
+        .or_exit();
+
+    match created {
+        Some(path) => cprintln!("<g>Created {}</g>", path.display()),
+        None => cprintln!("<y>Kept existing test file in {}</y>", tests_dir.display()),
+    }
+}
+
+pub fn add(options: &AddOptions, settings: &ProjectSettings) {
     if options.is_file && options.contains.is_some() {
         panic!("Files cannot contain other modules: {:?}", options.contains);
     }
 
-    let module = &options.module;
+    if let Some(spec) = &options.from_spec {
+        return add_from_spec(spec, settings);
+    }
+
+    let module = options.module.as_ref().unwrap();
     let module_type = if options.is_file {
         ModuleType::File
     } else {
         ModuleType::Directory
     };
 
-    let mut module_manager = ModuleManager::new(module, module_type, true)
+    if options.dry_run {
+        return dry_run(options, module_type, settings);
+    }
+
+    // The template may bring its own __init__.py, so it goes before the build.
+    let build = options.template_dir.is_none();
+    let mut module_manager = ModuleManager::new_with_settings(module, module_type, build, settings)
         .with_context(|e| {
             format!(
                 "Failed to create module manager for module {}: {}",
                 module, e
             )
         })
-        .unwrap();
+        .or_exit();
+
+    if let Some(template_dir) = &options.template_dir {
+        check_template(&module_manager, template_dir);
+    }
+
+    if options.force {
+        let reset = module_manager
+            .reset()
+            .with_context(|e| format!("Failed to reset module {}: {}", module, e))
+            .or_exit();
+        if reset {
+            cprintln!("<y>Reset {}</y>", module_manager.path().display());
+        }
+    }
+
+    if let Some(template_dir) = &options.template_dir {
+        let (created, skipped) = module_manager
+            .apply_template(template_dir, options.force)
+            .with_context(|e| {
+                format!(
+                    "Failed to apply template {} to module {}: {}",
+                    template_dir.display(),
+                    module,
+                    e
+                )
+            })
+            .or_exit();
+
+        for path in created {
+            cprintln!("<g>Created {}</g>", path.display());
+        }
+        for path in skipped {
+            cprintln!("<y>Kept existing {}</y>", path.display());
+        }
+    }
 
     module_manager
         .build()
@@ -33,14 +194,25 @@ pub fn add(options: &AddOptions) {
                 module, e
             )
         })
-        .unwrap();
+        .or_exit();
 
     if options.contains.is_some() {
         for sub_module in options.contains.as_ref().unwrap() {
             module_manager
                 .add_sub_module(&sub_module, ModuleType::File, true)
                 .with_context(|e| format!("Failed to add sub module {}: {}", sub_module, e))
-                .unwrap();
+                .or_exit();
+
+            if options.export {
+                module_manager
+                    .export_sub_module(&sub_module)
+                    .with_context(|e| format!("Failed to export sub module {}: {}", sub_module, e))
+                    .or_exit();
+            }
         }
     }
+
+    if options.with_test {
+        add_test_file(options, &module_manager);
+    }
 }
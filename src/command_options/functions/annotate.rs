@@ -0,0 +1,40 @@
+use color_print::cprintln;
+use failure::ResultExt;
+use crate::exit_code::OrExit;
+use crate::module_manager::{ModuleManager, ProjectSettings};
+use crate::command_options::options::AnnotateOptions;
+
+
+pub fn annotate(options: &AnnotateOptions, settings: &ProjectSettings) {
+    let module = &options.module;
+
+    let module_type = ModuleManager::resolve(module, settings)
+        .with_context(|e| format!("Failed to resolve module {}: {}", module, e))
+        .or_exit();
+
+    let mut module_manager = ModuleManager::new_with_settings(module, module_type, false, settings)
+        .with_context(|e| {
+            format!(
+                "Failed to create module manager for module {}: {}",
+                module, e
+            )
+        })
+        .or_exit();
+
+    module_manager
+        .parse()
+        .with_context(|e| format!("Failed to parse module {}: {}", module, e))
+        .or_exit();
+
+    module_manager
+        .annotate(&options.name, &options.type_)
+        .with_context(|e| format!("Failed to annotate {}.{}: {}", module, options.name, e))
+        .or_exit();
+
+    cprintln!(
+        "<g>Annotated {}.{} as {}</g>",
+        module,
+        options.name,
+        options.type_
+    );
+}
@@ -1,5 +1,6 @@
 
 use color_print::cprintln;
+use crate::exit_code::ExitCode;
 use crate::poetry::check::{check_poetry, check_python};
 
 
@@ -17,4 +18,8 @@ pub fn check() {
     } else {
         cprintln!("Python: <g> ({})</g>", python_version);
     }
-}
\ No newline at end of file
+
+    if !poetry || !python {
+        ExitCode::ToolMissing.exit();
+    }
+}
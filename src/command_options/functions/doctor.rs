@@ -0,0 +1,35 @@
+use color_print::cprintln;
+use failure::ResultExt;
+use crate::exit_code::OrExit;
+use crate::module_manager::{ModuleManager, ProjectSettings};
+use crate::command_options::options::DoctorOptions;
+
+
+/// Runs the selected checks, or all of them when none is selected.
+pub fn doctor(options: &DoctorOptions, settings: &ProjectSettings) {
+    let checks: [(bool, fn(&DoctorOptions, &ProjectSettings)); 1] =
+        [(options.orphans, report_orphans)];
+    let run_all = checks.iter().all(|(selected, _)| !selected);
+
+    for (selected, check) in checks {
+        if selected || run_all {
+            check(options, settings);
+        }
+    }
+}
+
+fn report_orphans(options: &DoctorOptions, settings: &ProjectSettings) {
+    let orphans = ModuleManager::orphans(&options.ignore, settings)
+        .with_context(|e| format!("Failed to look for orphan modules: {}", e))
+        .or_exit();
+
+    if orphans.is_empty() {
+        cprintln!("<g>Every module is imported by another module</g>");
+        return;
+    }
+
+    cprintln!("<y>Modules never imported by another module ({}):</y>", orphans.len());
+    for (module, path) in orphans {
+        cprintln!("│――<blue>{}</blue> ({})", module, path.display());
+    }
+}
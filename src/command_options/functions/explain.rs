@@ -0,0 +1,26 @@
+use std::env::current_dir;
+
+use color_print::{cprint, cprintln};
+use failure::ResultExt;
+use crate::exit_code::OrExit;
+use crate::module_manager::{ModuleManager, ProjectSettings};
+use crate::command_options::options::ExplainOptions;
+
+
+/// Prints the project root, where it came from, and how the module name
+/// maps to a path. Exits like other commands when it resolves to nothing.
+pub fn explain(options: &ExplainOptions, root_source: &str, settings: &ProjectSettings) {
+    let root = current_dir()
+        .with_context(|e| format!("Failed to read the current directory: {}", e))
+        .or_exit();
+    cprintln!("<s>root:</s> {} ({})", root.display(), root_source);
+
+    let display = ModuleManager::explain_display(&options.module, settings)
+        .with_context(|e| format!("Failed to explain module {}: {}", options.module, e))
+        .or_exit();
+    cprint!("{}", display);
+
+    ModuleManager::resolve(&options.module, settings)
+        .with_context(|e| format!("Failed to resolve module {}: {}", options.module, e))
+        .or_exit();
+}
@@ -1,105 +1,317 @@
+use std::io::{stdout, Write};
+
 use failure::ResultExt;
+use crate::exit_code::{ExitCode, OrExit};
 use color_print::{cprintln, cprint};
-use crate::module_manager::{ModuleManager, ModuleType};
-use crate::module_manager;
+use crate::module_manager::{FindFilter, FindRecord, ModuleManager, ProjectSettings};
+use crate::profile::{self, Phase};
+use crate::command_options::functions::root::{
+    check_not_empty, exit_on_parse_error, limit_to_changed, load_module, parse_own,
+    report_errors, root_modules,
+};
 use crate::command_options::options::FindOptions;
 
 
-pub fn find(options: &FindOptions) {
+fn find_subclasses(options: &FindOptions, settings: &ProjectSettings) -> bool {
+    let subclasses = ModuleManager::subclasses(&options.query, settings)
+        .with_context(|e| format!("Failed to find subclasses of {}: {}", options.query, e))
+        .or_exit();
+
+    let found = !subclasses.is_empty();
+    if options.jsonl {
+        let mut stdout = stdout().lock();
+        for (module, class) in subclasses {
+            let record = FindRecord {
+                module,
+                path: class.path,
+                kind: String::from("class"),
+                name: class.name,
+                line: class.line,
+                code: class.definition_code,
+            };
+            let line = serde_json::to_string(&record)
+                .with_context(|e| format!("Failed to export match {}: {}", record.name, e))
+                .or_exit();
+            writeln!(stdout, "{}", line).and_then(|_| stdout.flush()).or_exit();
+        }
+        return found;
+    }
+
+    if found {
+        cprintln!(
+            "<Y><s>󱁴 Searching for subclasses of <blink>[{}]</blink></s></Y>",
+            options.query
+        );
+    }
+
+    for (module, class) in subclasses {
+        let bases = class.base_classes.join(", ");
+        cprintln!(
+            "│――<green!>{}</green!>: <red>class</red> <yellow>{}</yellow>(<blue>{}</blue>)",
+            module,
+            class.name,
+            bases
+        );
+    }
+
+    found
+}
+
+/// Prints each match as a line of JSON, flushed right away so tools can
+/// read the results while the search goes on.
+fn print_records(module_manager: &ModuleManager, query: &str, filter: &FindFilter) -> bool {
+    let mut stdout = stdout().lock();
+    let mut found = false;
+
+    module_manager
+        .find_records(query, filter, &mut |record| {
+            found = true;
+            let line = serde_json::to_string(&record)
+                .with_context(|e| format!("Failed to export match {}: {}", record.name, e))
+                .or_exit();
+            writeln!(stdout, "{}", line).and_then(|_| stdout.flush()).or_exit();
+        })
+        .with_context(|e| format!("Failed to find module {}: {}", module_manager.module(), e))
+        .or_exit();
+
+    found
+}
+
+/// Prints the matches grouped under the path of their file.
+fn print_flat(module_manager: &ModuleManager, query: &str, filter: &FindFilter) -> bool {
+    let mut records = Vec::new();
+    module_manager
+        .find_records(query, filter, &mut |record| records.push(record))
+        .with_context(|e| format!("Failed to find module {}: {}", module_manager.module(), e))
+        .or_exit();
+
+    print!("{}", ModuleManager::flat_display(&records));
+    !records.is_empty()
+}
+
+fn print_search_header(query: &str, module: &str) {
+    cprintln!(
+        "<Y><s>󱁴 Searching for <blink>[{}]</blink> in <B>{}</B></s></Y>",
+        query,
+        module
+    );
+}
+
+/// Prints the matches in `module` and its sub modules while they are
+/// searched, one module at a time, so big trees show results right away.
+fn stream_matches(
+    module: &String,
+    query: &String,
+    filter: &FindFilter,
+    settings: &ProjectSettings,
+) -> bool {
+    let module_type = ModuleManager::resolve(module, settings)
+        .with_context(|e| format!("Failed to resolve module {}: {}", module, e))
+        .or_exit();
+
+    let mut stdout = stdout().lock();
+    let mut header_printed = false;
+    ModuleManager::find_streaming(
+        module,
+        module_type,
+        query,
+        filter,
+        settings,
+        &mut |display| {
+            if !header_printed {
+                print_search_header(query, module);
+                header_printed = true;
+            }
+            write!(stdout, "{}", display)
+                .and_then(|_| stdout.flush())
+                .or_exit();
+        },
+    )
+    .with_context(|e| format!("Failed to find module {}: {}", module, e))
+    .or_exit()
+}
+
+/// Exits with `ExitCode::NoMatches` when nothing is found, or with
+/// `ExitCode::Matched` when something is found under `--fail-on-match`.
+pub fn find(options: &FindOptions, settings: &ProjectSettings) {
+    let settings = &limit_to_changed(&options.since, settings);
+    let found = find_matches(options, settings);
+
+    // Stdin can only be read once, so it is checked as it is loaded.
+    if let Some(module) = &options.module {
+        if !ModuleManager::is_pattern(module) && module != "-" {
+            exit_on_parse_error(&parse_own(module, settings));
+        }
+    }
+
+    match (found, options.fail_on_match) {
+        (true, true) => ExitCode::Matched.exit(),
+        (false, false) => ExitCode::NoMatches.exit(),
+        _ => {}
+    }
+}
+
+/// The kinds of definitions and the display settings selected by the options.
+fn find_filter(options: &FindOptions) -> FindFilter {
+    let mut filter = FindFilter::kinds(
+        options.variable,
+        options.constants,
+        options.function,
+        options.class,
+        options.type_aliases,
+    );
+    if options.comments {
+        filter = FindFilter {
+            comments: true,
+            ..FindFilter::default()
+        };
+    }
+    if options.decorator {
+        filter = FindFilter {
+            decorators: true,
+            ..FindFilter::default()
+        };
+    }
+    if options.signature {
+        filter = FindFilter {
+            signatures: true,
+            ..FindFilter::default()
+        };
+    }
+    filter.follow_reexports = options.follow_reexports;
+    filter.parents = options.parents;
+    filter.context_lines = options.context_lines;
+    filter.package_ends = options.package_ends;
+    filter.hide_decorators = options.hide_decorators;
+    filter.show_empty = options.show_empty;
+
+    filter
+}
+
+/// Prints the number of matches of each module searched, most matches
+/// first, and their total.
+fn count_matches(options: &FindOptions, settings: &ProjectSettings) -> bool {
+    let root_modules = root_modules(settings);
+    let modules: Vec<String> = match &options.module {
+        Some(module) if ModuleManager::is_pattern(module) => {
+            ModuleManager::expand_pattern(module, settings)
+                .with_context(|e| format!("Failed to expand module pattern {}: {}", module, e))
+                .or_exit()
+                .into_iter()
+                .map(|(module, _)| module)
+                .collect()
+        }
+        Some(module) => vec![module.clone()],
+        None => {
+            if !check_not_empty(&root_modules, options.fail_on_empty) {
+                return true;
+            }
+            root_modules.modules.iter().map(|(module, _)| module.clone()).collect()
+        }
+    };
+
+    let filter = find_filter(options);
+    let mut records = Vec::new();
+    for module in &modules {
+        load_module(module, settings)
+            .find_records(&options.query, &filter, &mut |record| records.push(record))
+            .with_context(|e| format!("Failed to find module {}: {}", module, e))
+            .or_exit();
+    }
+
+    for (module, count) in ModuleManager::count_per_module(&records) {
+        cprintln!("<blue>{}</blue>: {}", module, count);
+    }
+    cprintln!("<s>total: {}</s>", records.len());
+
+    if options.module.is_none() {
+        report_errors(&root_modules);
+    }
+    !records.is_empty()
+}
+
+/// Prints the matches of the query, returning whether there were any.
+fn find_matches(options: &FindOptions, settings: &ProjectSettings) -> bool {
+    if options.subclasses {
+        return find_subclasses(options, settings);
+    }
+    if options.count_per_module {
+        return count_matches(options, settings);
+    }
+
+    let mut found = false;
+
     match &options.module {
+        Some(module) if ModuleManager::is_pattern(module) => {
+            let modules = ModuleManager::expand_pattern(module, settings)
+                .with_context(|e| format!("Failed to expand module pattern {}: {}", module, e))
+                .or_exit();
+
+            for (module, _) in modules {
+                let sub_options = FindOptions {
+                    module: Some(module),
+                    ..options.clone()
+                };
+
+                found |= find_matches(&sub_options, settings);
+            }
+        }
         Some(module) => {
             let query = &options.query;
 
-            let module_type = if options.is_file {
-                ModuleType::File
-            } else {
-                ModuleType::Directory
-            };
+            let filter = find_filter(options);
 
-            let mut module_manager = ModuleManager::new(module, module_type, false)
-                .with_context(|e| {
-                    format!(
-                        "Failed to create module manager for module {}: {}",
-                        module, e
-                    )
-                })
-                .unwrap();
-
-            module_manager
-                .reload()
-                .with_context(|e| {
-                    format!(
-                        "Failed to reload module manager for module {}: {}",
-                        module, e
-                    )
-                })
-                .unwrap();
-
-            let (find_vars, find_functions, find_classes) =
-                match !options.function && !options.class && !options.variable {
-                    true => (true, true, true),
-                    false => (options.variable, options.function, options.class),
-                };
+            if !ModuleManager::is_path(module) && !options.jsonl && !options.flat {
+                return stream_matches(module, query, &filter, settings);
+            }
+
+            let module_manager = load_module(module, settings);
+            if module == "-" {
+                exit_on_parse_error(&module_manager);
+            }
+
+            if options.jsonl {
+                return print_records(&module_manager, query, &filter);
+            }
+            if options.flat {
+                return print_flat(&module_manager, query, &filter);
+            }
+
+            // With --show-empty the displays are never empty, match or not.
+            let (displays, matched) = profile::time(Phase::Render, || {
+                module_manager.find(query, String::new(), &filter)
+            })
+            .with_context(|e| format!("Failed to find module {}: {}", module, e))
+            .or_exit();
 
-            let displays = module_manager
-                .find(
-                    query,
-                    String::new(),
-                    find_vars,
-                    find_functions,
-                    find_classes,
-                )
-                .with_context(|e| format!("Failed to find module {}: {}", module, e))
-                .unwrap();
-
-            if displays.len() > 0 {
-                cprintln!(
-                    "<Y><s>󱁴 Searching for <blink>[{}]</blink> in <B>{}</B></s></Y>",
-                    query,
-                    module
-                );
+            if !displays.is_empty() {
+                print_search_header(query, module);
             }
+            found = matched;
 
             for display in displays {
                 cprint!("{}", display)
             }
         }
         None => {
-            let _ = module_manager::ModuleManager::travel_root(None, Some(2))
-                .unwrap()
-                .filter(|m| {
-                    if m.file_name().unwrap() == "__init__.py" {
-                        if m.iter().count() != 3 {
-                            return false;
-                        } else {
-                            return true;
-                        }
-                    } else if m.iter().count() != 2 {
-                        return false;
-                    } else {
-                        return true;
-                    }
-                })
-                .map(|m| {
-                    let is_file = m.file_name().unwrap() != "__init__.py";
-                    let module = module_manager::ModuleManager::path_2_module(
-                        &m.to_str().unwrap().to_string(),
-                    )
-                    .with_context(|e| format!("Failed to convert path to module: {}", e))
-                    .unwrap();
-
-                    let sub_options = FindOptions {
-                        query: options.query.clone(),
-                        module: Some(module),
-                        is_file: is_file,
-                        function: options.function.clone(),
-                        class: options.class.clone(),
-                        variable: options.variable.clone(),
-                    };
-
-                    find(&sub_options)
-                })
-                .collect::<Vec<_>>();
+            let root_modules = root_modules(settings);
+            if !check_not_empty(&root_modules, options.fail_on_empty) {
+                // An empty project is not a failed search unless --fail-on-empty is set.
+                return true;
+            }
+
+            for (module, _) in &root_modules.modules {
+                let sub_options = FindOptions {
+                    module: Some(module.clone()),
+                    ..options.clone()
+                };
+
+                found |= find_matches(&sub_options, settings);
+            }
+
+            report_errors(&root_modules);
         }
     }
+
+    found
 }
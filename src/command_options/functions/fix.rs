@@ -0,0 +1,46 @@
+use color_print::cprintln;
+use failure::ResultExt;
+use crate::exit_code::OrExit;
+use crate::module_manager::{ModuleManager, ProjectSettings};
+use crate::command_options::options::FixOptions;
+
+
+/// Runs the selected fixes, or all of them when none is selected.
+/// Without `--write` the problems are only reported.
+pub fn fix(options: &FixOptions, settings: &ProjectSettings) {
+    let fixes: [(bool, fn(bool, &ProjectSettings)); 1] = [(options.init_files, fix_init_files)];
+    let run_all = fixes.iter().all(|(selected, _)| !selected);
+
+    for (selected, fix) in fixes {
+        if selected || run_all {
+            fix(options.write, settings);
+        }
+    }
+}
+
+fn fix_init_files(write: bool, settings: &ProjectSettings) {
+    let dirs = ModuleManager::missing_init_files(None, settings)
+        .with_context(|e| format!("Failed to look for missing __init__.py files: {}", e))
+        .or_exit();
+
+    if dirs.is_empty() {
+        cprintln!("<g>No directory is missing its __init__.py</g>");
+        return;
+    }
+
+    if write {
+        ModuleManager::create_init_files(&dirs)
+            .with_context(|e| format!("Failed to create __init__.py files: {}", e))
+            .or_exit();
+        cprintln!("<g>Created __init__.py in {} directories:</g>", dirs.len());
+    } else {
+        cprintln!(
+            "<y>{} directories have Python files but no __init__.py, run with --write to create them:</y>",
+            dirs.len()
+        );
+    }
+
+    for dir in dirs {
+        println!("│――{}", dir.strip_prefix("./").unwrap_or(&dir).display());
+    }
+}
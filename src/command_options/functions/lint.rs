@@ -0,0 +1,55 @@
+use color_print::cprintln;
+use failure::ResultExt;
+use crate::exit_code::OrExit;
+use crate::module_manager::{ModuleManager, ProjectSettings};
+use crate::command_options::options::LintOptions;
+use crate::command_options::functions::root::{check_not_empty, report_errors, root_modules};
+
+
+pub fn lint(options: &LintOptions, settings: &ProjectSettings) {
+    match &options.module {
+        Some(module) => {
+            // A package is linted with all the modules below it.
+            let module_managers = ModuleManager::modules_under(module, settings)
+                .with_context(|e| format!("Failed to load module {}: {}", module, e))
+                .or_exit();
+
+            for module_manager in module_managers {
+                let reports = match options.unused_imports {
+                    true => module_manager
+                        .unused_imports()
+                        .with_context(|e| {
+                            format!(
+                                "Failed to check the imports of module {}: {}",
+                                module_manager.module(),
+                                e
+                            )
+                        })
+                        .or_exit(),
+                    false => module_manager.duplicates(),
+                };
+
+                for report in reports {
+                    cprintln!("<yellow>{}</yellow>", report);
+                }
+            }
+        }
+        None => {
+            let root_modules = root_modules(settings);
+            if !check_not_empty(&root_modules, false) {
+                return;
+            }
+
+            for (module, _) in &root_modules.modules {
+                let sub_options = LintOptions {
+                    module: Some(module.clone()),
+                    unused_imports: options.unused_imports,
+                };
+
+                lint(&sub_options, settings)
+            }
+
+            report_errors(&root_modules);
+        }
+    }
+}
@@ -2,4 +2,14 @@ pub mod add;
 pub mod find;
 pub mod mv;
 pub mod view;
-pub mod check;
\ No newline at end of file
+pub mod check;
+pub mod doctor;
+pub mod fix;
+pub mod lint;
+pub mod root;
+pub mod annotate;
+pub mod stats;
+pub mod replace_import;
+pub mod rename_def;
+pub mod verify_imports;
+pub mod explain;
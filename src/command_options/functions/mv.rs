@@ -1,22 +1,249 @@
-use failure::ResultExt;
-use crate::module_manager::{ModuleManager, ModuleType};
+use std::fs::read_to_string;
+use std::io::{stdin, stdout, ErrorKind, IsTerminal, Write};
+use std::path::Path;
+
+use color_print::{cprint, cprintln};
+use failure::{Error, ResultExt};
+use crate::exit_code::{ExitCode, OrExit};
+use crate::module_manager::{ModuleManager, ModuleType, ProjectSettings};
 use crate::command_options::options::MoveOptions;
+use crate::poetry::pyproject::update_packages;
+
+/// Asks the user to confirm rewriting imports in `count` files.
+/// Without a terminal to ask on, only `--yes` allows the rewrite.
+fn confirm(count: usize) -> bool {
+    if !stdin().is_terminal() {
+        cprintln!("<r>Refusing to rewrite imports in {} files without --yes</r>", count);
+        return false;
+    }
+
+    cprint!("<y>This will rewrite imports in {} files. Continue? [y/N] </y>", count);
+    stdout().flush().unwrap();
+
+    let mut answer = String::new();
+    stdin().read_line(&mut answer).unwrap();
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Refuses to move a module onto a name the destination package already
+/// uses, listing the conflicts, unless `--force` is given. Merging into an
+/// existing package is the point of `--merge`, so it is not checked.
+fn check_collisions(
+    to: &str,
+    options: &MoveOptions,
+    settings: &ProjectSettings,
+) -> Result<(), Error> {
+    if options.force || options.merge {
+        return Ok(());
+    }
+
+    let conflicts = ModuleManager::collisions(to, settings)
+        .with_context(|e| format!("Failed to check the destination of {}: {}", to, e))?;
+
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    for conflict in &conflicts {
+        cprintln!("<y>- {}</y>", conflict);
+    }
+
+    Result::Err(Error::from(std::io::Error::new(
+        ErrorKind::AlreadyExists,
+        format!(
+            "{} would shadow {} existing names, use --force to move anyway",
+            to,
+            conflicts.len()
+        ),
+    )))
+}
+
+/// The module type asked for by `--as-package` or `--as-file`, if any.
+fn target_type(options: &MoveOptions) -> Option<ModuleType> {
+    match (options.as_package, options.as_file) {
+        (true, _) => Some(ModuleType::Directory),
+        (_, true) => Some(ModuleType::File),
+        _ => None,
+    }
+}
+
+/// Moves one module, then leaves a shim and updates pyproject.toml if asked to.
+fn move_module(
+    module_manager: &mut ModuleManager,
+    to: &str,
+    options: &MoveOptions,
+    settings: &ProjectSettings,
+) -> Result<(), Error> {
+    let module = module_manager.module().to_string();
+    let module_type = module_manager.module_type().clone();
+
+    let moved = match (options.merge, target_type(options)) {
+        (true, _) => module_manager.merge(to, options.scope.as_deref()),
+        (false, Some(target_type)) => module_manager
+            .mv_as(to, target_type, options.scope.as_deref())
+            .map(|_| Vec::new()),
+        (false, None) => module_manager.mv(to, options.scope.as_deref()).map(|_| Vec::new()),
+    };
+    let conflicts =
+        moved.with_context(|e| format!("Failed to move module {} to {}: {}", module, to, e))?;
+
+    if !conflicts.is_empty() {
+        let package = module_manager.path().parent().unwrap().to_path_buf();
+        for conflict in &conflicts {
+            cprintln!("<y>- {}</y>", package.join(conflict).display());
+        }
+
+        return Result::Err(Error::from(std::io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "Merged {} into {} except {} files with different contents on both sides, \
+                 left in {}",
+                module,
+                to,
+                conflicts.len(),
+                package.display()
+            ),
+        )));
+    }
+
+    if options.shim {
+        let shim = ModuleManager::write_shim(&module, to, &module_type, settings)
+            .with_context(|e| format!("Failed to write shim for module {}: {}", module, e))?;
+        cprintln!("<g>Left a deprecation shim at {}</g>", shim.display());
+    }
+
+    let pyproject = Path::new("pyproject.toml");
+    if options.pyproject && pyproject.exists() {
+        let changed = update_packages(pyproject, &module, to)
+            .with_context(|e| format!("Failed to update {}: {}", pyproject.display(), e))?;
+
+        if changed {
+            cprintln!("<g>Updated package declarations in {}</g>", pyproject.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the moves listed in `file` in dependency order, stopping at the
+/// first failure since later moves may depend on it.
+fn mv_batch(file: &Path, options: &MoveOptions, settings: &ProjectSettings) {
+    let contents = read_to_string(file)
+        .with_context(|e| format!("Failed to read {}: {}", file.display(), e))
+        .or_exit();
+
+    let moves = ModuleManager::parse_moves(&contents)
+        .with_context(|e| format!("Failed to parse {}: {}", file.display(), e))
+        .or_exit();
+
+    // The imports are rewritten with the full names, see `--root-package`.
+    let moves = moves
+        .into_iter()
+        .map(|(module, to)| (settings.qualify(&module), settings.qualify(&to)))
+        .collect();
+    let moves = ModuleManager::order_moves(moves)
+        .with_context(|e| format!("Failed to order the moves of {}: {}", file.display(), e))
+        .or_exit();
+
+    let invalid = moves
+        .iter()
+        .filter_map(|(module, _)| ModuleManager::resolve(module, settings).err())
+        .collect::<Vec<Error>>();
+
+    if !invalid.is_empty() {
+        for error in invalid {
+            cprintln!("<r>{}</r>", error);
+        }
+        ExitCode::Error.exit();
+    }
+
+    if !options.yes {
+        let mut count = 0;
+        let scope = options.scope.as_deref();
+        for (module, to) in &moves {
+            count += ModuleManager::files_to_rewrite(module, to, scope, settings)
+                .with_context(|e| format!("Failed to scan imports of module {}: {}", module, e))
+                .or_exit()
+                .len();
+        }
+
+        if count > 0 && !confirm(count) {
+            cprintln!("<r>Aborted moving {} modules</r>", moves.len());
+            ExitCode::Error.exit();
+        }
+    }
+
+    let mut moved = 0;
+    let mut failed = false;
+    for (module, to) in &moves {
+        if failed {
+            cprintln!("<y>- {} -> {} (skipped)</y>", module, to);
+            continue;
+        }
+
+        let result = match ModuleManager::locate(module, settings) {
+            Some(mut module_manager) => check_collisions(to, options, settings)
+                .and_then(|_| move_module(&mut module_manager, to, options, settings)),
+            None => Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} does not exist anymore", module),
+            ))),
+        };
+
+        match result {
+            Ok(_) => {
+                moved += 1;
+                cprintln!("<g>✔ {} -> {}</g>", module, to);
+            }
+            Err(e) => {
+                failed = true;
+                cprintln!("<r>✘ {} -> {}: {}</r>", module, to, e);
+            }
+        }
+    }
+
+    cprintln!("<b>Moved {} of {} modules</b>", moved, moves.len());
+
+    if failed {
+        ExitCode::Error.exit();
+    }
+}
+
+pub fn mv(options: &MoveOptions, settings: &ProjectSettings) {
+    if let Some(file) = &options.from_file {
+        return mv_batch(file, options, settings);
+    }
+
+    // The imports are rewritten with the full names, see `--root-package`.
+    let module = &settings.qualify(options.module.as_ref().unwrap());
+    let to = &settings.qualify(options.to.as_ref().unwrap());
+
+    check_collisions(to, options, settings).or_exit();
+
+    if !options.yes {
+        let files = ModuleManager::files_to_rewrite(module, to, options.scope.as_deref(), settings)
+            .with_context(|e| format!("Failed to scan imports of module {}: {}", module, e))
+            .or_exit();
+
+        if files.len() > 0 && !confirm(files.len()) {
+            cprintln!("<r>Aborted moving {} to {}</r>", module, to);
+            ExitCode::Error.exit();
+        }
+    }
 
-pub fn mv(options: &MoveOptions) {
-    let module = &options.module;
-    let to = &options.to;
+    let module_type = ModuleManager::resolve(module, settings)
+        .with_context(|e| format!("Failed to resolve module {}: {}", module, e))
+        .or_exit();
 
-    let mut module_manager = ModuleManager::new(module, ModuleType::Directory, false)
+    let mut module_manager = ModuleManager::new_with_settings(module, module_type, false, settings)
         .with_context(|e| {
             format!(
                 "Failed to create module manager for module {}: {}",
                 module, e
             )
         })
-        .unwrap();
+        .or_exit();
 
-    module_manager
-        .mv(to)
-        .with_context(|e| format!("Failed to move module {} to {}: {}", module, to, e))
-        .unwrap();
+    move_module(&mut module_manager, to, options, settings).or_exit();
 }
@@ -0,0 +1,47 @@
+use color_print::cprintln;
+use failure::ResultExt;
+use crate::exit_code::OrExit;
+use crate::module_manager::{ModuleManager, ProjectSettings};
+use crate::command_options::options::RenameDefOptions;
+
+
+pub fn rename_def(options: &RenameDefOptions, settings: &ProjectSettings) {
+    // Other modules import the definition by the full name.
+    let module = &settings.qualify(&options.module);
+
+    let module_type = ModuleManager::resolve(module, settings)
+        .with_context(|e| format!("Failed to resolve module {}: {}", module, e))
+        .or_exit();
+
+    let mut module_manager = ModuleManager::new_with_settings(module, module_type, false, settings)
+        .with_context(|e| {
+            format!(
+                "Failed to create module manager for module {}: {}",
+                module, e
+            )
+        })
+        .or_exit();
+
+    module_manager
+        .parse()
+        .with_context(|e| format!("Failed to parse module {}: {}", module, e))
+        .or_exit();
+
+    let count = module_manager
+        .rename_def(&options.old, &options.new)
+        .with_context(|e| format!("Failed to rename {}.{}: {}", module, options.old, e))
+        .or_exit();
+
+    cprintln!(
+        "<g>Renamed {} to {} in {} ({} occurrences)</g>",
+        options.old,
+        options.new,
+        module,
+        count
+    );
+    cprintln!(
+        "<y>Modules importing {}.{} are not updated</y>",
+        module,
+        options.old
+    );
+}
@@ -0,0 +1,32 @@
+use color_print::cprintln;
+use failure::ResultExt;
+use crate::exit_code::OrExit;
+use crate::module_manager::{ModuleManager, ProjectSettings};
+use crate::command_options::options::ReplaceImportOptions;
+
+
+pub fn replace_import(options: &ReplaceImportOptions, settings: &ProjectSettings) {
+    let files = ModuleManager::replace_in_root(&options.old, &options.new, None, settings)
+        .with_context(|e| {
+            format!(
+                "Failed to replace imports of {} with {}: {}",
+                options.old, options.new, e
+            )
+        })
+        .or_exit();
+
+    if files.is_empty() {
+        cprintln!("<y>No imports of {} found</y>", options.old);
+        return;
+    }
+
+    cprintln!(
+        "<g>Replaced imports of {} with {} in {} files:</g>",
+        options.old,
+        options.new,
+        files.len()
+    );
+    for file in files {
+        println!("│――{}", file.display());
+    }
+}
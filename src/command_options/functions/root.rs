@@ -0,0 +1,171 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use color_print::{cformat, cprintln};
+use failure::ResultExt;
+use crate::exit_code::{ExitCode, OrExit};
+use crate::module_manager::{ModuleManager, ModuleType, ProjectSettings};
+
+/// Loads and parses a module of the project, or a single file when given a
+/// path such as `scripts/run.py` or `-` for stdin.
+pub fn load_module(module: &String, settings: &ProjectSettings) -> ModuleManager {
+    if ModuleManager::is_path(module) {
+        return ModuleManager::from_path(module, settings)
+            .with_context(|e| format!("Failed to parse {}: {}", module, e))
+            .or_exit();
+    }
+
+    let module_type = ModuleManager::resolve(module, settings)
+        .with_context(|e| format!("Failed to resolve module {}: {}", module, e))
+        .or_exit();
+
+    let mut module_manager = ModuleManager::new_with_settings(module, module_type, false, settings)
+        .with_context(|e| {
+            format!(
+                "Failed to create module manager for module {}: {}",
+                module, e
+            )
+        })
+        .or_exit();
+
+    module_manager
+        .reload()
+        .with_context(|e| {
+            format!(
+                "Failed to reload module manager for module {}: {}",
+                module, e
+            )
+        })
+        .or_exit();
+
+    module_manager
+}
+
+/// Parses the own file of a module, or a single file when given a path,
+/// without loading the sub modules of a package.
+pub fn parse_own(module: &String, settings: &ProjectSettings) -> ModuleManager {
+    if ModuleManager::is_path(module) {
+        return load_module(module, settings);
+    }
+
+    let module_type = ModuleManager::resolve(module, settings)
+        .with_context(|e| format!("Failed to resolve module {}: {}", module, e))
+        .or_exit();
+
+    let mut module_manager = ModuleManager::new_with_settings(module, module_type, false, settings)
+        .with_context(|e| format!("Failed to create module manager for module {}: {}", module, e))
+        .or_exit();
+
+    module_manager
+        .parse()
+        .with_context(|e| format!("Failed to parse module {}: {}", module, e))
+        .or_exit();
+
+    module_manager
+}
+
+/// Exits with `ExitCode::ParseError` when the own file of a module named on
+/// the command line has a syntax error. Called once its results are shown,
+/// as the sub modules of a package may still have some.
+pub fn exit_on_parse_error(module_manager: &ModuleManager) {
+    if let Some(error) = module_manager.parse_error() {
+        eprintln!(
+            "{}",
+            cformat!(
+                "<r>Error: Failed to parse {}: {}</r>",
+                module_manager.path().display(),
+                error
+            )
+        );
+        ExitCode::ParseError.exit();
+    }
+}
+
+/// The `settings` limiting parsing to the files changed since the git ref
+/// `since`, or warns and keeps the whole project when git cannot tell.
+pub fn limit_to_changed(since: &Option<String>, settings: &ProjectSettings) -> ProjectSettings {
+    let mut settings = settings.clone();
+    if let Some(since) = since {
+        match ModuleManager::git_changed_files(since, &settings) {
+            Ok(files) => settings.changed_files = Some(Arc::new(files)),
+            Err(e) => cprintln!("<y>Warning: {}, using the whole project</y>", e),
+        }
+    }
+
+    settings
+}
+
+/// The modules found directly under the project root.
+pub struct RootModules {
+    pub modules: Vec<(String, ModuleType)>,
+    /// Paths that could not be converted to module names.
+    pub errors: Vec<String>,
+}
+
+pub fn root_modules(settings: &ProjectSettings) -> RootModules {
+    let mut modules = Vec::new();
+    let mut errors = Vec::new();
+
+    let entries = settings
+        .walker()
+        .max_depth(Some(2))
+        .walk()
+        .filter(|entry| entry.is_top_level);
+
+    for entry in entries {
+        let (path, module_type) = (entry.path, entry.module_type);
+
+        match ModuleManager::path_2_full_module(path.to_str().unwrap()) {
+            Ok(module) => modules.push((module, module_type)),
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    for package in settings.namespace_packages(Path::new(".")) {
+        let module = package.file_name().unwrap().to_string_lossy().to_string();
+        modules.push((module, ModuleType::Directory));
+    }
+
+    RootModules { modules, errors }
+}
+
+/// Tells the user when there is nothing to work on under the project root.
+/// Exits with a non-zero code if `fail_on_empty` is set.
+pub fn check_not_empty(root_modules: &RootModules, fail_on_empty: bool) -> bool {
+    if root_modules.modules.len() > 0 {
+        return true;
+    }
+
+    eprintln!(
+        "{}",
+        cformat!(
+            "<y>No Python modules found under {}</y>",
+            std::env::current_dir().unwrap().display()
+        )
+    );
+
+    if fail_on_empty {
+        ExitCode::Error.exit();
+    }
+
+    false
+}
+
+/// Reports the paths that could not be converted to modules once, after the
+/// results, on stderr so it never mixes with machine-readable output.
+pub fn report_errors(root_modules: &RootModules) {
+    if root_modules.errors.len() == 0 {
+        return;
+    }
+
+    eprintln!(
+        "{}",
+        cformat!(
+            "<r>Skipped {} paths that could not be converted to modules:</r>",
+            root_modules.errors.len()
+        )
+    );
+    for error in &root_modules.errors {
+        eprintln!("{}", cformat!("<r>  {}</r>", error));
+    }
+}
@@ -0,0 +1,186 @@
+use std::io::ErrorKind;
+
+use color_print::cprintln;
+use failure::{Error, ResultExt};
+use crate::exit_code::OrExit;
+use crate::module_manager::{ModuleManager, ProjectSettings};
+use crate::stats::{Stats, StatsDelta};
+use crate::command_options::options::StatsOptions;
+use crate::command_options::functions::root::limit_to_changed;
+
+
+/// Parses the file at `path` as it is at the git ref `rev`.
+fn module_at(rev: &str, path: &std::path::Path) -> ModuleManager {
+    let contents = ModuleManager::git_show(rev, path)
+        .with_context(|e| format!("Failed to read {} at {}: {}", path.display(), rev, e))
+        .or_exit();
+
+    ModuleManager::from_source(path, contents)
+        .with_context(|e| format!("Failed to parse {} at {}: {}", path.display(), rev, e))
+        .or_exit()
+}
+
+/// Prints the definitions added and removed between the refs of `range`,
+/// `base..head`, in the files changed between them, limited to `module` and
+/// the modules below it when set.
+fn compare(range: &str, module: &Option<String>, json: bool, settings: &ProjectSettings) {
+    let (base, head) = range
+        .split_once("..")
+        .ok_or_else(|| {
+            Error::from(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Expected base..head, got {}", range),
+            ))
+        })
+        .or_exit();
+
+    let files = ModuleManager::git_diff_files(base, head)
+        .with_context(|e| format!("Failed to list the changed files: {}", e))
+        .or_exit();
+
+    // The modules are named in full, see `ProjectSettings::root_package`.
+    let module = module.as_deref().map(|module| settings.qualify(module));
+    let selected = |module_manager: &ModuleManager| match &module {
+        Some(module) => {
+            module_manager.module() == module
+                || module_manager.module().starts_with(&format!("{}.", module))
+        }
+        None => true,
+    };
+
+    let mut deltas = Vec::new();
+    for (before_path, after_path) in files {
+        let before = before_path.map(|path| module_at(base, &path));
+        let after = after_path.map(|path| module_at(head, &path));
+        if !before.iter().chain(after.iter()).any(&selected) {
+            continue;
+        }
+
+        // A file that does not parse has no definitions, which is not a change.
+        let failed = [(base, &before), (head, &after)]
+            .into_iter()
+            .filter_map(|(rev, m)| Some((rev, m.as_ref()?)))
+            .find_map(|(rev, m)| Some((rev, m, m.parse_error()?)));
+        if let Some((rev, m, e)) = failed {
+            cprintln!(
+                "<y>Warning: skipping {}, it does not parse at {}: {}</y>",
+                m.path().display(),
+                rev,
+                e
+            );
+            continue;
+        }
+
+        let delta = StatsDelta::compare(before.as_ref(), after.as_ref());
+        if !delta.is_empty() {
+            deltas.push(delta);
+        }
+    }
+
+    if json {
+        let json = serde_json::to_string_pretty(&deltas)
+            .with_context(|e| format!("Failed to export stats: {}", e))
+            .or_exit();
+        println!("{}", json);
+        return;
+    }
+
+    for delta in deltas {
+        cprintln!(
+            "<blue>{}</blue>: {:+} classes, {:+} functions, {:+} vars",
+            delta.module,
+            delta.classes,
+            delta.functions,
+            delta.vars
+        );
+        for added in &delta.added {
+            cprintln!("│  <green>+ {}</green>", added);
+        }
+        for removed in &delta.removed {
+            cprintln!("│  <red>- {}</red>", removed);
+        }
+    }
+}
+
+pub fn stats(options: &StatsOptions, settings: &ProjectSettings) {
+    if let Some(range) = &options.compare {
+        return compare(range, &options.module, options.json, settings);
+    }
+
+    let settings = &limit_to_changed(&options.since, settings);
+
+    let module_managers = match &options.module {
+        Some(module) => ModuleManager::modules_under(module, settings)
+            .with_context(|e| format!("Failed to load module {}: {}", module, e))
+            .or_exit(),
+        None => ModuleManager::all_modules(settings)
+            .with_context(|e| format!("Failed to load modules: {}", e))
+            .or_exit(),
+    };
+
+    let stats = Stats::collect(&module_managers, options.max_args);
+
+    if options.json {
+        let json = serde_json::to_string_pretty(&stats)
+            .with_context(|e| format!("Failed to export stats: {}", e))
+            .or_exit();
+        println!("{}", json);
+        return;
+    }
+
+    for module_stats in stats.modules.iter().chain(std::iter::once(&stats.total)) {
+        cprintln!(
+            "<blue>{}</blue>: {} classes, {} functions, {} vars",
+            module_stats.module,
+            module_stats.classes,
+            module_stats.functions,
+            module_stats.vars
+        );
+
+        if options.count_async {
+            cprintln!(
+                "│  sync: <green>{}</green>, async: <magenta>{}</magenta>",
+                module_stats.sync_functions,
+                module_stats.async_functions
+            );
+        }
+
+        if options.count_lines {
+            cprintln!(
+                "│  lines: {} (code: <green>{}</green>, blank: {}, comments: <yellow>{}</yellow>)",
+                module_stats.lines,
+                module_stats.code_lines(),
+                module_stats.blank_lines,
+                module_stats.comment_lines
+            );
+        }
+    }
+
+    if options.count_async {
+        let fully_sync = stats.fully_sync_modules();
+        if !fully_sync.is_empty() {
+            cprintln!("<y>Fully synchronous modules ({}):</y>", fully_sync.len());
+            for module in fully_sync {
+                println!("│――{}", module);
+            }
+        }
+    }
+
+    if let Some(max_args) = options.max_args {
+        if !stats.too_many_args.is_empty() {
+            cprintln!(
+                "<y>Functions taking more than {} arguments ({}):</y>",
+                max_args,
+                stats.too_many_args.len()
+            );
+            for arg_count in &stats.too_many_args {
+                cprintln!(
+                    "│――<blue>{}</blue>: <magenta>{}</magenta> ({} arguments)",
+                    arg_count.module,
+                    arg_count.name,
+                    arg_count.arguments
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use color_print::cprintln;
+use failure::ResultExt;
+use crate::exit_code::{ExitCode, OrExit};
+use crate::module_manager::{ModuleManager, ProjectSettings};
+use crate::command_options::options::VerifyImportsOptions;
+
+
+/// Exits with `ExitCode::Matched` when references to the old module remain.
+pub fn verify_imports(options: &VerifyImportsOptions, settings: &ProjectSettings) {
+    let old = &settings.qualify(&options.old);
+    let references = ModuleManager::stale_references(old, settings)
+        .with_context(|e| format!("Failed to look for imports of {}: {}", old, e))
+        .or_exit();
+
+    if references.is_empty() {
+        cprintln!("<g>No imports of {} left</g>", old);
+        return;
+    }
+
+    cprintln!("<r>{} references to {} left:</r>", references.len(), old);
+    for (file, line, text) in references {
+        let file = file.strip_prefix("./").unwrap_or(&file).display().to_string();
+        cprintln!("│――<blue>{}:{}</blue>: {}", file, line, text);
+    }
+
+    ExitCode::Matched.exit();
+}
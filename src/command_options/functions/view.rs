@@ -1,73 +1,122 @@
 use failure::ResultExt;
-use crate::module_manager::{ModuleManager, ModuleType};
-use crate::module_manager;
+use crate::exit_code::OrExit;
+use crate::module_manager::{FindFilter, ModuleManager, ProjectSettings};
+use crate::profile::{self, Phase};
 use crate::command_options::options::ViewOptions;
+use crate::command_options::functions::root::{
+    check_not_empty, exit_on_parse_error, load_module, report_errors, root_modules,
+};
 
-
-pub fn view(options: &ViewOptions) {
+pub fn view(options: &ViewOptions, settings: &ProjectSettings) {
     match &options.module {
+        Some(module) if ModuleManager::is_pattern(module) => {
+            let modules = ModuleManager::expand_pattern(module, settings)
+                .with_context(|e| format!("Failed to expand module pattern {}: {}", module, e))
+                .or_exit();
+
+            for (module, _) in modules {
+                view_module(&module, options, settings);
+            }
+        }
         Some(module) => {
-            let file_path = module_manager::ModuleManager::module_2_path(module, &ModuleType::File)
-                .with_context(|e| format!("Failed to convert module to path: {}", e))
-                .unwrap();
+            let module_manager = view_module(module, options, settings);
+            exit_on_parse_error(&module_manager);
+        }
+        None => {
+            let root_modules = root_modules(settings);
+            if !check_not_empty(&root_modules, options.fail_on_empty) {
+                return;
+            }
 
-            let module_type = match file_path.exists() {
-                true => ModuleType::File,
-                false => ModuleType::Directory,
-            };
+            if options.list || options.count {
+                let names = ModuleManager::module_names(settings)
+                    .with_context(|e| format!("Failed to list modules: {}", e))
+                    .or_exit();
 
-            let mut module_manager = ModuleManager::new(module, module_type, false)
-                .with_context(|e| {
-                    format!(
-                        "Failed to create module manager for module {}: {}",
-                        module, e
-                    )
-                })
-                .unwrap();
+                match options.count {
+                    true => println!("{}", names.len()),
+                    false => print!("{}", ModuleManager::list_display(&names)),
+                }
+                report_errors(&root_modules);
+                return;
+            }
 
-            module_manager
-                .reload()
-                .with_context(|e| {
-                    format!(
-                        "Failed to reload module manager for module {}: {}",
-                        module, e
-                    )
-                })
-                .unwrap();
+            let modules = root_modules
+                .modules
+                .iter()
+                .map(|(module, _)| module.clone())
+                .collect::<Vec<_>>();
 
-            module_manager.mprint(String::new(), options.code);
-        }
-        None => {
-            let _ = module_manager::ModuleManager::travel_root(None, Some(2))
-                .unwrap()
-                .filter(|m| {
-                    if m.file_name().unwrap() == "__init__.py" {
-                        if m.iter().count() != 3 {
-                            return false;
-                        } else {
-                            return true;
-                        }
-                    } else if m.iter().count() != 2 {
-                        return false;
-                    } else {
-                        return true;
-                    }
-                })
-                .map(|m| {
-                    let module = module_manager::ModuleManager::path_2_module(
-                        &m.to_str().unwrap().to_string(),
-                    )
-                    .with_context(|e| format!("Failed to convert path to module: {}", e))
-                    .unwrap();
+            if options.json {
+                let module_managers = modules
+                    .iter()
+                    .map(|module| load_module(module, settings))
+                    .collect::<Vec<_>>();
+                let json = serde_json::to_string_pretty(&module_managers)
+                    .with_context(|e| format!("Failed to export modules: {}", e))
+                    .or_exit();
+                println!("{}", json);
+                report_errors(&root_modules);
+                return;
+            }
 
-                    let sub_options = ViewOptions {
-                        module: Some(module),
-                        code: options.code.clone(),
-                    };
+            for module in &modules {
+                view_module(module, options, settings);
+            }
 
-                    view(&sub_options)
-                })
-                .collect::<Vec<_>>();
+            report_errors(&root_modules);
         }
     }
 }
+
+/// Prints one module as asked by `options`, returning it once shown.
+fn view_module(
+    module: &String,
+    options: &ViewOptions,
+    settings: &ProjectSettings,
+) -> ModuleManager {
+    let module_manager = load_module(module, settings);
+
+    if options.json {
+        let json = module_manager
+            .to_json()
+            .with_context(|e| format!("Failed to export module {}: {}", module, e))
+            .or_exit();
+        println!("{}", json);
+        return module_manager;
+    }
+
+    let mut filter = FindFilter::kinds(
+        options.variables,
+        false,
+        options.functions,
+        options.classes,
+        false,
+    );
+    filter.package_ends = options.package_ends;
+    filter.raw = options.raw;
+    filter.hide_private_args = options.hide_private_args;
+    filter.group_by_kind = options.group_by_kind;
+    filter.hide_decorators = options.hide_decorators;
+    filter.conditional = options.conditional;
+    filter.counts = options.counts;
+    filter.reverse = options.reverse;
+    filter.compact_vars = options.compact_vars;
+    let show_code = options.code
+        || options.raw
+        || options.group_by_kind
+        || options.variables
+        || options.functions
+        || options.classes;
+
+    profile::time(Phase::Render, || match options.markdown {
+        true => print!(
+            "{}",
+            module_manager.markdown_display(1, show_code, options.summary, &filter)
+        ),
+        false => module_manager.mprint(String::new(), show_code, options.summary, &filter),
+    });
+
+    module_manager
+
+}
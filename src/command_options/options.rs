@@ -1,7 +1,51 @@
+use std::path::PathBuf;
+
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
 pub struct Options {
+    #[structopt(long = "follow-symlinks", global = true)]
+    /// Follow symbolic links when walking the project
+    pub follow_symlinks: bool,
+
+    #[structopt(long = "namespace-packages", global = true)]
+    /// Treat directories with Python files but no __init__.py as packages
+    pub namespace_packages: bool,
+
+    #[structopt(long = "exclude", global = true, number_of_values = 1)]
+    /// Skip the files and directories matching this glob, e.g. `build` or `**/migrations`
+    pub exclude: Vec<String>,
+
+    #[structopt(long = "gitignore", global = true)]
+    /// Skip what the .gitignore of the project root ignores
+    pub gitignore: bool,
+
+    #[structopt(long = "stubs", global = true)]
+    /// Also load the .pyi stubs of modules that have no .py file
+    pub stubs: bool,
+
+    // No `-v` short, it is taken by `find --variable`.
+    #[structopt(long = "verbose", global = true, parse(from_occurrences))]
+    /// Print what rp does, repeat for more details: twice also times the parse of each file
+    pub verbose: u64,
+
+    #[structopt(long = "profile", global = true)]
+    /// Print the time spent walking, reading, parsing and rendering on stderr
+    pub profile: bool,
+
+    #[structopt(long = "root", global = true, parse(from_os_str))]
+    /// The project root, by default the nearest directory above with a pyproject.toml
+    pub root: Option<PathBuf>,
+
+    #[structopt(long = "root-package", global = true)]
+    /// Name the modules of this package without it, e.g. `foo` for `mypkg.foo`;
+    /// resolved under the project root, so combine with `--root src` for a src layout
+    pub root_package: Option<String>,
+
+    #[structopt(long = "allow-wide-scan", global = true)]
+    /// Allow the project root to be the filesystem root or the home directory
+    pub allow_wide_scan: bool,
+
     #[structopt(subcommand)]
     pub subcommand: SubCommand,
 }
@@ -22,13 +66,44 @@ pub enum SubCommand {
 
     #[structopt(name = "check", about = "check requirements")]
     Check(CheckOptions),
+
+    #[structopt(name = "fix", about = "find and fix problems of the project layout")]
+    Fix(FixOptions),
+
+    #[structopt(name = "doctor", about = "look for dead code in the project")]
+    Doctor(DoctorOptions),
+
+    #[structopt(name = "lint", about = "lint a module")]
+    Lint(LintOptions),
+
+    #[structopt(name = "annotate", about = "annotate a module variable")]
+    Annotate(AnnotateOptions),
+
+    #[structopt(name = "stats", about = "count the definitions of the project")]
+    Stats(StatsOptions),
+
+    #[structopt(name = "replace-import", about = "rewrite the imports of a module without moving files")]
+    ReplaceImport(ReplaceImportOptions),
+
+    #[structopt(name = "rename-def", about = "rename a definition within its module")]
+    RenameDef(RenameDefOptions),
+
+    #[structopt(name = "verify-imports", about = "check that no import of a module is left")]
+    VerifyImports(VerifyImportsOptions),
+
+    #[structopt(name = "explain", about = "show how a module name resolves to a path")]
+    Explain(ExplainOptions),
 }
 
 #[derive(StructOpt)]
 pub struct AddOptions {
-    #[structopt()]
+    #[structopt(required_unless = "from-spec")]
     /// The name of the module to add
-    pub module: String,
+    pub module: Option<String>,
+
+    #[structopt(long = "from-spec", name = "from-spec", parse(from_os_str), conflicts_with = "module")]
+    /// Create the module tree described in a file, packages ending with `/` and children indented
+    pub from_spec: Option<PathBuf>,
 
     #[structopt(short = "f", long = "file")]
     /// Is the module a file?
@@ -37,31 +112,93 @@ pub struct AddOptions {
     #[structopt(short = "c", long = "contains")]
     /// List of modules that this module contains (files only)
     pub contains: Option<Vec<String>>,
+
+    #[structopt(short = "e", long = "export")]
+    /// Re-export the contained modules from the package's __init__.py
+    pub export: bool,
+
+    #[structopt(long = "dry-run")]
+    /// Only print the files and directories that would be created
+    pub dry_run: bool,
+
+    #[structopt(long = "template-dir", parse(from_os_str))]
+    /// Scaffold the package from this directory, replacing `{module}` in file names and contents
+    pub template_dir: Option<PathBuf>,
+
+    #[structopt(long = "force", conflicts_with = "from-spec")]
+    /// Reset an existing module's file, or package's __init__.py, to empty or to the template's
+    pub force: bool,
+
+    #[structopt(long = "with-test")]
+    /// Also create `test_<name>.py` importing the module, unless it exists
+    pub with_test: bool,
+
+    #[structopt(long = "tests-dir", parse(from_os_str))]
+    /// Where `--with-test` creates the test file, by default `tests_dir` of .rustipy.toml or `tests`
+    pub tests_dir: Option<PathBuf>,
 }
 
 #[derive(StructOpt)]
 pub struct MoveOptions {
-    #[structopt()]
+    #[structopt(required_unless = "from-file")]
     /// The name of the module to move
-    pub module: String,
+    pub module: Option<String>,
 
-    #[structopt()]
+    #[structopt(required_unless = "from-file")]
     /// The name of the module to move to
-    pub to: String,
+    pub to: Option<String>,
+
+    #[structopt(long = "from-file", name = "from-file", parse(from_os_str), conflicts_with = "module")]
+    /// Apply the moves listed in a file, one `old new` pair per line
+    pub from_file: Option<PathBuf>,
+
+    #[structopt(short = "y", long = "yes")]
+    /// Rewrite imports without asking for confirmation
+    pub yes: bool,
+
+    #[structopt(long = "pyproject")]
+    /// Also update the package declarations of pyproject.toml
+    pub pyproject: bool,
+
+    #[structopt(long = "shim")]
+    /// Leave a module at the old location re-exporting the new one with a deprecation warning
+    pub shim: bool,
+
+    #[structopt(long = "scope")]
+    /// Only rewrite the imports of the files under this directory
+    pub scope: Option<String>,
+
+    #[structopt(long = "merge")]
+    /// Merge a package into an existing package, keeping the files of both.
+    /// The files that differ on both sides are left in place and listed
+    pub merge: bool,
+
+    #[structopt(long = "force")]
+    /// Move even if the new name shadows a name of the destination package
+    pub force: bool,
+
+    #[structopt(long = "as-package", conflicts_with_all = &["as-file", "merge"])]
+    /// Turn a file module into a package, its code moving to the package's __init__.py
+    pub as_package: bool,
+
+    #[structopt(long = "as-file", name = "as-file", conflicts_with = "merge")]
+    /// Turn a package holding nothing but its __init__.py into a file module
+    pub as_file: bool,
 }
 
-#[derive(StructOpt)]
+#[derive(StructOpt, Clone)]
 pub struct FindOptions {
     #[structopt()]
     /// The name of the module to find
     pub query: String,
 
     #[structopt()]
-    /// The name of the module to find
+    /// The name of the module to find, may be a pattern such as `pkg.*` or `pkg.**.handlers`,
+    /// a file path such as `scripts/run.py`, or `-` to read the source from stdin
     pub module: Option<String>,
 
     #[structopt(short = "i", long = "is_file")]
-    /// Is the module a file?
+    /// Deprecated, whether the module is a file or a package is now detected
     pub is_file: bool,
 
     #[structopt(short = "f", long = "function")]
@@ -75,17 +212,163 @@ pub struct FindOptions {
     #[structopt(short = "v", long = "variable")]
     /// find variables
     pub variable: bool,
+
+    #[structopt(long = "constants")]
+    /// find module-level UPPER_CASE constants
+    pub constants: bool,
+
+    #[structopt(short = "t", long = "type-aliases")]
+    /// find type aliases
+    pub type_aliases: bool,
+
+    #[structopt(long = "follow-reexports")]
+    /// Resolve re-exported names to the module that defines them
+    pub follow_reexports: bool,
+
+    #[structopt(short = "p", long = "parents")]
+    /// Show the inheritance chain of matched classes
+    pub parents: bool,
+
+    #[structopt(long = "subclasses")]
+    /// List every class of the project inheriting from the class named by the query
+    pub subclasses: bool,
+
+    #[structopt(long = "comments")]
+    /// Search the `#` comments instead of the definitions, e.g. `find TODO --comments`
+    pub comments: bool,
+
+    #[structopt(long = "decorator")]
+    /// Search the decorators instead of the names, e.g. `find app.route --decorator`
+    pub decorator: bool,
+
+    #[structopt(long = "signature")]
+    /// Match the query, a regex or plain text, against whole function signatures,
+    /// e.g. `find "request: Request" --signature`
+    pub signature: bool,
+
+    #[structopt(long = "hide-decorators")]
+    /// Leave the `@decorator` lines out of the matched definitions
+    pub hide_decorators: bool,
+
+    #[structopt(short = "C", long = "context-lines", default_value = "0")]
+    /// Lines of source to show around each comment match
+    pub context_lines: usize,
+
+    #[structopt(long = "package-ends")]
+    /// Mark where the children of each package end
+    pub package_ends: bool,
+
+    #[structopt(long = "fail-on-empty")]
+    /// Exit with an error when no Python modules are found
+    pub fail_on_empty: bool,
+
+    #[structopt(long = "jsonl")]
+    /// Print one JSON object per match as soon as it is found
+    pub jsonl: bool,
+
+    #[structopt(long = "flat")]
+    /// Group the matches under the path of their file instead of the module tree
+    pub flat: bool,
+
+    #[structopt(long = "show-empty")]
+    /// Also list, greyed out, the modules searched without a match
+    pub show_empty: bool,
+
+    #[structopt(long = "count-per-module")]
+    /// Only print the number of matches of each module, most matches first, and the total
+    pub count_per_module: bool,
+
+    #[structopt(long = "since")]
+    /// Only search the files changed since this git ref, e.g. `main`
+    pub since: Option<String>,
+
+    #[structopt(long = "fail-on-match")]
+    /// Exit with code 7 when anything matches and 0 otherwise, to forbid names in CI
+    pub fail_on_match: bool,
 }
 
-#[derive(StructOpt)]
+#[derive(StructOpt, Clone)]
 pub struct ViewOptions {
     #[structopt()]
-    /// The name of the module to view
+    /// The name of the module to view, may be a pattern such as `pkg.*` or `pkg.**.handlers`,
+    /// a file path such as `scripts/run.py`, or `-` to read the source from stdin
     pub module: Option<String>,
 
     #[structopt(short = "c", long = "code")]
     /// Show the definitions code
     pub code: bool,
+
+    #[structopt(short = "s", long = "summary")]
+    /// Only show the number of classes, functions and variables of each module
+    pub summary: bool,
+
+    #[structopt(long = "counts")]
+    /// Show the number of classes and functions next to each module of the tree
+    pub counts: bool,
+
+    #[structopt(short = "j", long = "json")]
+    /// Export the module tree as JSON
+    pub json: bool,
+
+    #[structopt(short = "m", long = "markdown")]
+    /// Render the module tree as Markdown, e.g. to paste into the docs
+    pub markdown: bool,
+
+    #[structopt(long = "list")]
+    /// Without a module, only list the dotted names of every module, without parsing them
+    pub list: bool,
+
+    #[structopt(long = "count")]
+    /// Without a module, only print the number of modules, without parsing them
+    pub count: bool,
+
+    #[structopt(long = "classes")]
+    /// Only show the classes code
+    pub classes: bool,
+
+    #[structopt(long = "functions")]
+    /// Only show the functions code
+    pub functions: bool,
+
+    #[structopt(long = "variables")]
+    /// Only show the variables code
+    pub variables: bool,
+
+    #[structopt(long = "package-ends")]
+    /// Mark where the children of each package end
+    pub package_ends: bool,
+
+    #[structopt(long = "raw")]
+    /// Show the exact source of the functions and classes instead of their signatures
+    pub raw: bool,
+
+    #[structopt(long = "hide-private-args")]
+    /// Leave the arguments starting with `_` out of the signatures
+    pub hide_private_args: bool,
+
+    #[structopt(long = "hide-decorators")]
+    /// Leave the `@decorator` lines out of the signatures
+    pub hide_decorators: bool,
+
+    #[structopt(long = "conditional")]
+    /// Also show the classes and functions defined under a top-level `if`, e.g. per platform
+    pub conditional: bool,
+
+    #[structopt(long = "compact-vars")]
+    /// Show the variables of each module on one line, by name and type only
+    pub compact_vars: bool,
+
+    #[structopt(long = "reverse")]
+    /// List the sub modules and definitions of every module in reverse order
+    pub reverse: bool,
+
+    #[structopt(long = "group-by-kind")]
+    /// List classes, functions and variables under separate headers
+    pub group_by_kind: bool,
+
+    #[structopt(long = "fail-on-empty")]
+    /// Exit with an error when no Python modules are found
+    pub fail_on_empty: bool,
 }
 
 #[derive(StructOpt)]
@@ -93,4 +376,123 @@ pub struct CheckOptions {
     #[structopt(short = "f", long = "fix")]
     /// Fix the errors
     pub fix: bool,
-}
\ No newline at end of file
+}
+#[derive(StructOpt)]
+pub struct FixOptions {
+    #[structopt(long = "init-files")]
+    /// Find the directories with Python files but no __init__.py
+    pub init_files: bool,
+
+    #[structopt(short = "w", long = "write")]
+    /// Apply the fixes instead of only reporting the problems
+    pub write: bool,
+}
+
+#[derive(StructOpt)]
+pub struct DoctorOptions {
+    #[structopt(long = "orphans")]
+    /// List the modules that no other module of the project imports
+    pub orphans: bool,
+
+    #[structopt(long = "ignore", number_of_values = 1)]
+    /// Entry points never reported, as module patterns such as `scripts.*`
+    pub ignore: Vec<String>,
+}
+
+#[derive(StructOpt)]
+pub struct LintOptions {
+    #[structopt()]
+    /// The name of the module to lint, together with the modules below it
+    pub module: Option<String>,
+
+    #[structopt(long = "unused-imports")]
+    /// Report the imports of project modules that are never used instead of duplicate names
+    pub unused_imports: bool,
+}
+
+#[derive(StructOpt)]
+pub struct AnnotateOptions {
+    #[structopt()]
+    /// The name of the module containing the variable
+    pub module: String,
+
+    #[structopt()]
+    /// The name of the variable to annotate
+    pub name: String,
+
+    #[structopt(short = "t", long = "type", default_value = "Any")]
+    /// The type annotation to add
+    pub type_: String,
+}
+
+#[derive(StructOpt)]
+pub struct StatsOptions {
+    #[structopt()]
+    /// Only count this module and the modules below it
+    pub module: Option<String>,
+
+    #[structopt(short = "j", long = "json")]
+    /// Export the stats as JSON
+    pub json: bool,
+
+    #[structopt(short = "a", long = "count-async")]
+    /// Count sync and async functions and list the fully synchronous modules
+    pub count_async: bool,
+
+    #[structopt(short = "l", long = "count-lines")]
+    /// Count the code, blank and comment lines of each module
+    pub count_lines: bool,
+
+    #[structopt(long = "max-args")]
+    /// List the functions taking more than this many arguments
+    pub max_args: Option<usize>,
+
+    #[structopt(long = "since")]
+    /// Only count the files changed since this git ref, e.g. `main`
+    pub since: Option<String>,
+
+    #[structopt(long = "compare")]
+    /// Report the definitions added and removed between two git refs, as `base..head`,
+    /// in the files changed between them
+    pub compare: Option<String>,
+}
+
+#[derive(StructOpt)]
+pub struct ReplaceImportOptions {
+    #[structopt()]
+    /// The module imported today, e.g. `requests`
+    pub old: String,
+
+    #[structopt()]
+    /// The module to import instead
+    pub new: String,
+}
+
+#[derive(StructOpt)]
+pub struct RenameDefOptions {
+    #[structopt()]
+    /// The name of the module containing the definition
+    pub module: String,
+
+    #[structopt()]
+    /// The current name of the function, class or variable
+    pub old: String,
+
+    #[structopt()]
+    /// The new name
+    pub new: String,
+}
+
+#[derive(StructOpt)]
+pub struct VerifyImportsOptions {
+    #[structopt()]
+    /// The module that should no longer be imported, e.g. the old name of a moved module
+    pub old: String,
+}
+
+#[derive(StructOpt)]
+pub struct ExplainOptions {
+    #[structopt()]
+    /// The dotted name of the module, e.g. `pkg.sub.mod`
+    pub module: String,
+}
@@ -0,0 +1,61 @@
+//! Project settings read from the `.rustipy.toml` of the project root.
+//!
+//! ```toml
+//! # Where `add --with-test` creates the test files.
+//! tests_dir = "tests"
+//! ```
+
+use failure::{Error, ResultExt};
+use std::path::Path;
+use toml_edit::Document;
+
+pub const CONFIG_FILE: &str = ".rustipy.toml";
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    /// The directory of the test files created by `add --with-test`.
+    pub tests_dir: Option<String>,
+}
+
+impl Config {
+    /// Reads the `.rustipy.toml` of the project root. A project without one
+    /// gets the defaults.
+    pub fn load() -> Result<Config, Error> {
+        let path = Path::new(CONFIG_FILE);
+        if !path.is_file() {
+            return Ok(Config::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|e| format!("Could not read file {}: {}", CONFIG_FILE, e))?;
+        let config = Self::parse(&contents)
+            .with_context(|e| format!("Could not parse {}: {}", CONFIG_FILE, e))?;
+        Ok(config)
+    }
+
+    /// Parses the contents of a `.rustipy.toml`, ignoring unknown keys.
+    pub fn parse(contents: &str) -> Result<Config, Error> {
+        let document = contents.parse::<Document>()?;
+
+        Ok(Config {
+            tests_dir: document
+                .get("tests_dir")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse("# settings\ntests_dir = \"test/unit\"\n").unwrap();
+        assert_eq!(config.tests_dir, Some(String::from("test/unit")));
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("tests_dir = ").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+//! The exit codes of the command line, so scripts can tell failures apart.
+//!
+//! | Code | Meaning                                                          |
+//! |------|------------------------------------------------------------------|
+//! | 0    | Success                                                          |
+//! | 1    | Any other error                                                  |
+//! | 2    | The module was not found, e.g. `find`/`view` on a missing module |
+//! | 3    | A file could not be parsed                                       |
+//! | 4    | The destination already exists, e.g. `mv` onto an existing module |
+//! | 5    | A required tool is missing, e.g. `check` without poetry or python |
+//! | 6    | `find` found no matches                                          |
+//! | 7    | `find --fail-on-match` found a match, or `verify-imports` a stale import |
+use color_print::cformat;
+use failure::Error;
+use std::io::ErrorKind;
+
+use crate::module_manager::ModuleManager;
+use crate::profile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    Error = 1,
+    NotFound = 2,
+    ParseError = 3,
+    AlreadyExists = 4,
+    ToolMissing = 5,
+    NoMatches = 6,
+    Matched = 7,
+}
+
+impl ExitCode {
+    /// The exit code of an error, from the first I/O error in its chain of causes.
+    pub fn of(error: &Error) -> ExitCode {
+        for cause in error.iter_chain() {
+            if let Some(io_error) = cause.downcast_ref::<std::io::Error>() {
+                return match io_error.kind() {
+                    ErrorKind::NotFound => ExitCode::NotFound,
+                    ErrorKind::AlreadyExists => ExitCode::AlreadyExists,
+                    ErrorKind::InvalidData => ExitCode::ParseError,
+                    _ => ExitCode::Error,
+                };
+            }
+        }
+
+        ExitCode::Error
+    }
+
+    /// Ends the process with this code, after the reports of `finish`.
+    pub fn exit(self) -> ! {
+        finish();
+        std::process::exit(self as i32)
+    }
+}
+
+/// Prints the reports kept for the end of the run: the slowest files to
+/// parse with `--verbose` twice, and the time of each phase with `--profile`.
+/// Called on return from `main` and by `ExitCode::exit`.
+pub fn finish() {
+    ModuleManager::log_slowest_parses(10);
+    if profile::is_enabled() {
+        eprint!("{}", profile::report());
+    }
+}
+
+/// Ends the process on errors with the exit code matching the error,
+/// instead of panicking like `unwrap`.
+pub trait OrExit<T> {
+    fn or_exit(self) -> T;
+}
+
+impl<T, E: Into<Error>> OrExit<T> for Result<T, E> {
+    fn or_exit(self) -> T {
+        match self {
+            Ok(value) => value,
+            Err(error) => {
+                let error = error.into();
+                eprintln!("{}", cformat!("<r>Error: {}</r>", error));
+                ExitCode::of(&error).exit()
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Python project management helpers.
+//!
+//! The same module tree used by the `rp` command line tool is exposed here so
+//! other tools can parse, search and move Python modules programmatically.
+//!
+//! ```
+//! use rp::{FindFilter, ModuleManager, ModuleType, PythonDef};
+//!
+//! let mut module_manager =
+//!     ModuleManager::new("tests_data.test_module", ModuleType::File, false).unwrap();
+//! module_manager.reload().unwrap();
+//!
+//! let function = &module_manager.functions()[0];
+//! assert_eq!(function.name, "test_function");
+//! assert!(function.get_definition_code().starts_with("def test_function("));
+//!
+//! let (displays, found) = module_manager
+//!     .find(&String::from("TestClass"), String::new(), &FindFilter::all())
+//!     .unwrap();
+//! assert!(found && displays.len() > 0);
+//! ```
+
+pub mod ast;
+pub mod command_options;
+pub mod config;
+pub mod exit_code;
+pub mod logger;
+pub mod module_manager;
+pub mod poetry;
+pub mod profile;
+pub mod project_walker;
+pub mod python_def;
+pub mod stats;
+
+pub use ast::{parse_ast, parse_comments, parse_imports, parse_root_ast};
+pub use module_manager::{DefRef, FindFilter, ModuleManager, ModuleType, ProjectSettings};
+pub use project_walker::{ProjectWalker, WalkEntry};
+pub use python_def::{ArgType, Attribute, Class, Comment, Import, Method, PythonDef, TypeAlias};
@@ -0,0 +1,42 @@
+//! A minimal logger printing the `log` records of rp to stderr.
+//!
+//! Nothing is printed below warnings unless asked for: one `--verbose` shows
+//! info records, two show debug records such as the parse time of each file.
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(self: &Self, metadata: &Metadata) -> bool {
+        // The records of dependencies are left out, they are not about the project.
+        metadata.level() <= log::max_level() && metadata.target().starts_with("rp")
+    }
+
+    fn log(self: &Self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(self: &Self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// The level of the records shown for a number of `--verbose` flags.
+pub fn level_filter(verbosity: u64) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Installs the logger. Does nothing if a logger is already installed.
+pub fn init(verbosity: u64) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level_filter(verbosity));
+    }
+}
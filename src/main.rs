@@ -1,25 +1,112 @@
+use std::io::ErrorKind;
+use std::path::Path;
+
 use exitfailure::ExitFailure;
+use failure::{Error, ResultExt};
 use structopt::StructOpt;
 
-mod ast;
-mod command_options;
-mod module_manager;
-mod poetry;
-mod python_def;
+use rp::command_options::functions::{add::add, find::find, mv::mv, view::view, check::check, doctor::doctor, fix::fix, lint::lint, annotate::annotate, stats::stats, replace_import::replace_import, rename_def::rename_def, verify_imports::verify_imports, explain::explain};
+use rp::command_options::options::{FindOptions, Options, SubCommand, ViewOptions};
+use rp::exit_code::OrExit;
+use rp::{ModuleManager, ProjectSettings};
 
-use command_options::functions::{add::add, find::find, mv::mv, view::view, check::check};
-use command_options::options::{Options, SubCommand};
+/// Makes the file paths given on the command line absolute, since they are
+/// relative to where rp was started rather than to the project root.
+fn anchor_paths(subcommand: &mut SubCommand, cwd: &Path) {
+    match subcommand {
+        SubCommand::Add(add_options) => {
+            add_options.from_spec = add_options.from_spec.take().map(|f| cwd.join(f));
+            add_options.template_dir = add_options.template_dir.take().map(|d| cwd.join(d));
+            add_options.tests_dir = add_options.tests_dir.take().map(|d| cwd.join(d));
+        }
+        SubCommand::Move(move_options) => {
+            move_options.from_file = move_options.from_file.take().map(|f| cwd.join(f));
+            move_options.scope = move_options
+                .scope
+                .take()
+                .map(|d| cwd.join(d).to_string_lossy().to_string());
+        }
+        SubCommand::Find(FindOptions { module: Some(module), .. })
+        | SubCommand::View(ViewOptions { module: Some(module), .. })
+            if ModuleManager::is_path(module) && module != "-" =>
+        {
+            *module = cwd.join(&module).to_string_lossy().to_string();
+        }
+        _ => {}
+    }
+}
 
 fn main() -> Result<(), ExitFailure> {
-    let options = Options::from_args();
+    let mut options = Options::from_args();
+    rp::logger::init(options.verbose);
+    if options.profile {
+        rp::profile::enable();
+    }
+
+    // Work from the project root so rp can be run from any of its directories.
+    let cwd = std::env::current_dir()?;
+    let root = match &options.root {
+        Some(root) => Some(root.clone()),
+        None => ModuleManager::find_project_root(&cwd),
+    };
+    let root_source = match (&options.root, &root) {
+        (Some(_), _) => "from --root",
+        (None, Some(_)) => "nearest pyproject.toml",
+        (None, None) => "current directory",
+    };
+    if let Some(root) = root {
+        anchor_paths(&mut options.subcommand, &cwd);
+        std::env::set_current_dir(&root)
+            .with_context(|e| format!("Failed to enter project root {}: {}", root.display(), e))
+            .or_exit();
+    }
+
+    // Walking / or ~ would scan the whole disk, which is never what was meant.
+    let project_root = std::env::current_dir()?;
+    if !options.allow_wide_scan && ModuleManager::is_wide_root(&project_root) {
+        Result::<(), Error>::Err(Error::from(std::io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!(
+                "Refusing to scan every Python file under {}, run rp from a project \
+                 or pass --allow-wide-scan",
+                project_root.display()
+            ),
+        )))
+        .or_exit();
+    }
+
+    let settings = &ProjectSettings {
+        follow_symlinks: options.follow_symlinks,
+        namespace_packages: options.namespace_packages,
+        excludes: options.exclude.clone(),
+        gitignore: options.gitignore,
+        stubs: options.stubs,
+        root_package: options.root_package.clone(),
+        changed_files: None,
+    };
 
     match options.subcommand {
-        SubCommand::Add(add_options) => add(&add_options),
-        SubCommand::Move(move_options) => mv(&move_options),
-        SubCommand::Find(find_options) => find(&find_options),
-        SubCommand::View(view_options) => view(&view_options),
+        SubCommand::Add(add_options) => add(&add_options, settings),
+        SubCommand::Move(move_options) => mv(&move_options, settings),
+        SubCommand::Find(find_options) => find(&find_options, settings),
+        SubCommand::View(view_options) => view(&view_options, settings),
         SubCommand::Check(_) => check(),
+        SubCommand::Fix(fix_options) => fix(&fix_options, settings),
+        SubCommand::Doctor(doctor_options) => doctor(&doctor_options, settings),
+        SubCommand::Lint(lint_options) => lint(&lint_options, settings),
+        SubCommand::Annotate(annotate_options) => annotate(&annotate_options, settings),
+        SubCommand::Stats(stats_options) => stats(&stats_options, settings),
+        SubCommand::ReplaceImport(replace_import_options) => {
+            replace_import(&replace_import_options, settings)
+        }
+        SubCommand::RenameDef(rename_def_options) => rename_def(&rename_def_options, settings),
+        SubCommand::VerifyImports(verify_imports_options) => {
+            verify_imports(&verify_imports_options, settings)
+        }
+        SubCommand::Explain(explain_options) => explain(&explain_options, root_source, settings),
     }
 
+    rp::exit_code::finish();
+
     Ok(())
 }
@@ -1,19 +1,31 @@
-use crate::ast::{parse_ast, parse_root_ast};
-use crate::python_def::{Attribute, Class, Method, PythonDef};
+use crate::ast::{
+    line_of, literal_ranges, name_references, parse_comments, parse_conditional_defs,
+    parse_deletions, parse_imports, parse_root_ast, try_parse_contents,
+};
+use crate::profile::{self, Phase};
+use crate::project_walker::ProjectWalker;
+use crate::python_def::{Attribute, Class, Comment, Import, Method, PythonDef, TypeAlias};
 use color_print::cformat;
 use failure::{Error, ResultExt};
 use fs_extra::dir::{move_dir, CopyOptions};
-use log::{debug, info};
+use fs_extra::file::CopyOptions as FileCopyOptions;
+use log::{debug, info, log_enabled, Level};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs::{create_dir_all, rename, File};
 use std::io::ErrorKind;
 use std::{
     io::Read,
-    path::{Path, PathBuf},
+    ops::Range,
+    path::{Component, Path, PathBuf},
+    process::Command,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
 };
 use walkdir::WalkDir;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ModuleType {
     File,
     Directory,
@@ -29,17 +41,288 @@ impl PartialEq for ModuleType {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct ModuleManager {
     path: PathBuf,
     module: String,
     classes: Vec<Class>,
     functions: Vec<Method>,
     vars: Vec<Attribute>,
+    imports: Vec<Import>,
+    type_aliases: Vec<TypeAlias>,
+    /// Classes and functions defined under a top-level `if`, kept apart as
+    /// they may not exist at runtime.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    conditional_classes: Vec<Class>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    conditional_functions: Vec<Method>,
+    /// Comments are searchable but not part of the exported definitions.
+    #[serde(skip)]
+    comments: Vec<Comment>,
+    /// The source of the module file, to show context around matches.
+    #[serde(skip)]
+    source: String,
+    /// The modification time of the file when it was last parsed.
+    #[serde(skip)]
+    parsed_at: Option<SystemTime>,
+    /// Why the file could not be parsed, in which case it has no definitions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_error: Option<String>,
+    /// The project-wide settings of the command line, see `with_settings`.
+    /// Passed down to the sub modules.
+    #[serde(skip)]
+    settings: ProjectSettings,
+    /// Whether `refresh` parses every file again, modified or not, see
+    /// `with_no_cache`. Passed down to the sub modules.
+    #[serde(skip)]
+    no_cache: bool,
     module_type: ModuleType,
     sub_modules: Vec<ModuleManager>,
 }
 
+/// The project-wide settings of the command line, given to each
+/// `ModuleManager` and to the functions walking the whole project.
+#[derive(Clone, Debug, Default)]
+pub struct ProjectSettings {
+    /// Whether traversals follow symbolic links, off unless `--follow-symlinks` is given.
+    pub follow_symlinks: bool,
+    /// Whether directories holding Python files but no `__init__.py` are
+    /// treated as PEP 420 namespace packages. Off unless `--namespace-packages`
+    /// is given, since it also picks up directories that are not packages.
+    pub namespace_packages: bool,
+    /// Globs of the files and directories every traversal skips, see `ProjectWalker`.
+    pub excludes: Vec<String>,
+    /// Whether traversals skip what the project's `.gitignore` ignores,
+    /// off unless `--gitignore` is given.
+    pub gitignore: bool,
+    /// Whether the `.pyi` stubs of modules without a `.py` file are loaded
+    /// like modules, off unless `--stubs` is given.
+    pub stubs: bool,
+    /// The package, relative to the project root, whose modules can be named
+    /// without it: with `mypkg`, `foo` stands for `mypkg.foo`. A `src/`
+    /// layout is reached with `--root src` first. Only the names typed, and
+    /// those given by `path_2_module`, are shortened; modules are still
+    /// listed and imported by their full name, and the modules of the
+    /// project root keep theirs.
+    pub root_package: Option<String>,
+    /// Only parses these files when set, e.g. the files changed since a git
+    /// ref. The other modules are still walked but come out empty.
+    pub changed_files: Option<Arc<HashSet<PathBuf>>>,
+}
+
+impl ProjectSettings {
+    /// The full name of a `module` typed on the command line, see
+    /// `root_package`.
+    pub fn qualify(self: &Self, module: &str) -> String {
+        if self.root_package.is_none() {
+            return module.to_string();
+        }
+
+        // A module of the project root, such as one listed by the walker,
+        // is already a full name.
+        let top = module.split(".").next().unwrap();
+        if Path::new(top).is_dir() || Path::new(&format!("{}.py", top)).is_file() {
+            return module.to_string();
+        }
+
+        ModuleManager::qualified(module, self.root_package.as_deref())
+    }
+
+    /// Is `path` parsed, see `changed_files`?
+    fn is_selected(self: &Self, path: &Path) -> bool {
+        match &self.changed_files {
+            Some(files) => files.contains(path.strip_prefix(".").unwrap_or(path)),
+            None => true,
+        }
+    }
+
+    /// Is `dir` a namespace package: a directory without `__init__.py` that
+    /// directly contains Python files? Always false unless enabled.
+    pub fn is_namespace_package(self: &Self, dir: &Path) -> bool {
+        ModuleManager::namespace_package_at(dir, self.namespace_packages)
+    }
+
+    /// The namespace packages directly inside `dir`, sorted. Hidden
+    /// directories such as `.git` or `.venv` are never packages.
+    pub fn namespace_packages(self: &Self, dir: &Path) -> Vec<PathBuf> {
+        ModuleManager::namespace_packages_in(dir, self.namespace_packages)
+    }
+
+    /// A walk over the project's Python files with the traversal settings:
+    /// symbolic links, excludes and `.gitignore`.
+    pub fn walker(self: &Self) -> ProjectWalker {
+        let mut walker = ProjectWalker::new()
+            .follow_symlinks(self.follow_symlinks)
+            .gitignore(self.gitignore)
+            .stubs(self.stubs);
+        for glob in self.excludes.iter() {
+            walker = walker.exclude(glob);
+        }
+
+        walker
+    }
+}
+
+/// Which kinds of definitions `ModuleManager::find` searches.
+#[derive(Clone, Debug, Default)]
+pub struct FindFilter {
+    pub vars: bool,
+    /// Module-level constants, also included in `vars`.
+    pub constants: bool,
+    pub functions: bool,
+    pub classes: bool,
+    pub type_aliases: bool,
+    /// Also match re-exported names and resolve them to their definition.
+    pub follow_reexports: bool,
+    /// Show the inheritance chain of matched classes.
+    pub parents: bool,
+    /// `#` comments, e.g. TODOs. Not part of `all`.
+    pub comments: bool,
+    /// Functions, classes and methods with a decorator matching the query.
+    /// Not part of `all`.
+    pub decorators: bool,
+    /// Functions and methods whose signature matches the query, as a regex
+    /// or, when it is not a valid one, as plain text. Not part of `all`.
+    pub signatures: bool,
+    /// Lines of source shown around each comment match.
+    pub context_lines: usize,
+    /// Close each package's children with an "end of package" line.
+    pub package_ends: bool,
+    /// Show the verbatim source of functions and classes instead of their
+    /// reconstructed signatures.
+    pub raw: bool,
+    /// Leave the arguments named with a leading `_` out of signatures.
+    pub hide_private_args: bool,
+    /// List the definitions under one labeled section per kind.
+    pub group_by_kind: bool,
+    /// Leave the `@decorator` lines out of the displayed definitions.
+    pub hide_decorators: bool,
+    /// Also show the classes and functions defined under a top-level `if`.
+    pub conditional: bool,
+    /// Show the number of classes and functions next to each module of the tree.
+    pub counts: bool,
+    /// Also show, greyed out, the modules searched without a match.
+    pub show_empty: bool,
+    /// List the sub modules and definitions of each module in reverse order.
+    pub reverse: bool,
+    /// Show the variables of a module on one line, by name and type only.
+    pub compact_vars: bool,
+}
+
+impl FindFilter {
+    /// A filter matching every kind of definition.
+    pub fn all() -> Self {
+        FindFilter {
+            vars: true,
+            constants: true,
+            functions: true,
+            classes: true,
+            type_aliases: true,
+            follow_reexports: false,
+            parents: false,
+            comments: false,
+            decorators: false,
+            signatures: false,
+            context_lines: 0,
+            package_ends: false,
+            raw: false,
+            hide_private_args: false,
+            group_by_kind: false,
+            hide_decorators: false,
+            conditional: false,
+            counts: false,
+            show_empty: false,
+            reverse: false,
+            compact_vars: false,
+        }
+    }
+
+    /// A filter matching the selected kinds, or every kind when none is selected.
+    pub fn kinds(
+        vars: bool,
+        constants: bool,
+        functions: bool,
+        classes: bool,
+        type_aliases: bool,
+    ) -> Self {
+        match !vars && !constants && !functions && !classes && !type_aliases {
+            true => FindFilter::all(),
+            false => FindFilter {
+                vars,
+                constants,
+                functions,
+                classes,
+                type_aliases,
+                ..FindFilter::default()
+            },
+        }
+    }
+}
+
+/// A match of `find` as plain data, for machine readable output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FindRecord {
+    pub module: String,
+    pub path: String,
+    /// `variable`, `constant`, `type_alias`, `function`, `class`, `method`,
+    /// `comment` or `import`.
+    pub kind: String,
+    pub name: String,
+    pub line: usize,
+    /// The definition code, or the text of a comment, without colors.
+    pub code: String,
+}
+
+/// A definition of a module tree, see `ModuleManager::walk_defs`.
+#[derive(Debug, Clone, Copy)]
+pub enum DefRef<'a> {
+    Class {
+        module: &'a ModuleManager,
+        class: &'a Class,
+    },
+    Function {
+        module: &'a ModuleManager,
+        function: &'a Method,
+    },
+    /// A method, with the class defining it.
+    Method {
+        module: &'a ModuleManager,
+        class: &'a Class,
+        method: &'a Method,
+    },
+    Variable {
+        module: &'a ModuleManager,
+        var: &'a Attribute,
+    },
+}
+
+impl<'a> DefRef<'a> {
+    /// The module owning the definition.
+    pub fn module(self: &Self) -> &'a ModuleManager {
+        match *self {
+            DefRef::Class { module, .. }
+            | DefRef::Function { module, .. }
+            | DefRef::Method { module, .. }
+            | DefRef::Variable { module, .. } => module,
+        }
+    }
+
+    pub fn name(self: &Self) -> &'a str {
+        match *self {
+            DefRef::Class { class, .. } => &class.name,
+            DefRef::Function { function, .. } => &function.name,
+            DefRef::Method { method, .. } => &method.name,
+            DefRef::Variable { var, .. } => &var.name,
+        }
+    }
+}
+
+/// How many import hops name resolution follows before giving up.
+const MAX_RESOLVE_DEPTH: usize = 8;
+
+/// How long each file took to parse, kept only when debug records are on.
+static PARSE_TIMES: RwLock<Vec<(PathBuf, Duration)>> = RwLock::new(Vec::new());
+
 impl ModuleManager {
     /// Creates a new ModuleManager from a module and a module type.
     /// Path should in format "path.to.module".
@@ -48,9 +331,9 @@ impl ModuleManager {
     /// If path is a file, it will search for a file with the same name but with .py extension.
     /// #Example
     /// ```
-    /// use module_manager::{ModuleManager, ModuleType};
+    /// use rp::module_manager::{ModuleManager, ModuleType};
     ///
-    /// let module_manager = ModuleManager::new("path.to.module", ModuleType::File, true).unwrap();
+    /// let module_manager = ModuleManager::new("path.to.module", ModuleType::File, false).unwrap();
     /// ```
     /// #Errors
     /// Returns an error if the module manager could not be created.
@@ -59,133 +342,704 @@ impl ModuleManager {
     /// #Notes
     /// If build is true, it will create the module and reload it.
     pub fn new(module: &str, module_type: ModuleType, build: bool) -> Result<Self, Error> {
-        let mut path = String::new();
+        Self::new_with_settings(module, module_type, build, &ProjectSettings::default())
+    }
 
-        for component in module.split(".") {
-            path.push_str(component);
-            path.push_str("/");
-        }
+    /// See `new`, with the project `settings` of the command line rather
+    /// than the defaults: `module` is qualified with the root package, and
+    /// the settings are kept with `with_settings`.
+    pub fn new_with_settings(
+        module: &str,
+        module_type: ModuleType,
+        build: bool,
+        settings: &ProjectSettings,
+    ) -> Result<Self, Error> {
+        let module = settings.qualify(module);
+        let path = Self::module_2_path(&module, &module_type, settings)?;
+        let mut module_manager =
+            Self::at_path(path, &module, module_type).with_settings(settings.clone());
 
-        if module_type == ModuleType::Directory {
-            path.push_str("__init__.py");
-        } else {
-            path.pop();
-            path.push_str(".py");
+        if build {
+            module_manager.build()?;
+            module_manager.reload()?;
         }
 
-        let mut module_manager = Self {
-            path: PathBuf::from(path),
+        Ok(module_manager)
+    }
+
+    /// An empty, unparsed module manager for the file at `path`.
+    fn at_path(path: PathBuf, module: &str, module_type: ModuleType) -> Self {
+        Self {
+            path: path,
             module: module.to_owned(),
             classes: Vec::new(),
             functions: Vec::new(),
             vars: Vec::new(),
+            imports: Vec::new(),
+            type_aliases: Vec::new(),
+            conditional_classes: Vec::new(),
+            conditional_functions: Vec::new(),
+            comments: Vec::new(),
+            source: String::new(),
+            parsed_at: None,
+            parse_error: None,
+            settings: ProjectSettings::default(),
+            no_cache: false,
             module_type: module_type,
             sub_modules: Vec::new(),
+        }
+    }
+
+    /// Walks and loads this module and its sub modules with `settings`:
+    /// symbolic links, excludes, stubs, namespace packages and so on.
+    pub fn with_settings(mut self: Self, settings: ProjectSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Loads the directories without `__init__.py` below this module as
+    /// namespace packages, or not, whatever its settings say.
+    pub fn with_namespace_packages(mut self: Self, enabled: bool) -> Self {
+        self.settings.namespace_packages = enabled;
+        self
+    }
+
+    /// Makes `refresh` parse every file again instead of reusing the
+    /// definitions of the files not modified since they were parsed.
+    pub fn with_no_cache(mut self: Self, no_cache: bool) -> Self {
+        self.no_cache = no_cache;
+        self
+    }
+
+    pub fn module(self: &Self) -> &str {
+        &self.module
+    }
+
+    pub fn path(self: &Self) -> &Path {
+        &self.path
+    }
+
+    pub fn module_type(self: &Self) -> &ModuleType {
+        &self.module_type
+    }
+
+    pub fn classes(self: &Self) -> &Vec<Class> {
+        &self.classes
+    }
+
+    pub fn functions(self: &Self) -> &Vec<Method> {
+        &self.functions
+    }
+
+    pub fn vars(self: &Self) -> &Vec<Attribute> {
+        &self.vars
+    }
+
+    pub fn imports(self: &Self) -> &Vec<Import> {
+        &self.imports
+    }
+
+    pub fn type_aliases(self: &Self) -> &Vec<TypeAlias> {
+        &self.type_aliases
+    }
+
+    pub fn sub_modules(self: &Self) -> &Vec<ModuleManager> {
+        &self.sub_modules
+    }
+
+    /// The contents of the module's file when it was last parsed.
+    pub fn source(self: &Self) -> &str {
+        &self.source
+    }
+
+    /// Why the file could not be parsed, if it could not.
+    pub fn parse_error(self: &Self) -> Option<&str> {
+        self.parse_error.as_deref()
+    }
+
+    /// The badge marking a module whose file could not be parsed.
+    fn parse_error_badge(self: &Self) -> String {
+        match self.parse_error {
+            Some(_) => cformat!(" <yellow>⚠ parse error</yellow>"),
+            None => String::new(),
+        }
+    }
+
+    /// Every class, function, method and variable of this module and its
+    /// sub-modules, each module's definitions before its sub-modules'.
+    pub fn walk_defs(self: &Self) -> Box<dyn Iterator<Item = DefRef<'_>> + '_> {
+        let classes = self.classes.iter().flat_map(move |class| {
+            std::iter::once(DefRef::Class {
+                module: self,
+                class,
+            })
+            .chain(class.methods.iter().map(move |method| DefRef::Method {
+                module: self,
+                class,
+                method,
+            }))
+        });
+        let functions = self.functions.iter().map(move |function| DefRef::Function {
+            module: self,
+            function,
+        });
+        let vars = self
+            .vars
+            .iter()
+            .map(move |var| DefRef::Variable { module: self, var });
+        let sub_modules = self.sub_modules.iter().flat_map(|m| m.walk_defs());
+
+        Box::new(classes.chain(functions).chain(vars).chain(sub_modules))
+    }
+
+    /// The nearest directory holding a `pyproject.toml`, from `start` upward,
+    /// like git looks for `.git`.
+    pub fn find_project_root(start: &Path) -> Option<PathBuf> {
+        start
+            .ancestors()
+            .find(|dir| dir.join("pyproject.toml").is_file())
+            .map(Path::to_path_buf)
+    }
+
+    /// Is `dir` the filesystem root or the home directory, where walking
+    /// would scan far more than a project?
+    pub fn is_wide_root(dir: &Path) -> bool {
+        let dir = dir.canonicalize().unwrap_or(dir.to_path_buf());
+        let home = std::env::var_os("HOME")
+            .and_then(|home| PathBuf::from(home).canonicalize().ok());
+
+        dir.parent().is_none() || home == Some(dir)
+    }
+
+    /// The Python files of the project changed since the git ref `since`.
+    /// Errors if git is missing or the current directory is not a repository.
+    pub fn git_changed_files(
+        since: &str,
+        settings: &ProjectSettings,
+    ) -> Result<HashSet<PathBuf>, Error> {
+        let output = Command::new("git")
+            .args(["diff", "--name-only", "--relative", since])
+            .output()
+            .with_context(|e| format!("Could not run git: {}", e))?;
+
+        if !output.status.success() {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::Other,
+                format!(
+                    "git diff {} failed: {}",
+                    since,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            )));
+        }
+
+        Ok(Self::changed_python_files(
+            &String::from_utf8_lossy(&output.stdout),
+            Self::travel_root(None, None, settings)?,
+        ))
+    }
+
+    /// The Python files changed between the git refs `base` and `head`,
+    /// relative to the project root, as their paths at `base` and at `head`,
+    /// see `diff_file_pairs`.
+    pub fn git_diff_files(
+        base: &str,
+        head: &str,
+    ) -> Result<Vec<(Option<PathBuf>, Option<PathBuf>)>, Error> {
+        let output = Command::new("git")
+            .args(["diff", "--name-status", "--find-renames", "--relative", base, head])
+            .output()
+            .with_context(|e| format!("Could not run git: {}", e))?;
+
+        if !output.status.success() {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::Other,
+                format!(
+                    "git diff {}..{} failed: {}",
+                    base,
+                    head,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            )));
+        }
+
+        Ok(Self::diff_file_pairs(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// The Python files listed in `diff`, the output of `git diff --name-status`,
+    /// as their paths before and after. An added file has no path before, a
+    /// deleted one none after, and a renamed one keeps both.
+    pub fn diff_file_pairs(diff: &str) -> Vec<(Option<PathBuf>, Option<PathBuf>)> {
+        let python = |path: &str| match path.ends_with(".py") {
+            true => Some(PathBuf::from(path)),
+            false => None,
         };
 
-        if build {
-            module_manager.build()?;
-            module_manager.reload()?;
+        diff.lines()
+            .filter_map(|line| {
+                let mut fields = line.trim().split('\t');
+                let status = fields.next()?;
+                let path = fields.next()?;
+
+                match status.chars().next()? {
+                    'A' => Some((None, python(path))),
+                    'D' => Some((python(path), None)),
+                    'R' => Some((python(path), python(fields.next()?))),
+                    _ => Some((python(path), python(path))),
+                }
+            })
+            .filter(|(before, after)| before.is_some() || after.is_some())
+            .collect()
+    }
+
+    /// The contents of `path` at the git ref `rev`. Errors if git fails, e.g.
+    /// when the ref or the file does not exist.
+    pub fn git_show(rev: &str, path: &Path) -> Result<String, Error> {
+        let output = Command::new("git")
+            .arg("show")
+            .arg(format!("{}:./{}", rev, path.display()))
+            .output()
+            .with_context(|e| format!("Could not run git: {}", e))?;
+
+        if !output.status.success() {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::Other,
+                format!(
+                    "git show {}:{} failed: {}",
+                    rev,
+                    path.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            )));
         }
 
-        Ok(module_manager)
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// The Python `files` of the project listed in `diff`, the output of
+    /// `git diff --name-only` with one path per line.
+    pub fn changed_python_files(
+        diff: &str,
+        files: impl Iterator<Item = PathBuf>,
+    ) -> HashSet<PathBuf> {
+        let changed = diff
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect::<HashSet<PathBuf>>();
+
+        files
+            .map(|file| match file.strip_prefix(".") {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => file.clone(),
+            })
+            .filter(|file| changed.contains(file))
+            .collect()
+    }
+
+    /// Prefixes `module` with `root_package`, unless it already starts with it.
+    pub fn qualified(module: &str, root_package: Option<&str>) -> String {
+        match root_package {
+            Some(root_package)
+                if module != root_package
+                    && !module.starts_with(&format!("{}.", root_package)) =>
+            {
+                format!("{}.{}", root_package, module)
+            }
+            _ => module.to_string(),
+        }
+    }
+
+    /// The name of `module` as typed with the root package set, see
+    /// `ProjectSettings::root_package`: `mypkg.foo` is `foo` with `mypkg`.
+    pub fn unqualified(module: &str, root_package: Option<&str>) -> String {
+        match root_package {
+            Some(root_package) => module
+                .strip_prefix(&format!("{}.", root_package))
+                .unwrap_or(module)
+                .to_string(),
+            None => module.to_string(),
+        }
+    }
+
+    /// See `ProjectSettings::is_namespace_package`, enabled or not by `enabled`.
+    fn namespace_package_at(dir: &Path, enabled: bool) -> bool {
+        if !enabled
+            || !dir.is_dir()
+            || dir.join("__init__.py").exists()
+        {
+            return false;
+        }
+
+        match std::fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).any(|e| {
+                e.path().is_file() && e.path().extension().map_or(false, |ext| ext == "py")
+            }),
+            Err(_) => false,
+        }
+    }
+
+    /// See `ProjectSettings::namespace_packages`, enabled or not by `enabled`.
+    fn namespace_packages_in(dir: &Path, enabled: bool) -> Vec<PathBuf> {
+        let mut packages = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| {
+                    !p.file_name().unwrap().to_string_lossy().starts_with('.')
+                        && Self::namespace_package_at(p, enabled)
+                })
+                .collect::<Vec<PathBuf>>(),
+            Err(_) => Vec::new(),
+        };
+        packages.sort();
+        packages
+    }
+
+    /// The directories under `prefix` that directly contain Python files but
+    /// no `__init__.py`, sorted. Hidden directories and, when enabled,
+    /// namespace packages are left out, as is the project root itself.
+    pub fn missing_init_files(
+        prefix: Option<String>,
+        settings: &ProjectSettings,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let files_iter = Self::travel_root(prefix, None, settings)
+            .with_context(|e| format!("Could not travel root directory: {}", e))?;
+
+        let mut dirs = files_iter
+            .filter_map(|file| file.parent().map(|dir| dir.to_path_buf()))
+            .filter(|dir| {
+                let hidden = dir.components().any(|c| match c {
+                    Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+                    _ => false,
+                });
+
+                !hidden
+                    && dir.components().any(|c| matches!(c, Component::Normal(_)))
+                    && !dir.join("__init__.py").exists()
+                    && !settings.is_namespace_package(dir)
+            })
+            .collect::<Vec<_>>();
+
+        dirs.sort();
+        dirs.dedup();
+        Ok(dirs)
     }
 
+    /// Creates an empty `__init__.py` in each of `dirs`.
+    pub fn create_init_files(dirs: &[PathBuf]) -> Result<(), Error> {
+        for dir in dirs {
+            let path = dir.join("__init__.py");
+            File::create(&path)
+                .with_context(|e| format!("Could not create file {}: {}", path.display(), e))?;
+        }
+
+        Ok(())
+    }
+
+    /// The paths of the Python files under `prefix`, see
+    /// `ProjectSettings::walker`.
     pub fn travel_root(
         prefix: Option<String>,
         max_dept: Option<usize>,
+        settings: &ProjectSettings,
     ) -> Result<impl Iterator<Item = PathBuf>, Error> {
-        let prefix = match prefix {
-            Some(prefix) => "./".to_owned() + &prefix,
-            None => String::from("./"),
+        Ok(settings
+            .walker()
+            .root(prefix)
+            .max_depth(max_dept)
+            .walk()
+            .map(|entry| entry.path))
+    }
+
+    /// The type of the module defined by the Python file at `path`, and
+    /// whether it sits directly under the project root: `name/__init__.py`,
+    /// or its `.pyi` stub, is a package, any other file a file module.
+    pub fn classify_path(path: &Path) -> (ModuleType, bool) {
+        let components = path
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .collect::<Vec<_>>();
+
+        let module_type = match components.last() {
+            Some(Component::Normal(file)) if *file == "__init__.py" || *file == "__init__.pyi" => {
+                ModuleType::Directory
+            }
+            _ => ModuleType::File,
+        };
+
+        let is_top_level = match module_type {
+            ModuleType::File => components.len() == 1,
+            ModuleType::Directory => components.len() == 2,
+        };
+
+        (module_type, is_top_level)
+    }
+
+    /// The type of the module at `path` if it sits directly under the project
+    /// root: `name.py` is a file module and `name/__init__.py` a package.
+    pub fn top_level_type(path: &Path) -> Option<ModuleType> {
+        match Self::classify_path(path) {
+            (module_type, true) => Some(module_type),
+            _ => None,
+        }
+    }
+
+    /// Applies `(byte range, replacement)` edits to a file's contents.
+    /// Edits are applied right to left so earlier offsets stay valid, and
+    /// everything outside the edited ranges is kept byte for byte. Line
+    /// breaks in the replacements follow the file's (CRLF or LF), and the
+    /// file keeps ending with a newline only if it did before.
+    pub fn apply_edits(
+        contents: &str,
+        mut edits: Vec<(Range<usize>, String)>,
+    ) -> Result<String, Error> {
+        edits.sort_by(|a, b| b.0.start.cmp(&a.0.start).then(b.0.end.cmp(&a.0.end)));
+
+        let line_ending = match contents.contains("\r\n") {
+            true => "\r\n",
+            false => "\n",
         };
 
-        let mut iter = WalkDir::new(prefix).follow_links(true);
+        let mut result = contents.to_string();
+        let mut last_start = contents.len();
+        for (range, replacement) in edits {
+            if range.start > range.end
+                || range.end > last_start
+                || !contents.is_char_boundary(range.start)
+                || !contents.is_char_boundary(range.end)
+            {
+                return Result::Err(Error::from(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Invalid or overlapping edit at {:?}", range),
+                )));
+            }
+
+            last_start = range.start;
+            let replacement = replacement.replace("\r\n", "\n").replace("\n", line_ending);
+            result.replace_range(range, &replacement);
+        }
 
-        if max_dept.is_some() {
-            iter = iter.max_depth(max_dept.unwrap());
+        match (contents.ends_with('\n'), result.ends_with('\n')) {
+            (true, false) if !result.is_empty() => result.push_str(line_ending),
+            (false, true) => {
+                let end = result.trim_end_matches('\n').trim_end_matches('\r').len();
+                let end = end.max(result.len() - line_ending.len());
+                result.truncate(end);
+            }
+            _ => {}
         }
 
-        let iter = iter
+        Ok(result)
+    }
+
+    /// The ranges of the `module` group of every match of `pattern`.
+    fn module_tokens(contents: &str, pattern: &str) -> Result<Vec<Range<usize>>, Error> {
+        let pattern =
+            Regex::new(pattern).with_context(|e| format!("Could not create regex: {}", e))?;
+
+        Ok(pattern
+            .captures_iter(contents)
+            .filter_map(|caps| caps.name("module"))
+            .map(|m| m.range())
+            .collect())
+    }
+
+    /// Rewrites the imports of `old` into `new` in a file's contents.
+    /// Only whole module names are replaced, never inside string literals or
+    /// comments, and the rest of the file is untouched: the edits only cover
+    /// the module names, so blank lines, indentation and spacing survive even
+    /// when the new name is longer or shorter.
+    fn rewrite_imports(contents: &str, old: &str, new: &str) -> Result<String, Error> {
+        let edits = Self::module_references(contents, old)?
             .into_iter()
-            .filter(|e| {
-                let e = e.as_ref().unwrap();
-                e.file_type().is_file()
-                    && match e.path().extension() {
-                        Some(extension) => extension == "py",
-                        None => false,
-                    }
+            .map(|r| (r, new.to_string()))
+            .collect();
+
+        Self::apply_edits(contents, edits)
+    }
+
+    /// The ranges of the module name `old` in the imports and attribute uses
+    /// of a file's contents, the names `rewrite_imports` replaces.
+    fn module_references(contents: &str, old: &str) -> Result<Vec<Range<usize>>, Error> {
+        let shadowed = Self::is_rebound(contents, old.split(".").next().unwrap())?;
+        let old = regex::escape(old);
+        let mut patterns = vec![
+            // Handle normal import: import old -> new
+            format!(r"\bimport\s+(?P<module>{})(?:[^\w]|$)", old),
+            // Handle from import: from old import -> from new import
+            format!(r"\bfrom\s+(?P<module>{})(?:\.\w+)*\s+import\b", old),
+        ];
+        // Handle module mapping: old. -> new. Skipped when the name refers
+        // to something else in this file, e.g. `import other as old`.
+        if !shadowed {
+            patterns.push(format!(r"(?:^|[^\w.])(?P<module>{})\.", old));
+        }
+
+        // Every pass matches the original contents, so a new name containing
+        // the old one is never rewritten twice.
+        let mut ranges = Vec::new();
+        for pattern in &patterns {
+            ranges.extend(Self::module_tokens(contents, pattern)?);
+        }
+        ranges.sort_by_key(|r| r.start);
+        ranges.dedup();
+
+        let (strings, comments) = literal_ranges(contents);
+        let ranges = ranges
+            .into_iter()
+            .filter(|r| {
+                !strings
+                    .iter()
+                    .chain(comments.iter())
+                    .any(|l| l.contains(&r.start))
             })
-            .map(|e| e.unwrap().into_path());
+            .collect();
 
-        Ok(iter)
+        Ok(ranges)
     }
 
-    fn replace_in_root(old: &str, new: &str) -> Result<(), Error> {
-        let files_iter = Self::travel_root(None, None)
+    /// The references to the module `old` left in the project, as the file,
+    /// line number and trimmed line of each. These are the lines
+    /// `replace_in_root` would rewrite, so an empty result means a move of
+    /// `old` is complete.
+    pub fn stale_references(
+        old: &str,
+        settings: &ProjectSettings,
+    ) -> Result<Vec<(PathBuf, usize, String)>, Error> {
+        let files_iter = Self::travel_root(None, None, settings)
             .with_context(|e| format!("Could not travel root directory: {}", e))?;
 
+        let mut references = Vec::new();
         for file in files_iter {
-            debug!("Replacing in {}", file.display());
-            let mut contents = Self::read_file(&file)
+            let contents = Self::read_file(&file)
                 .with_context(|e| format!("Could not read file {}: {}", file.display(), e))?;
 
-            // Handle normal import: import old -> new
-            let pattern = Regex::new(&format!(r"import\s+{}((\.((\w|_)+(\d|\w|_)*))+|\s+)", old))
-                .with_context(|e| format!("Could not create regex: {}", e))?;
+            let mut lines = Self::module_references(&contents, old)?
+                .into_iter()
+                .map(|r| line_of(&contents, r.start))
+                .collect::<Vec<usize>>();
+            lines.dedup();
 
-            contents = pattern
-                .replace_all(&contents, |caps: &regex::Captures| {
-                    let mut replacement = String::from("import ");
-                    replacement.push_str(new);
-
-                    let after = caps.get(1);
-                    match after {
-                        Some(after) => {
-                            replacement.push_str(after.as_str());
-                        }
-                        None => {}
-                    }
+            for line in lines {
+                let text = contents.lines().nth(line - 1).unwrap_or_default().trim();
+                references.push((file.clone(), line, text.to_string()));
+            }
+        }
 
-                    replacement
-                })
-                .to_string();
+        Ok(references)
+    }
 
-            // Handle from import: from old import -> from new import
-            let pattern = Regex::new(&format!(r"from\s+{}(\.((\w|_)+(\d|\w|_)*))*\s+import", old))
+    /// Is `name` bound to something else than the module of that name, by
+    /// `import x as name`, `from x import name` or `with ... as name`?
+    /// An alias of the module itself (`import old as o`) binds `o`, whose
+    /// uses need no change.
+    fn is_rebound(contents: &str, name: &str) -> Result<bool, Error> {
+        let name = regex::escape(name);
+        let bindings = [
+            format!(r"\bas\s+{}\b", name),
+            format!(r"(?m)^\s*from\s+[\w.]+\s+import\s+[^#\n]*\b{}\b", name),
+        ];
+
+        let (strings, comments) = literal_ranges(contents);
+        for binding in &bindings {
+            let pattern = Regex::new(binding)
                 .with_context(|e| format!("Could not create regex: {}", e))?;
 
-            contents = pattern
-                .replace_all(&contents, |caps: &regex::Captures| {
-                    let mut replacement = String::from("from ");
-                    replacement.push_str(new);
-
-                    let after = caps.get(1);
-                    match after {
-                        Some(after) => {
-                            replacement.push_str(after.as_str());
-                        }
-                        None => {}
-                    }
+            let rebound = pattern.find_iter(contents).any(|m| {
+                !strings
+                    .iter()
+                    .chain(comments.iter())
+                    .any(|l| l.contains(&m.start()))
+            });
+            if rebound {
+                return Ok(true);
+            }
+        }
 
-                    replacement.push_str(" import");
-                    replacement
-                })
-                .to_string();
+        Ok(false)
+    }
 
-            // Handle module mapping: old. -> new.
-            let pattern = Regex::new(&format!(r"{}\.", old))
-                .with_context(|e| format!("Could not create regex: {}", e))?;
+    /// Lists the files whose imports would change when `old` is renamed to `new`,
+    /// without writing anything. With a `scope`, only the files under that
+    /// directory are considered.
+    pub fn files_to_rewrite(
+        old: &str,
+        new: &str,
+        scope: Option<&str>,
+        settings: &ProjectSettings,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let files_iter = Self::travel_root(scope.map(String::from), None, settings)
+            .with_context(|e| format!("Could not travel root directory: {}", e))?;
+
+        let mut files = Vec::new();
+        for file in files_iter {
+            let contents = Self::read_file(&file)
+                .with_context(|e| format!("Could not read file {}: {}", file.display(), e))?;
+
+            if Self::rewrite_imports(&contents, old, new)? != contents {
+                files.push(file);
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Rewrites the imports of `old` into `new` in every file of the project,
+    /// or only under the `scope` directory, whether or not `old` is a local
+    /// module. Returns the files that changed.
+    pub fn replace_in_root(
+        old: &str,
+        new: &str,
+        scope: Option<&str>,
+        settings: &ProjectSettings,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let mut files = Vec::new();
+        for entry in settings.walker().root(scope.map(String::from)).walk() {
+            let file = entry.path;
+            debug!("Replacing in {}", file.display());
+            let contents = Self::read_file(&file)
+                .with_context(|e| format!("Could not read file {}: {}", file.display(), e))?;
 
-            contents = pattern
-                .replace_all(&contents, format!("{}.", new).as_str())
-                .to_string();
+            let new_contents = Self::rewrite_imports(&contents, old, new)?;
+            if new_contents == contents {
+                continue;
+            }
 
-            std::fs::write(&file, contents)
+            std::fs::write(&file, new_contents)
                 .with_context(|e| format!("Could not write to file {}: {}", file.display(), e))?;
+            files.push(file);
         }
 
-        Ok(())
+        Ok(files)
+    }
+
+    /// The directories and file that `make_tree` would create for `path`,
+    /// outermost first. Paths that already exist are left out.
+    fn tree_to_make(path: &Path) -> Vec<PathBuf> {
+        let mut paths = path
+            .ancestors()
+            .filter(|p| !p.as_os_str().is_empty() && !p.exists())
+            .map(|p| p.to_path_buf())
+            .collect::<Vec<PathBuf>>();
+        paths.reverse();
+        paths
+    }
+
+    /// The paths `build` would create for this module and its sub modules,
+    /// without touching the filesystem.
+    pub fn planned_paths(self: &Self) -> Vec<PathBuf> {
+        let mut paths = Self::tree_to_make(&self.path);
+        for sub_module in &self.sub_modules {
+            for path in sub_module.planned_paths() {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+
+        paths
     }
 
     fn make_tree(path: &Path) -> Result<(), Error> {
@@ -208,7 +1062,17 @@ impl ModuleManager {
         Ok(())
     }
 
-    pub fn path_2_module(path: &str) -> Result<String, Error> {
+    /// The name of the module at `path` as typed on the command line, without
+    /// the root package if one is set. See `path_2_full_module` for the name
+    /// it is imported by.
+    pub fn path_2_module(path: &str, settings: &ProjectSettings) -> Result<String, Error> {
+        let module = Self::path_2_full_module(path)?;
+        Ok(Self::unqualified(&module, settings.root_package.as_deref()))
+    }
+
+    /// The dotted name the module at `path` is imported by, relative to the
+    /// project root.
+    pub fn path_2_full_module(path: &str) -> Result<String, Error> {
         let path = PathBuf::from(path);
         let mut module = String::new();
 
@@ -219,13 +1083,16 @@ impl ModuleManager {
                 continue;
             }
 
-            if component == "__init__.py" {
+            if component == "__init__.py" || component == "__init__.pyi" {
                 module.pop();
                 return Ok(module);
             }
 
-            if component.ends_with(".py") {
-                module.push_str(&component[..component.len() - 3]);
+            if let Some(name) = component
+                .strip_suffix(".py")
+                .or_else(|| component.strip_suffix(".pyi"))
+            {
+                module.push_str(name);
                 return Ok(module);
             }
 
@@ -239,7 +1106,23 @@ impl ModuleManager {
         )));
     }
 
-    pub fn module_2_path(module: &str, module_type: &ModuleType) -> Result<PathBuf, Error> {
+    /// The path of the file defining `module`, qualified with the root
+    /// package if one is set. A name with an empty dotted segment, as in
+    /// `a..b`, `.a` or `a.`, is rejected rather than turned into a
+    /// malformed path.
+    pub fn module_2_path(
+        module: &str,
+        module_type: &ModuleType,
+        settings: &ProjectSettings,
+    ) -> Result<PathBuf, Error> {
+        let module = &settings.qualify(module);
+        if module.split(".").any(|segment| segment.is_empty()) {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid module name {:?}: empty dotted segment", module),
+            )));
+        }
+
         let mut path = String::new();
 
         for component in module.split(".") {
@@ -254,40 +1137,161 @@ impl ModuleManager {
             path.push_str(".py");
         }
 
-        Ok(PathBuf::from(path))
-    }
+        // With --stubs, a module only described by a stub is read from it.
+        let path = PathBuf::from(path);
+        let stub = path.with_extension("pyi");
+        if settings.stubs && !path.exists() && stub.exists() {
+            return Ok(stub);
+        }
 
-    fn read_file(path: &Path) -> Result<String, Error> {
-        let mut file = File::open(path)
-            .with_context(|e| format!("Could not open file {}: {}", path.display(), e))?;
+        Ok(path)
+    }
 
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .with_context(|e| format!("Could not read file {}: {}", path.display(), e))?;
+    /// Is every dotted part of `module` a Python identifier that is not a
+    /// keyword, so the module can be imported?
+    pub fn is_valid_module_name(module: &str) -> bool {
+        const KEYWORDS: [&str; 35] = [
+            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+            "continue", "def", "del", "elif", "else", "except", "finally", "for", "from",
+            "global", "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass",
+            "raise", "return", "try", "while", "with", "yield",
+        ];
 
-        Ok(contents)
+        module.split(".").all(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => {
+                    (first.is_alphabetic() || first == '_')
+                        && chars.all(|c| c.is_alphanumeric() || c == '_')
+                        && !KEYWORDS.contains(&part)
+                }
+                None => false,
+            }
+        })
     }
 
-    fn get_sub_modules(self: &mut Self) -> Result<Vec<ModuleManager>, Error> {
+    /// Reads a module tree from an indented spec, one module per line:
+    /// packages end with `/` (or `:`), files are plain names, and children are
+    /// indented under their package. `#` starts a comment.
+    ///
+    /// ```text
+    /// app/
+    ///     models/
+    ///         user
+    ///     views
+    /// ```
+    ///
+    /// Returns the dotted modules, each package before its children.
+    pub fn parse_spec(contents: &str) -> Result<Vec<(String, ModuleType)>, Error> {
+        let mut modules = Vec::new();
+        // The enclosing (indent, module, type) of the current line.
+        let mut parents: Vec<(usize, String, ModuleType)> = Vec::new();
+
+        for (number, line) in contents.lines().enumerate() {
+            let content = line.split('#').next().unwrap().trim_end();
+            let name = content.trim_start().trim_start_matches("- ").trim();
+            if name.is_empty() {
+                continue;
+            }
+
+            let indent = content.len() - content.trim_start().len();
+            while parents.last().map_or(false, |(i, _, _)| *i >= indent) {
+                parents.pop();
+            }
+
+            let (name, module_type) = match name.strip_suffix('/').or(name.strip_suffix(':')) {
+                Some(name) => (name.trim(), ModuleType::Directory),
+                None => (name, ModuleType::File),
+            };
+
+            let module = match parents.last() {
+                Some((_, parent, ModuleType::Directory)) => format!("{}.{}", parent, name),
+                Some((_, parent, ModuleType::File)) => {
+                    return Result::Err(Error::from(std::io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "line {}: {} is a file and cannot contain {}",
+                            number + 1,
+                            parent,
+                            name
+                        ),
+                    )));
+                }
+                None => name.to_string(),
+            };
+
+            if !Self::is_valid_module_name(&module) {
+                return Result::Err(Error::from(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("line {}: {} is not a valid module name", number + 1, module),
+                )));
+            }
+
+            parents.push((indent, module.clone(), module_type.clone()));
+            modules.push((module, module_type));
+        }
+
+        Ok(modules)
+    }
+
+    /// Creates the modules of a spec, see `parse_spec`. Returns the paths
+    /// created and the paths that already existed.
+    pub fn create_tree(
+        modules: &[(String, ModuleType)],
+        settings: &ProjectSettings,
+    ) -> Result<(Vec<PathBuf>, Vec<PathBuf>), Error> {
+        let mut created = Vec::new();
+        let mut existing = Vec::new();
+
+        for (module, module_type) in modules {
+            let module_manager =
+                Self::new_with_settings(module, module_type.clone(), false, settings)?;
+
+            if module_manager.path.exists() {
+                existing.push(module_manager.path);
+                continue;
+            }
+
+            module_manager
+                .build()
+                .with_context(|e| format!("Could not create module {}: {}", module, e))?;
+            created.push(module_manager.path);
+        }
+
+        Ok((created, existing))
+    }
+
+    fn read_file(path: &Path) -> Result<String, Error> {
+        profile::time(Phase::Read, || {
+            let mut file = File::open(path)
+                .with_context(|e| format!("Could not open file {}: {}", path.display(), e))?;
+
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)
+                .with_context(|e| format!("Could not read file {}: {}", path.display(), e))?;
+
+            Ok(contents)
+        })
+    }
+
+    /// Lists the direct sub modules of a package, sorted by name.
+    fn sub_module_names(self: &Self) -> Result<Vec<(String, ModuleType)>, Error> {
         if self.module_type == ModuleType::File {
             return Ok(Vec::new());
         }
 
         let mut sub_modules = Vec::new();
 
-        let files_iter = Self::travel_root(
-            Some(self.path.parent().unwrap().to_str().unwrap().to_string()),
-            Some(2),
-        )
-        .with_context(|e| format!("Could not travel root directory: {}", e))?;
+        let entries = self
+            .settings
+            .walker()
+            .root(Some(self.path.parent().unwrap().to_str().unwrap().to_string()))
+            .max_depth(Some(2))
+            .walk();
 
         let accepted_root = self.path.parent().unwrap();
-        for file in files_iter {
-            let module_type = if file.ends_with("__init__.py") {
-                ModuleType::Directory
-            } else {
-                ModuleType::File
-            };
+        for entry in entries {
+            let (file, module_type) = (entry.path, entry.module_type);
 
             if module_type == ModuleType::File
                 && file.strip_prefix("./").unwrap().parent().unwrap() != accepted_root
@@ -295,12 +1299,10 @@ impl ModuleManager {
                 continue;
             }
 
-            match Self::path_2_module(file.to_str().unwrap()) {
+            match Self::path_2_full_module(file.to_str().unwrap()) {
                 Ok(module) => {
-                    if module.starts_with(&self.module) && module != self.module {
-                        let mut sub_module_manager = Self::new(&module, module_type, false)?;
-                        sub_module_manager.reload()?;
-                        sub_modules.push(sub_module_manager);
+                    if Self::is_sub_module(&module, &self.module) {
+                        sub_modules.push((module, module_type));
                     }
                 }
                 Err(e) => {
@@ -309,40 +1311,401 @@ impl ModuleManager {
             }
         }
 
+        for package in self.settings.namespace_packages(accepted_root) {
+            let name = package.file_name().unwrap().to_string_lossy();
+            sub_modules.push((format!("{}.{}", self.module, name), ModuleType::Directory));
+        }
+
+        sub_modules.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(sub_modules)
+    }
+
+    /// Is `module` below `parent`, by at least one non-empty dotted segment?
+    fn is_sub_module(module: &str, parent: &str) -> bool {
+        match module.strip_prefix(parent).and_then(|rest| rest.strip_prefix('.')) {
+            Some(rest) => !rest.is_empty() && !rest.split('.').any(|s| s.is_empty()),
+            None => false,
+        }
+    }
+
+    fn get_sub_modules(self: &mut Self) -> Result<Vec<ModuleManager>, Error> {
+        let mut sub_modules = Vec::new();
+        for (module, module_type) in self.sub_module_names()? {
+            // Every level must be deeper than its parent, or a degenerate
+            // path would load the same module again forever.
+            if !Self::is_sub_module(&module, &self.module) {
+                debug!("Skipping {} as a sub module of {}", module, self.module);
+                continue;
+            }
+
+            let mut sub_module_manager =
+                Self::new_with_settings(&module, module_type, false, &self.settings)?
+                    .with_no_cache(self.no_cache);
+            sub_module_manager.reload()?;
+            sub_modules.push(sub_module_manager);
+        }
+
         Ok(sub_modules)
     }
 
+    /// The modification time of the module file, if it can be read.
+    fn modified_time(self: &Self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Like `reload`, but only re-parses the files modified since they were
+    /// last parsed, reusing the loaded definitions of the others. New sub
+    /// modules are loaded and deleted ones dropped. With `with_no_cache`,
+    /// every file is parsed again.
+    /// Returns the modules that were parsed again.
+    pub fn refresh(self: &mut Self) -> Result<Vec<String>, Error> {
+        let mut parsed = Vec::new();
+
+        if self.no_cache || self.parsed_at.is_none() || self.modified_time() != self.parsed_at {
+            self.parse()?;
+            parsed.push(self.module.clone());
+        }
+
+        let mut previous = std::mem::take(&mut self.sub_modules);
+        for (module, module_type) in self.sub_module_names()? {
+            let position = previous
+                .iter()
+                .position(|m| m.module == module && m.module_type == module_type);
+
+            let sub_module_manager = match position {
+                Some(position) => {
+                    let mut sub_module_manager = previous.swap_remove(position);
+                    parsed.extend(sub_module_manager.refresh()?);
+                    sub_module_manager
+                }
+                None => {
+                    let mut sub_module_manager =
+                        Self::new_with_settings(&module, module_type, false, &self.settings)?
+                            .with_no_cache(self.no_cache);
+                    sub_module_manager.reload()?;
+                    parsed.push(module);
+                    sub_module_manager
+                }
+            };
+            self.sub_modules.push(sub_module_manager);
+        }
+
+        Ok(parsed)
+    }
+
     pub fn build(self: &Self) -> Result<(), Error> {
         Self::make_tree(&self.path)
             .with_context(|e| format!("Could not make tree for {}: {}", self.path.display(), e))?;
         Ok(())
     }
 
-    pub fn reload(self: &mut Self) -> Result<(), Error> {
-        let (ast, original_code) = parse_ast(&self.path, None).with_context(|e| {
-            format!(
-                "Could not parse file {}: {}",
-                self.path.display(),
-                e.to_string()
-            )
-        })?;
-        let (classes, functions, vars) = parse_root_ast(
+    /// Creates `test_<name>.py` in `tests_dir`, importing this module and
+    /// holding a placeholder test. An existing test file is kept.
+    /// Returns the created file, if any.
+    pub fn create_test_file(self: &Self, tests_dir: &Path) -> Result<Option<PathBuf>, Error> {
+        let (parent, name) = match self.module.rsplit_once('.') {
+            Some((parent, name)) => (Some(parent), name),
+            None => (None, self.module.as_str()),
+        };
+
+        let path = tests_dir.join(format!("test_{}.py", name));
+        if path.exists() {
+            return Ok(None);
+        }
+
+        let import = match parent {
+            Some(parent) => format!("from {} import {}", parent, name),
+            None => format!("import {}", name),
+        };
+        let contents = format!(
+            "{}\n\n\ndef test_{}():\n    assert {} is not None\n",
+            import, name, name
+        );
+
+        create_dir_all(tests_dir)
+            .with_context(|e| format!("Could not create {}: {}", tests_dir.display(), e))?;
+        std::fs::write(&path, contents)
+            .with_context(|e| format!("Could not write to file {}: {}", path.display(), e))?;
+        Ok(Some(path))
+    }
+
+    /// Checks that `template_dir` can be applied to this module: it must be
+    /// a package and the directory must exist.
+    pub fn check_template(self: &Self, template_dir: &Path) -> Result<(), Error> {
+        if self.module_type == ModuleType::File {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "Templates can only be applied to packages",
+            )));
+        }
+
+        if !template_dir.is_dir() {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("Template directory {} not found", template_dir.display()),
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Copies the files of `template_dir` into this package, replacing the
+    /// `{module}` tokens of their contents with the module name, and of their
+    /// names with the module name using `_` instead of dots. Existing files
+    /// are kept, except the package's own `__init__.py` with `force`.
+    /// Returns the created or overwritten files and the skipped ones.
+    pub fn apply_template(
+        self: &Self,
+        template_dir: &Path,
+        force: bool,
+    ) -> Result<(Vec<PathBuf>, Vec<PathBuf>), Error> {
+        self.check_template(template_dir)?;
+
+        let package_dir = self.path.parent().unwrap();
+        let file_token = self.module.replace(".", "_");
+        let mut created = Vec::new();
+        let mut skipped = Vec::new();
+
+        for template in Self::package_files(template_dir) {
+            let relative = template.strip_prefix(template_dir).unwrap();
+            let destination = package_dir.join(
+                relative
+                    .to_string_lossy()
+                    .replace("{module}", &file_token),
+            );
+
+            // The other files of the package are never overwritten.
+            let overwrite = force && destination == self.path;
+            if destination.exists() && !overwrite {
+                skipped.push(destination);
+                continue;
+            }
+
+            create_dir_all(destination.parent().unwrap()).with_context(|e| {
+                format!("Could not create directory {}: {}", destination.display(), e)
+            })?;
+            let mut copy_options = FileCopyOptions::new();
+            copy_options.overwrite = overwrite;
+            fs_extra::file::copy(&template, &destination, &copy_options)
+                .with_context(|e| format!("Could not copy {}: {}", template.display(), e))?;
+
+            // Binary files such as images are copied as they are.
+            if let Ok(contents) = std::fs::read_to_string(&destination) {
+                if contents.contains("{module}") {
+                    std::fs::write(&destination, contents.replace("{module}", &self.module))
+                        .with_context(|e| {
+                            format!("Could not write file {}: {}", destination.display(), e)
+                        })?;
+                }
+            }
+
+            created.push(destination);
+        }
+
+        Ok((created, skipped))
+    }
+
+    /// Empties the module's own file, `name.py` or the package's
+    /// `__init__.py`, leaving the other files of a package untouched.
+    /// Returns whether there was a file to empty.
+    pub fn reset(self: &Self) -> Result<bool, Error> {
+        if !self.path.is_file() {
+            return Ok(false);
+        }
+
+        File::create(&self.path)
+            .with_context(|e| format!("Could not reset file {}: {}", self.path.display(), e))?;
+        Ok(true)
+    }
+
+    /// Parses this module's own file, leaving its sub modules untouched.
+    pub fn parse(self: &mut Self) -> Result<(), Error> {
+        // A namespace package has no `__init__.py`, so nothing of its own to parse.
+        let package_dir = self.path.parent().unwrap();
+        if self.module_type == ModuleType::Directory
+            && !self.path.exists()
+            && self.settings.is_namespace_package(package_dir)
+        {
+            return Ok(());
+        }
+
+        if !self.settings.is_selected(&self.path) {
+            return Ok(());
+        }
+
+        let started = Instant::now();
+        let parsed_at = self.modified_time();
+        let original_code = Self::read_file(&self.path)?;
+
+        profile::time(Phase::Parse, || self.set_parsed(original_code))?;
+        self.parsed_at = parsed_at;
+        Self::record_parse_time(&self.path, started.elapsed(), log_enabled!(Level::Debug));
+
+        Ok(())
+    }
+
+    /// Logs the parse time of a file at debug level, shown with `--verbose`
+    /// twice, and keeps it for `slowest_parses` when `keep` is set, only at
+    /// that level so the times do not pile up otherwise.
+    fn record_parse_time(path: &Path, elapsed: Duration, keep: bool) {
+        if !keep {
+            return;
+        }
+
+        debug!("Parsed {} in {:.2}ms", path.display(), elapsed.as_secs_f64() * 1000.0);
+        PARSE_TIMES
+            .write()
+            .unwrap()
+            .push((path.to_path_buf(), elapsed));
+    }
+
+    /// The `count` files that took the longest to parse so far, slowest
+    /// first.
+    pub fn slowest_parses(count: usize) -> Vec<(PathBuf, Duration)> {
+        let mut times = PARSE_TIMES.read().unwrap().clone();
+        times.sort_by(|a, b| b.1.cmp(&a.1));
+        times.truncate(count);
+        times
+    }
+
+    /// Logs the `count` slowest files to parse at debug level.
+    pub fn log_slowest_parses(count: usize) {
+        let slowest = Self::slowest_parses(count);
+        if slowest.is_empty() {
+            return;
+        }
+
+        debug!("Slowest files to parse:");
+        for (path, elapsed) in slowest {
+            debug!("  {} ({:.2}ms)", path.display(), elapsed.as_secs_f64() * 1000.0);
+        }
+    }
+
+    /// Parses `original_code` and stores its definitions. A syntax error is
+    /// kept in `parse_error` and leaves the module without definitions.
+    fn set_parsed(self: &mut Self, original_code: String) -> Result<(), Error> {
+        let ast = match try_parse_contents(&original_code, &self.path.display().to_string()) {
+            Ok(ast) => {
+                self.parse_error = None;
+                ast
+            }
+            Err(e) => {
+                debug!("{}", e);
+                self.parse_error = Some(e.to_string());
+                Vec::new()
+            }
+        };
+
+        let imports = parse_imports(&ast, &original_code, &self.path.to_str().unwrap().to_string());
+        let comments = parse_comments(&original_code, &self.path.to_str().unwrap().to_string());
+        let deletions = parse_deletions(&ast, &original_code);
+        let (conditional_classes, conditional_functions) =
+            parse_conditional_defs(&ast, &original_code, &self.path.to_str().unwrap().to_string())
+                .with_context(|e| format!("Could not parse conditional definitions: {}", e))?;
+        let (classes, functions, vars, type_aliases) = parse_root_ast(
             ast,
             &original_code,
             &self.path.to_str().unwrap().to_string(),
         )
         .with_context(|e| format!("Could not parse root ast: {}", e))?;
 
+        // A name deleted after its definition is no longer available.
+        let deleted = |name: &str, line: usize| {
+            deletions.iter().any(|(deleted, at)| deleted == name && *at > line)
+        };
+
         self.classes = classes;
         self.functions = functions;
         self.vars = vars;
+        self.conditional_classes = conditional_classes;
+        self.conditional_functions = conditional_functions;
+        self.classes.retain(|c| !deleted(&c.name, c.line));
+        self.functions.retain(|f| !deleted(&f.name, f.line));
+        self.vars.retain(|v| !deleted(&v.name, v.line));
+        self.conditional_classes.retain(|c| !deleted(&c.name, c.line));
+        self.conditional_functions.retain(|f| !deleted(&f.name, f.line));
+        self.imports = imports;
+        self.type_aliases = type_aliases;
+        self.comments = comments;
+        self.source = original_code;
+
+        Ok(())
+    }
+
+    /// Is `module` a file path such as `scripts/run.py` or `stubs/api.pyi`,
+    /// or `-` for stdin, rather than a dotted module name?
+    pub fn is_path(module: &str) -> bool {
+        module == "-"
+            || module.contains('/')
+            || module.ends_with(".py")
+            || module.ends_with(".pyi")
+    }
+
+    /// Parses `contents` as the file at `path`, e.g. its contents at another
+    /// git ref, without reading the file itself.
+    pub fn from_source(path: &Path, contents: String) -> Result<Self, Error> {
+        let module = Self::path_2_full_module(path.to_str().unwrap())
+            .with_context(|e| format!("Could not convert {} to module: {}", path.display(), e))?;
+        let module_type = Self::classify_path(path).0;
+
+        let mut module_manager = Self::at_path(path.to_path_buf(), &module, module_type);
+        module_manager.set_parsed(contents)?;
+        Ok(module_manager)
+    }
+
+    /// Parses a single file, or Python source from stdin for `-`, without
+    /// resolving a dotted module name. The module is named after the file.
+    pub fn from_path(path: &str, settings: &ProjectSettings) -> Result<Self, Error> {
+        let mut module_manager = Self::at_path(PathBuf::from(path), "", ModuleType::File)
+            .with_settings(settings.clone());
+
+        if path == "-" {
+            let mut contents = String::new();
+            std::io::stdin()
+                .read_to_string(&mut contents)
+                .with_context(|e| format!("Could not read stdin: {}", e))?;
+
+            module_manager.module = String::from("<stdin>");
+            module_manager.set_parsed(contents)?;
+            return Ok(module_manager);
+        }
+
+        if !module_manager.path.is_file() {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} is not a file", path),
+            )));
+        }
+
+        module_manager.module = match module_manager.path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => path.to_string(),
+        };
+        module_manager.parse()?;
+
+        Ok(module_manager)
+    }
+
+    pub fn reload(self: &mut Self) -> Result<(), Error> {
+        self.parse()?;
         self.sub_modules = self.get_sub_modules()?;
 
         Ok(())
     }
 
-    pub fn mv(self: &mut Self, to: &str) -> Result<(), Error> {
-        let new_path = Self::module_2_path(to, &self.module_type)?;
+    /// Moves the module to `to` and rewrites the imports of the project, or
+    /// only those under the `scope` directory, which must contain the module.
+    pub fn mv(self: &mut Self, to: &str, scope: Option<&str>) -> Result<(), Error> {
+        self.check_scope(scope)?;
+
+        let new_path = Self::module_2_path(to, &self.module_type, &self.settings)?;
+        if new_path.exists() {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already exists", new_path.display()),
+            )));
+        }
+
         Self::make_tree(&new_path)?;
 
         if self.module_type == ModuleType::Directory {
@@ -362,7 +1725,7 @@ impl ModuleManager {
             })?;
         }
 
-        Self::replace_in_root(&self.module, to)
+        Self::replace_in_root(&self.module, to, scope, &self.settings)
             .with_context(|e| format!("Could not replace in root directory: {}", e))?;
 
         self.path = new_path;
@@ -371,193 +1734,4153 @@ impl ModuleManager {
         Ok(())
     }
 
-    pub fn add_sub_module(
+    /// Moves the module to `to` as a module of `module_type`: a file module
+    /// becomes the `__init__.py` of a new package, and a package holding
+    /// nothing but its `__init__.py` collapses into a file. The package of
+    /// the moved code changes, so its relative imports are made absolute.
+    /// Falls back to `mv` when the type does not change.
+    pub fn mv_as(
         self: &mut Self,
-        sub_module: &str,
+        to: &str,
         module_type: ModuleType,
-        build: bool,
+        scope: Option<&str>,
     ) -> Result<(), Error> {
-        if self.module_type == ModuleType::File {
+        if module_type == self.module_type {
+            return self.mv(to, scope);
+        }
+
+        self.check_scope(scope)?;
+
+        let old_dir = self.path.parent().unwrap().to_path_buf();
+        if self.module_type == ModuleType::Directory {
+            let others = std::fs::read_dir(&old_dir)
+                .with_context(|e| format!("Could not read directory {}: {}", old_dir.display(), e))?
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_name() != "__init__.py" && e.file_name() != "__pycache__")
+                .count();
+
+            if others > 0 {
+                return Result::Err(Error::from(std::io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!(
+                        "{} holds {} entries besides its __init__.py, it cannot become a file",
+                        old_dir.display(),
+                        others
+                    ),
+                )));
+            }
+        }
+
+        let new_path = Self::module_2_path(to, &module_type, &self.settings)?;
+        // A new package needs a directory of its own.
+        let taken = match module_type {
+            ModuleType::Directory => new_path.parent().unwrap(),
+            ModuleType::File => new_path.as_path(),
+        };
+        if taken.exists() {
             return Result::Err(Error::from(std::io::Error::new(
-                ErrorKind::Unsupported,
-                "Files cannot contain other modules",
+                ErrorKind::AlreadyExists,
+                format!("{} already exists", taken.display()),
             )));
         }
 
-        let sub_module_manager = Self::new(
-            format!("{}.{}", &self.module, sub_module).as_str(),
-            module_type,
-            build,
-        )?;
+        let contents = Self::read_file(&self.path)
+            .with_context(|e| format!("Could not read file {}: {}", self.path.display(), e))?;
+        let contents = Self::absolute_imports(&contents, &self.module, &self.module_type)?;
 
-        self.sub_modules.push(sub_module_manager);
+        debug!("Converting {} to {}", self.path.display(), new_path.display());
+        Self::make_tree(&new_path)?;
+        std::fs::write(&new_path, contents)
+            .with_context(|e| format!("Could not write to file {}: {}", new_path.display(), e))?;
+
+        match self.module_type {
+            ModuleType::Directory => std::fs::remove_dir_all(&old_dir).with_context(|e| {
+                format!("Could not remove directory {}: {}", old_dir.display(), e)
+            })?,
+            ModuleType::File => std::fs::remove_file(&self.path).with_context(|e| {
+                format!("Could not remove file {}: {}", self.path.display(), e)
+            })?,
+        }
+
+        Self::replace_in_root(&self.module, to, scope, &self.settings)
+            .with_context(|e| format!("Could not replace in root directory: {}", e))?;
+
+        self.path = new_path;
+        self.module = to.to_owned();
+        self.module_type = module_type;
+        self.reload()?;
         Ok(())
     }
 
-    pub fn find(
-        self: &Self,
-        query: &String,
-        prefix: String,
-        find_vars: bool,
-        find_functions: bool,
-        find_classes: bool,
-    ) -> Result<Vec<String>, Error> {
-        let mut display = String::new();
-        display.push_str(&prefix);
-        display.push_str("│――");
-
-        let display_path = match self.module_type {
-            ModuleType::Directory => self.path.parent().unwrap().to_str().unwrap(),
-            ModuleType::File => self.path.to_str().unwrap(),
-        };
+    /// Rewrites the relative `from` imports in the contents of `module` into
+    /// absolute ones. Imports reaching above the top-level package are left
+    /// as they are.
+    fn absolute_imports(
+        contents: &str,
+        module: &str,
+        module_type: &ModuleType,
+    ) -> Result<String, Error> {
+        let pattern = Regex::new(r"\bfrom\s+(?P<module>\.+[\w.]*)\s+import\b")
+            .with_context(|e| format!("Could not create regex: {}", e))?;
 
-        let file_path = cformat!(
-            "{}/{}",
-            std::env::current_dir().unwrap().to_str().unwrap(),
-            display_path
-        );
-        match self.module_type {
-            ModuleType::File => {
-                display.push_str(cformat!("📄 <green!>{}</green!>\n", file_path).as_str())
-            }
-            ModuleType::Directory => {
-                display.push_str(cformat!("📁 <blue!>{}</blue!>\n", file_path).as_str())
-            }
+        let mut package: Vec<&str> = module.split(".").collect();
+        if *module_type == ModuleType::File {
+            package.pop();
         }
 
-        let sub_prefix = format!("{}│  ", prefix);
-        let mut found = false;
-        let mut displays = Vec::new();
-        displays.push(display);
+        let (strings, comments) = literal_ranges(contents);
+        let mut edits = Vec::new();
+        for caps in pattern.captures_iter(contents) {
+            let relative = caps.name("module").unwrap();
+            if strings
+                .iter()
+                .chain(comments.iter())
+                .any(|l| l.contains(&relative.start()))
+            {
+                continue;
+            }
 
-        if find_vars {
-            for var in self.vars.clone() {
-                let found_var = var.find(query, None, Some(&sub_prefix));
-                if found_var.len() > 0 {
-                    found = true;
-                    displays.push(found_var);
-                }
+            let name = relative.as_str().trim_start_matches('.');
+            let level = relative.as_str().len() - name.len();
+            if level > package.len() {
+                continue;
             }
-        }
 
-        if find_functions {
-            for function in self.functions.clone() {
-                let found_function = function.find(query, None, Some(&sub_prefix));
-                if found_function.len() > 0 {
-                    found = true;
-                    displays.push(found_function);
-                }
+            let mut target = package[..package.len() + 1 - level].join(".");
+            if !name.is_empty() {
+                target.push_str(".");
+                target.push_str(name);
             }
+            edits.push((relative.range(), target));
         }
 
-        if find_classes || find_functions {
-            for class in self.classes.clone() {
-                let found_class = class.find(query, None, Some(&sub_prefix));
-                if found_class.len() > 0 {
-                    found = true;
-                    displays.push(found_class);
-                }
+        Self::apply_edits(contents, edits)
+    }
+
+    /// Errors unless the module is under the `scope` directory.
+    fn check_scope(self: &Self, scope: Option<&str>) -> Result<(), Error> {
+        if let Some(scope) = scope {
+            if !Self::normalized(&self.path).starts_with(Self::normalized(Path::new(scope))) {
+                return Result::Err(Error::from(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} is outside of the scope {}", self.path.display(), scope),
+                )));
             }
         }
 
-        if self.module_type == ModuleType::Directory {
-            for sub_module in &self.sub_modules {
-                let sub_displays = sub_module
-                    .find(
-                        query,
-                        format!("{}│  ", prefix),
-                        find_vars,
-                        find_functions,
-                        find_classes,
-                    )
-                    .with_context(|e| format!("Could not find in sub module: {}", e))?;
+        Ok(())
+    }
 
-                if sub_displays.len() > 0 {
-                    found = true;
-                    displays.extend(sub_displays)
+    /// `path` made absolute from the current directory, with its `.` and `..`
+    /// components resolved without looking at the filesystem, so that
+    /// `pkg/sub`, `./pkg//sub` and `pkg/../pkg/sub` compare equal.
+    pub fn normalized(path: &Path) -> PathBuf {
+        let mut normalized = PathBuf::new();
+        for component in std::env::current_dir().unwrap().join(path).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    normalized.pop();
                 }
+                component => normalized.push(component),
             }
-
-            displays.push(format!("{}│  *\n", prefix));
         }
-
-        return match found {
-            true => Ok(displays),
-            false => Ok(Vec::new()),
-        };
+        normalized
     }
 
-    pub fn mprint(self: &Self, prefix: String, show_code: bool) {
-        let mut display = String::new();
-        display.push_str(&prefix);
-        display.push_str("│――");
-        let display_name = &self.module.split(".").last().unwrap();
-        match self.module_type {
-            ModuleType::File => {
-                display.push_str(cformat!("📄 <green>{}</green>", display_name).as_str())
+    /// Moves the package into the existing package `to`, keeping the files of
+    /// both sides, then rewrites the imports like `mv`. The files both sides
+    /// have with different contents are left in place and returned, the
+    /// rest is merged and its imports rewritten file by file. Falls back to
+    /// `mv` when `to` does not exist.
+    pub fn merge(self: &mut Self, to: &str, scope: Option<&str>) -> Result<Vec<PathBuf>, Error> {
+        if self.module_type == ModuleType::File {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "Only packages can be merged",
+            )));
+        }
+
+        let new_path = Self::module_2_path(to, &self.module_type, &self.settings)?;
+        if !new_path.exists() {
+            self.mv(to, scope)?;
+            return Ok(Vec::new());
+        }
+
+        self.check_scope(scope)?;
+
+        let from_dir = self.path.parent().unwrap().to_path_buf();
+        let to_dir = new_path.parent().unwrap().to_path_buf();
+
+        let conflicts = Self::merge_conflicts(&from_dir, &to_dir)?;
+        let mut merged = Vec::new();
+        for file in Self::package_files(&from_dir) {
+            let relative = file.strip_prefix(&from_dir).unwrap().to_path_buf();
+            if conflicts.contains(&relative) {
+                continue;
             }
-            ModuleType::Directory => {
-                display.push_str(cformat!("📁 <blue>{}</blue>", display_name).as_str())
+
+            let destination = to_dir.join(&relative);
+            let holds_conflicts = relative.ends_with("__init__.py")
+                && conflicts.iter().any(|c| c.starts_with(relative.parent().unwrap()));
+            if holds_conflicts {
+                // The package stays importable for its conflicting files.
+                if !destination.exists() {
+                    create_dir_all(destination.parent().unwrap())?;
+                    std::fs::copy(&file, &destination).with_context(|e| {
+                        format!("Could not copy file {}: {}", file.display(), e)
+                    })?;
+                }
+                continue;
+            }
+
+            if destination.exists() {
+                // Same contents on both sides, the destination already has it.
+                std::fs::remove_file(&file)
+                    .with_context(|e| format!("Could not remove {}: {}", file.display(), e))?;
+            } else {
+                debug!("Merging {} into {}", file.display(), destination.display());
+                create_dir_all(destination.parent().unwrap())?;
+                rename(&file, &destination)
+                    .with_context(|e| format!("Could not move file {}: {}", file.display(), e))?;
+            }
+            merged.push(relative);
+        }
+
+        if conflicts.is_empty() {
+            std::fs::remove_dir_all(&from_dir)
+                .with_context(|e| format!("Could not remove {}: {}", from_dir.display(), e))?;
+
+            Self::replace_in_root(&self.module, to, scope, &self.settings)
+                .with_context(|e| format!("Could not replace in root directory: {}", e))?;
+
+            self.path = new_path;
+            self.module = to.to_owned();
+            self.reload()?;
+            return Ok(conflicts);
+        }
+
+        // The package stays for its conflicting files, only the imports of
+        // the merged modules move.
+        let merged_modules = merged
+            .iter()
+            .map(PathBuf::as_path)
+            .filter_map(Self::relative_module)
+            .filter(|relative| !relative.is_empty());
+        for relative in merged_modules {
+            let old = format!("{}.{}", self.module, relative);
+            let new = format!("{}.{}", to, relative);
+            Self::replace_in_root(&old, &new, scope, &self.settings)
+                .with_context(|e| format!("Could not replace in root directory: {}", e))?;
+        }
+        Self::remove_empty_dirs(&from_dir)?;
+
+        self.reload()?;
+        Ok(conflicts)
+    }
+
+    /// The dotted name of the Python file at `relative` within its package,
+    /// empty for the package's own `__init__.py`, or `None` for other files.
+    fn relative_module(relative: &Path) -> Option<String> {
+        if relative.extension()? != "py" {
+            return None;
+        }
+
+        let mut segments = relative
+            .with_extension("")
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+        if segments.last().map(String::as_str) == Some("__init__") {
+            segments.pop();
+        }
+
+        Some(segments.join("."))
+    }
+
+    /// Removes the directories under `dir` left without files, deepest first.
+    fn remove_empty_dirs(dir: &Path) -> Result<(), Error> {
+        for entry in WalkDir::new(dir).contents_first(true).into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if entry.file_type().is_dir() && path.read_dir()?.next().is_none() {
+                std::fs::remove_dir(path)
+                    .with_context(|e| format!("Could not remove {}: {}", path.display(), e))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The files under `dir`, leaving out `__pycache__` directories.
+    fn package_files(dir: &Path) -> Vec<PathBuf> {
+        WalkDir::new(dir)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != "__pycache__")
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.into_path())
+            .collect()
+    }
+
+    /// The paths, relative to the packages, of the files that both `from_dir`
+    /// and `to_dir` contain with different contents.
+    pub fn merge_conflicts(from_dir: &Path, to_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+        let mut conflicts = Vec::new();
+
+        for file in Self::package_files(from_dir) {
+            let relative = file.strip_prefix(from_dir).unwrap().to_path_buf();
+            let destination = to_dir.join(&relative);
+
+            if destination.exists()
+                && std::fs::read(&file)? != std::fs::read(&destination)?
+            {
+                conflicts.push(relative);
+            }
+        }
+
+        Ok(conflicts)
+    }
+
+    pub fn add_sub_module(
+        self: &mut Self,
+        sub_module: &str,
+        module_type: ModuleType,
+        build: bool,
+    ) -> Result<(), Error> {
+        if self.module_type == ModuleType::File {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "Files cannot contain other modules",
+            )));
+        }
+
+        let sub_module_manager = Self::new_with_settings(
+            format!("{}.{}", &self.module, sub_module).as_str(),
+            module_type,
+            build,
+            &self.settings,
+        )?;
+
+        self.sub_modules.push(sub_module_manager);
+        Ok(())
+    }
+
+    /// Annotates the module-level variable `name` in place, turning its first
+    /// `name = value` assignment into `name: type_ = value`.
+    /// Refuses if the variable is already annotated somewhere in the module.
+    pub fn annotate(self: &Self, name: &str, type_: &str) -> Result<(), Error> {
+        let vars = self
+            .vars
+            .iter()
+            .filter(|v| v.name == name)
+            .collect::<Vec<&Attribute>>();
+
+        if vars.len() == 0 {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} has no variable named {}", self.module, name),
+            )));
+        }
+
+        if vars.iter().any(|v| v.type_.is_some()) {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{}.{} is already annotated", self.module, name),
+            )));
+        }
+
+        let (_, end) = match vars.iter().min_by_key(|v| v.line).unwrap().name_span {
+            Some(span) => span,
+            None => {
+                return Result::Err(Error::from(std::io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!("{}.{} is assigned along with other names", self.module, name),
+                )))
+            }
+        };
+
+        let contents = Self::read_file(&self.path)
+            .with_context(|e| format!("Could not read file {}: {}", self.path.display(), e))?;
+        let contents = Self::apply_edits(&contents, vec![(end..end, format!(": {}", type_))])?;
+
+        std::fs::write(&self.path, contents)
+            .with_context(|e| format!("Could not write to file {}: {}", self.path.display(), e))?;
+
+        Ok(())
+    }
+
+    /// Is `name` defined at the top level of this module?
+    fn defines(self: &Self, name: &str) -> bool {
+        self.functions.iter().any(|f| f.name == name)
+            || self.classes.iter().any(|c| c.name == name)
+            || self.vars.iter().any(|v| v.name == name)
+            || self.type_aliases.iter().any(|t| t.name == name)
+    }
+
+    /// The ranges of the identifier `name` in `contents` that refer to the
+    /// module-level name. Strings, comments, attributes (`obj.name`) and
+    /// keyword arguments (`f(name=1)`) are skipped.
+    fn identifier_ranges(contents: &str, name: &str) -> Result<Vec<Range<usize>>, Error> {
+        let pattern = Regex::new(&format!(r"\b{}\b", regex::escape(name)))
+            .with_context(|e| format!("Could not create regex: {}", e))?;
+        let keyword_argument =
+            Regex::new(r"^\s*=[^=]").with_context(|e| format!("Could not create regex: {}", e))?;
+        let (strings, comments) = literal_ranges(contents);
+
+        Ok(pattern
+            .find_iter(contents)
+            .filter(|m| {
+                !strings
+                    .iter()
+                    .chain(comments.iter())
+                    .any(|l| l.contains(&m.start()))
+            })
+            .filter(|m| {
+                let before = contents[..m.start()].trim_end();
+                let is_attribute = before.ends_with('.');
+                let is_keyword = (before.ends_with('(') || before.ends_with(','))
+                    && keyword_argument.is_match(&contents[m.end()..]);
+                !is_attribute && !is_keyword
+            })
+            .map(|m| m.range())
+            .collect())
+    }
+
+    /// Renames the top-level definition `old` to `new` along with its uses
+    /// in this module's file, leaving alone the functions that rebind `old`
+    /// and the methods of the same name. Other modules importing `old` are
+    /// not updated.
+    /// Returns the number of replaced occurrences.
+    pub fn rename_def(self: &Self, old: &str, new: &str) -> Result<usize, Error> {
+        if !self.defines(old) {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} does not define {}", self.module, old),
+            )));
+        }
+
+        if self.defines(new) {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already defines {}", self.module, new),
+            )));
+        }
+
+        let contents = Self::read_file(&self.path)
+            .with_context(|e| format!("Could not read file {}: {}", self.path.display(), e))?;
+        let edits = name_references(&contents, old)
+            .with_context(|e| format!("Could not parse file {}: {}", self.path.display(), e))?
+            .into_iter()
+            .map(|r| (r, new.to_string()))
+            .collect::<Vec<_>>();
+        let count = edits.len();
+        let contents = Self::apply_edits(&contents, edits)?;
+
+        std::fs::write(&self.path, contents)
+            .with_context(|e| format!("Could not write to file {}: {}", self.path.display(), e))?;
+
+        Ok(count)
+    }
+
+    /// Source of a module that re-exports everything from `new` and warns
+    /// that `old` is deprecated.
+    fn shim_content(old: &str, new: &str) -> String {
+        format!(
+            "import warnings\n\
+             \n\
+             from {new} import *  # noqa: F401,F403\n\
+             \n\
+             warnings.warn(\n    \
+             \"{old} has moved to {new}, import it from there instead\",\n    \
+             DeprecationWarning,\n    \
+             stacklevel=2,\n\
+             )\n",
+            old = old,
+            new = new
+        )
+    }
+
+    /// Leaves a backward-compatible module at `old` re-exporting `new`.
+    /// Must run after the imports were rewritten, so the shim itself is not.
+    pub fn write_shim(
+        old: &str,
+        new: &str,
+        module_type: &ModuleType,
+        settings: &ProjectSettings,
+    ) -> Result<PathBuf, Error> {
+        let path = Self::module_2_path(old, module_type, settings)?;
+        Self::make_tree(&path)?;
+
+        std::fs::write(&path, Self::shim_content(old, new))
+            .with_context(|e| format!("Could not write to file {}: {}", path.display(), e))?;
+
+        Ok(path)
+    }
+
+    /// Re-exports a sub module from the package by appending
+    /// `from .<sub_module> import *` to its `__init__.py`.
+    /// Does nothing if the line is already present.
+    pub fn export_sub_module(self: &Self, sub_module: &str) -> Result<(), Error> {
+        if self.module_type == ModuleType::File {
+            return Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "Files cannot contain other modules",
+            )));
+        }
+
+        let line = format!("from .{} import *", sub_module);
+        let mut contents = Self::read_file(&self.path)
+            .with_context(|e| format!("Could not read file {}: {}", self.path.display(), e))?;
+
+        if contents.lines().any(|l| l.trim() == line) {
+            info!("{} already exports {}", self.path.display(), sub_module);
+            return Ok(());
+        }
+
+        if contents.len() > 0 && !contents.ends_with("\n") {
+            contents.push('\n');
+        }
+        contents.push_str(&line);
+        contents.push('\n');
+
+        std::fs::write(&self.path, contents)
+            .with_context(|e| format!("Could not write to file {}: {}", self.path.display(), e))?;
+
+        Ok(())
+    }
+
+    /// What a module moved to `to` would shadow in the destination package:
+    /// a sub-module of the same name, or a name defined or imported by the
+    /// package's `__init__.py`. Nothing when the package does not exist yet.
+    pub fn collisions(to: &str, settings: &ProjectSettings) -> Result<Vec<String>, Error> {
+        let (parent, leaf) = match to.rsplit_once('.') {
+            Some(split) => split,
+            None => return Ok(Vec::new()),
+        };
+
+        if Self::resolve(parent, settings).ok() != Some(ModuleType::Directory) {
+            return Ok(Vec::new());
+        }
+
+        let mut package = Self::new_with_settings(parent, ModuleType::Directory, false, settings)?;
+        package
+            .parse()
+            .with_context(|e| format!("Could not parse package {}: {}", parent, e))?;
+
+        let mut conflicts = Vec::new();
+        for (module, _) in package.sub_module_names()? {
+            if module == to {
+                conflicts.push(format!("module {} already exists", module));
+            }
+        }
+
+        if package.defines(leaf) {
+            conflicts.push(format!("{} already defines {}", parent, leaf));
+        }
+
+        if package
+            .imports
+            .iter()
+            .any(|i| i.alias.as_deref().unwrap_or(&i.name) == leaf)
+        {
+            conflicts.push(format!("{} already imports {}", parent, leaf));
+        }
+
+        Ok(conflicts)
+    }
+
+    /// Detects whether `module` is a file (`module.py`) or a package
+    /// (`module/__init__.py`). Errors if neither exists, or if both do since
+    /// the module would then be ambiguous.
+    pub fn resolve(module: &str, settings: &ProjectSettings) -> Result<ModuleType, Error> {
+        let file_path = Self::module_2_path(module, &ModuleType::File, settings)?;
+        let dir_path = Self::module_2_path(module, &ModuleType::Directory, settings)?;
+
+        let is_package =
+            dir_path.exists() || settings.is_namespace_package(dir_path.parent().unwrap());
+
+        match (file_path.exists(), is_package) {
+            (true, false) => Ok(ModuleType::File),
+            (false, true) => Ok(ModuleType::Directory),
+            (true, true) => Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Module {} is ambiguous: both {} and {} exist",
+                    module,
+                    file_path.display(),
+                    dir_path.display()
+                ),
+            ))),
+            (false, false) => Result::Err(Error::from(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "Module {} not found: neither {} nor {} exists",
+                    module,
+                    file_path.display(),
+                    dir_path.display()
+                ),
+            ))),
+        }
+    }
+
+    /// Describes how `resolve` maps `module` to a path: both candidate paths,
+    /// whether each exists, and the chosen module type.
+    pub fn explain_display(module: &str, settings: &ProjectSettings) -> Result<String, Error> {
+        let file_path = Self::module_2_path(module, &ModuleType::File, settings)?;
+        let dir_path = Self::module_2_path(module, &ModuleType::Directory, settings)?;
+        let status = |exists: bool| match exists {
+            true => cformat!("<g>exists</g>"),
+            false => cformat!("<r>missing</r>"),
+        };
+
+        let mut display = cformat!("<s>module:</s> {}\n", module);
+        display.push_str(&cformat!(
+            "<s>file candidate:</s> {} ({})\n",
+            file_path.display(),
+            status(file_path.exists())
+        ));
+        display.push_str(&cformat!(
+            "<s>package candidate:</s> {} ({})\n",
+            dir_path.display(),
+            status(dir_path.exists())
+        ));
+        if settings.is_namespace_package(dir_path.parent().unwrap()) {
+            display.push_str(&cformat!(
+                "<s>namespace package:</s> {}\n",
+                dir_path.parent().unwrap().display()
+            ));
+        }
+
+        let resolved = match Self::resolve(module, settings) {
+            Ok(module_type) => format!("{:?}", module_type),
+            Err(e) => cformat!("<r>nothing, {}</r>", e),
+        };
+        display.push_str(&cformat!("<s>resolves to:</s> {}\n", resolved));
+
+        Ok(display)
+    }
+
+    /// Loads the definitions of an existing module, see `resolve`.
+    /// Sub modules are not loaded.
+    /// Returns None if the module does not exist or is ambiguous.
+    pub fn locate(module: &str, settings: &ProjectSettings) -> Option<ModuleManager> {
+        let module_type = Self::resolve(module, settings).ok()?;
+
+        let mut module_manager =
+            Self::new_with_settings(module, module_type, false, settings).ok()?;
+        module_manager.parse().ok()?;
+        Some(module_manager)
+    }
+
+    /// Parses a batch of moves, one `old new` pair per line. The two modules may
+    /// also be separated by a comma. Blank lines and `#` comments are skipped.
+    pub fn parse_moves(contents: &str) -> Result<Vec<(String, String)>, Error> {
+        let mut moves = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parts = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|p| !p.is_empty())
+                .collect::<Vec<&str>>();
+
+            match parts.as_slice() {
+                [old, new] => moves.push((old.to_string(), new.to_string())),
+                _ => {
+                    return Result::Err(Error::from(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("line {}: expected `old new`, found `{}`", index + 1, line),
+                    )))
+                }
+            }
+        }
+
+        Ok(moves)
+    }
+
+    /// Is `module` the module `parent` or one of its sub modules?
+    fn is_within(module: &str, parent: &str) -> bool {
+        module == parent || module.starts_with(&format!("{}.", parent))
+    }
+
+    /// Orders a batch of moves so that none of them clobbers another:
+    /// a module is moved away before something else is moved onto it,
+    /// and sub modules are moved before their parent package.
+    /// Otherwise the original order is kept.
+    pub fn order_moves(moves: Vec<(String, String)>) -> Result<Vec<(String, String)>, Error> {
+        let mut pending = moves;
+        let mut ordered = Vec::new();
+
+        while !pending.is_empty() {
+            let next = (0..pending.len()).find(|&i| {
+                let (from, to) = &pending[i];
+                pending.iter().enumerate().all(|(j, (other_from, _))| {
+                    j == i
+                        || !(Self::is_within(to, other_from)
+                            || (other_from != from && Self::is_within(other_from, from)))
+                })
+            });
+
+            match next {
+                Some(i) => ordered.push(pending.remove(i)),
+                None => {
+                    return Result::Err(Error::from(std::io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "Moves depend on each other in a cycle: {}",
+                            pending
+                                .iter()
+                                .map(|(from, to)| format!("{} -> {}", from, to))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                    )))
+                }
+            }
+        }
+
+        Ok(ordered)
+    }
+
+    /// Lists the dotted name and type of every module in the project.
+    pub fn module_names(settings: &ProjectSettings) -> Result<Vec<(String, ModuleType)>, Error> {
+        let mut modules = Vec::new();
+        for entry in settings.walker().walk() {
+            let (file, module_type) = (entry.path, entry.module_type);
+
+            match Self::path_2_full_module(file.to_str().unwrap()) {
+                Ok(module) => modules.push((module, module_type)),
+                Err(e) => debug!("Could not convert {} to module: {}", file.display(), e),
+            }
+        }
+
+        modules.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(modules)
+    }
+
+    /// One line per module of `modules`, marked as a file or a package, as
+    /// listed by `view --list`.
+    pub fn list_display(modules: &[(String, ModuleType)]) -> String {
+        let mut display = String::new();
+        for (module, module_type) in modules {
+            let line = match module_type {
+                ModuleType::File => cformat!("📄 <green>{}</green>\n", module),
+                ModuleType::Directory => cformat!("📁 <blue>{}</blue>\n", module),
+            };
+            display.push_str(&line);
+        }
+
+        display
+    }
+
+    /// Loads the definitions of every module in the project, without nesting
+    /// them into sub modules. Files that cannot be loaded are skipped.
+    pub fn all_modules(settings: &ProjectSettings) -> Result<Vec<ModuleManager>, Error> {
+        Self::load_modules(|_| true, settings)
+    }
+
+    /// Loads the definitions of `module` and of every module below it, at
+    /// any depth, like `all_modules` does for the whole project.
+    pub fn modules_under(
+        module: &str,
+        settings: &ProjectSettings,
+    ) -> Result<Vec<ModuleManager>, Error> {
+        // The modules are listed by their full name, see `ProjectSettings::root_package`.
+        let module = &settings.qualify(module);
+        Self::resolve(module, settings)?;
+
+        let prefix = format!("{}.", module);
+        Self::load_modules(|name| name == module || name.starts_with(&prefix), settings)
+    }
+
+    fn load_modules(
+        selected: impl Fn(&str) -> bool,
+        settings: &ProjectSettings,
+    ) -> Result<Vec<ModuleManager>, Error> {
+        let mut modules = Vec::new();
+        for (module, module_type) in Self::module_names(settings)? {
+            if !selected(&module) {
+                continue;
+            }
+
+            let mut module_manager =
+                Self::new_with_settings(&module, module_type, false, settings)?;
+            if !settings.is_selected(&module_manager.path) {
+                continue;
+            }
+
+            match module_manager.parse() {
+                Ok(_) => modules.push(module_manager),
+                Err(e) => debug!("Could not load {}: {}", module, e),
+            }
+        }
+
+        Ok(modules)
+    }
+
+    /// Is `module` a glob pattern rather than a plain module name?
+    pub fn is_pattern(module: &str) -> bool {
+        module.contains("*")
+    }
+
+    /// Expands a module pattern against the modules of the project.
+    /// `*` matches a single name segment (or part of one, as in `test_*`),
+    /// `**` matches any number of segments.
+    pub fn expand_pattern(
+        pattern: &str,
+        settings: &ProjectSettings,
+    ) -> Result<Vec<(String, ModuleType)>, Error> {
+        let pattern_segments = pattern.split(".").collect::<Vec<&str>>();
+
+        let modules = Self::module_names(settings)?
+            .into_iter()
+            .filter(|(module, _)| {
+                let segments = module.split(".").collect::<Vec<&str>>();
+                Self::match_segments(&pattern_segments, &segments)
+            })
+            .collect();
+
+        Ok(modules)
+    }
+
+    pub(crate) fn match_segments(pattern: &[&str], segments: &[&str]) -> bool {
+        match pattern.split_first() {
+            None => segments.len() == 0,
+            Some((&"**", rest)) => {
+                (0..=segments.len()).any(|i| Self::match_segments(rest, &segments[i..]))
+            }
+            Some((first, rest)) => match segments.split_first() {
+                Some((segment, segments)) => {
+                    Self::match_wildcard(first, segment) && Self::match_segments(rest, segments)
+                }
+                None => false,
+            },
+        }
+    }
+
+    fn match_wildcard(pattern: &str, text: &str) -> bool {
+        match pattern.split_once("*") {
+            None => pattern == text,
+            Some((prefix, rest)) => match text.strip_prefix(prefix) {
+                Some(text) => (0..=text.len())
+                    .filter(|i| text.is_char_boundary(*i))
+                    .any(|i| Self::match_wildcard(rest, &text[i..])),
+                None => false,
+            },
+        }
+    }
+
+    /// Finds every class in the project inheriting, directly or not, from a
+    /// class named `base` (or `module.Base` for an exact match).
+    /// Returns the defining module of each subclass along with the class.
+    pub fn subclasses(
+        base: &str,
+        settings: &ProjectSettings,
+    ) -> Result<Vec<(String, Class)>, Error> {
+        let modules = Self::all_modules(settings)?;
+
+        let mut children: HashMap<String, Vec<(String, Class)>> = HashMap::new();
+        for module_manager in &modules {
+            for class in &module_manager.classes {
+                for base_class in &class.base_classes {
+                    let parent = match module_manager.resolve_class(base_class, MAX_RESOLVE_DEPTH)
+                    {
+                        Some((module, parent)) => format!("{}.{}", module, parent.name),
+                        None => base_class.clone(),
+                    };
+
+                    children
+                        .entry(parent)
+                        .or_insert_with(Vec::new)
+                        .push((module_manager.module.clone(), class.clone()));
+                }
             }
         }
 
-        println!("{}", display);
+        let mut queue = children
+            .keys()
+            .filter(|k| *k == base || k.rsplit(".").next() == Some(base))
+            .cloned()
+            .collect::<Vec<String>>();
+        queue.sort();
+
+        let mut seen = HashSet::new();
+        let mut subclasses = Vec::new();
+        while let Some(parent) = queue.pop() {
+            for (module, class) in children.get(&parent).cloned().unwrap_or_default() {
+                let key = format!("{}.{}", module, class.name);
+                if seen.insert(key.clone()) {
+                    subclasses.push((module, class));
+                    queue.push(key);
+                }
+            }
+        }
+
+        subclasses.sort_by(|a, b| (&a.0, &a.1.name).cmp(&(&b.0, &b.1.name)));
+        Ok(subclasses)
+    }
+
+    /// The module each import of this module points at, once each.
+    /// `from pkg import name` points at the sub module `pkg.name` when its
+    /// file exists, and at `pkg` when `name` is only defined there.
+    pub fn import_edges(self: &Self) -> Vec<String> {
+        let mut edges = Vec::new();
+        for import in &self.imports {
+            let edge = match (import.is_from(), self.import_target(import)) {
+                (true, Some(package)) => {
+                    let sub_module = format!("{}.{}", package, import.name);
+                    match Self::module_exists(&sub_module, &self.settings) {
+                        true => sub_module,
+                        false => package,
+                    }
+                }
+                (true, None) => continue,
+                (false, _) => import.name.clone(),
+            };
+
+            if !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        }
+
+        edges
+    }
+
+    /// Is there a file or a package for `module`?
+    fn module_exists(module: &str, settings: &ProjectSettings) -> bool {
+        [ModuleType::File, ModuleType::Directory]
+            .iter()
+            .any(|module_type| {
+                Self::module_2_path(module, module_type, settings)
+                    .map_or(false, |path| path.exists())
+            })
+    }
+
+    /// The modules of `known` that this module imports. Importing a module
+    /// also imports its packages, so `import a.b` counts for `a` and `a.b`.
+    /// The module itself and its own packages are left out: a child
+    /// importing a sibling does not make its package used.
+    pub fn imported_modules(self: &Self, known: &HashSet<String>) -> Vec<String> {
+        let own_package = |module: &str| {
+            module == self.module || self.module.starts_with(&format!("{}.", module))
+        };
+
+        let mut imported = Vec::new();
+        for target in self.import_edges() {
+            let segments = target.split(".").collect::<Vec<&str>>();
+            for end in 1..=segments.len() {
+                let module = segments[..end].join(".");
+                if !own_package(&module)
+                    && known.contains(&module)
+                    && !imported.contains(&module)
+                {
+                    imported.push(module);
+                }
+            }
+        }
+
+        imported
+    }
+
+    /// The modules of the project that no other module imports, with their
+    /// paths. Entry points are never reported: `__main__` modules, top-level
+    /// scripts and the modules matching one of the `ignore` patterns, see
+    /// `expand_pattern`.
+    pub fn orphans(
+        ignore: &[String],
+        settings: &ProjectSettings,
+    ) -> Result<Vec<(String, PathBuf)>, Error> {
+        let modules = Self::all_modules(settings)?;
+        let known = modules
+            .iter()
+            .map(|m| m.module.clone())
+            .collect::<HashSet<String>>();
+        let imported = modules
+            .iter()
+            .flat_map(|m| m.imported_modules(&known))
+            .collect::<HashSet<String>>();
+
+        let ignore = ignore
+            .iter()
+            .map(|pattern| pattern.split(".").collect::<Vec<&str>>())
+            .collect::<Vec<_>>();
+
+        let orphans = modules
+            .into_iter()
+            .filter(|m| {
+                let segments = m.module.split(".").collect::<Vec<&str>>();
+                let entry_point = segments.last() == Some(&"__main__")
+                    || (segments.len() == 1 && m.module_type == ModuleType::File)
+                    || ignore.iter().any(|p| Self::match_segments(p, &segments));
+
+                !entry_point && !imported.contains(&m.module)
+            })
+            .map(|m| (m.module, m.path))
+            .collect();
+
+        Ok(orphans)
+    }
+
+    /// Resolves the module a `from ... import` statement points at, turning
+    /// relative imports into absolute dotted names.
+    fn import_target(self: &Self, import: &Import) -> Option<String> {
+        if !import.is_from() {
+            return None;
+        }
+
+        if import.level == 0 {
+            return import.module.clone();
+        }
+
+        let mut package: Vec<&str> = self.module.split(".").collect();
+        if self.module_type == ModuleType::File {
+            package.pop();
+        }
+        for _ in 1..import.level {
+            package.pop()?;
+        }
+
+        let mut target = package.join(".");
+        if import.module.is_some() {
+            if target.len() > 0 {
+                target.push_str(".");
+            }
+            target.push_str(&import.module.clone().unwrap());
+        }
+
+        match target.len() {
+            0 => None,
+            _ => Some(target),
+        }
+    }
+
+    /// Finds where `name` is actually defined, starting from this module and
+    /// following `from ... import` re-exports. Returns the rendered definition.
+    pub fn find_definition(
+        self: &Self,
+        name: &str,
+        print_prefix: &String,
+        depth: usize,
+    ) -> Option<String> {
+        for var in &self.vars {
+            if var.name == name {
+                return Some(var.find(name, Some(true), Some(print_prefix)));
+            }
+        }
+
+        for function in &self.functions {
+            if function.name == name {
+                return Some(function.find(name, Some(true), Some(print_prefix)));
+            }
+        }
+
+        for class in &self.classes {
+            if class.name == name {
+                return Some(class.find(name, Some(true), Some(print_prefix)));
+            }
+        }
+
+        if depth == 0 {
+            return None;
+        }
+
+        for import in &self.imports {
+            if import.bound_name() != name && import.name != "*" {
+                continue;
+            }
+
+            let target = match self.import_target(import) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let looked_up = match import.name.as_str() {
+                "*" => name,
+                imported => imported,
+            };
+
+            if let Some(module_manager) = Self::locate(&target, &self.settings) {
+                let found = module_manager.find_definition(looked_up, print_prefix, depth - 1);
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Resolves a re-exported name to its canonical definition.
+    /// Returns None for external imports that are not part of the project.
+    pub fn resolve_reexport(self: &Self, import: &Import, print_prefix: &String) -> Option<String> {
+        let target = self.import_target(import)?;
+        let module_manager = Self::locate(&target, &self.settings)?;
+        module_manager.find_definition(&import.name, print_prefix, MAX_RESOLVE_DEPTH)
+    }
+
+    /// Resolves a class name as seen from this module, either defined here or
+    /// imported (`Base`, `module.Base` or `alias.Base`).
+    /// Returns the module defining the class along with the class.
+    pub fn resolve_class(self: &Self, name: &str, depth: usize) -> Option<(String, Class)> {
+        if let Some((qualifier, name)) = name.rsplit_once(".") {
+            if depth == 0 {
+                return None;
+            }
+
+            for import in &self.imports {
+                if import.bound_name() != qualifier && import.name != qualifier {
+                    continue;
+                }
+
+                let target = match (import.is_from(), self.import_target(import)) {
+                    (true, Some(package)) => format!("{}.{}", package, import.name),
+                    (true, None) => continue,
+                    (false, _) => import.name.clone(),
+                };
+
+                if let Some(module_manager) = Self::locate(&target, &self.settings) {
+                    return module_manager.resolve_class(name, depth - 1);
+                }
+            }
+
+            return None;
+        }
+
+        for class in &self.classes {
+            if class.name == name {
+                return Some((self.module.clone(), class.clone()));
+            }
+        }
+
+        if depth == 0 {
+            return None;
+        }
+
+        for import in &self.imports {
+            if import.bound_name() != name && import.name != "*" {
+                continue;
+            }
+
+            let target = match self.import_target(import) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let looked_up = match import.name.as_str() {
+                "*" => name,
+                imported => imported,
+            };
+
+            if let Some(module_manager) = Self::locate(&target, &self.settings) {
+                let found = module_manager.resolve_class(looked_up, depth - 1);
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Renders the inheritance chain of a class defined in this module, one
+    /// line per base class. Bases outside the project are shown by name only.
+    pub fn parents(self: &Self, class: &Class, print_prefix: &String, depth: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for base in &class.base_classes {
+            match self.resolve_class(base, MAX_RESOLVE_DEPTH) {
+                Some((module, parent)) => {
+                    lines.push(cformat!(
+                        "{}↳ <yellow>{}</yellow> <blue>({})</blue>\n",
+                        print_prefix,
+                        parent.name,
+                        module
+                    ));
+
+                    if depth == 0 {
+                        continue;
+                    }
+
+                    if let Some(module_manager) = Self::locate(&module, &self.settings) {
+                        lines.extend(module_manager.parents(
+                            &parent,
+                            &format!("{}    ", print_prefix),
+                            depth - 1,
+                        ));
+                    }
+                }
+                None => lines.push(cformat!("{}↳ <yellow>{}</yellow>\n", print_prefix, base)),
+            }
+        }
+
+        lines
+    }
+
+    /// The `│――` line of the module in `find` results, with its full path,
+    /// greyed out under `--show-empty` when nothing matched at or below it.
+    fn find_header(self: &Self, prefix: &String, empty: bool) -> String {
+        if empty {
+            return cformat!("{}│――<dim>{}</dim>\n", prefix, self.path.display());
+        }
+
+        let mut display = String::new();
+        display.push_str(prefix);
+        display.push_str("│――");
+
+        let display_path = match self.module_type {
+            ModuleType::Directory => self.path.parent().unwrap().to_str().unwrap(),
+            ModuleType::File => self.path.to_str().unwrap(),
+        };
+
+        let file_path = cformat!(
+            "{}/{}",
+            std::env::current_dir().unwrap().to_str().unwrap(),
+            display_path
+        );
+        match self.module_type {
+            ModuleType::File => {
+                display.push_str(cformat!("📄 <green!>{}</green!>", file_path).as_str())
+            }
+            ModuleType::Directory => {
+                display.push_str(cformat!("📁 <blue!>{}</blue!>", file_path).as_str())
+            }
+        }
+        display.push_str(&self.parse_error_badge());
+        display.push_str("\n");
+
+        display
+    }
+
+    /// The matches of `query` in the module's own definitions, without its
+    /// sub modules, each line starting with `sub_prefix`.
+    fn own_matches(
+        self: &Self,
+        query: &String,
+        sub_prefix: &String,
+        filter: &FindFilter,
+    ) -> Result<Vec<String>, Error> {
+        let mut displays = Vec::new();
+
+        if filter.vars || filter.constants {
+            for var in self.vars.clone() {
+                if !filter.vars && !var.is_constant {
+                    continue;
+                }
+
+                let found_var = var.find(query, None, Some(sub_prefix));
+                if found_var.len() > 0 {
+                    displays.push(found_var);
+                }
+            }
+        }
+
+        if filter.type_aliases {
+            for type_alias in self.type_aliases.clone() {
+                let found_type_alias = type_alias.find(query, None, Some(sub_prefix));
+                if found_type_alias.len() > 0 {
+                    displays.push(found_type_alias);
+                }
+            }
+        }
+
+        if filter.functions {
+            for function in &self.functions {
+                let found_function =
+                    Self::shown_function(function, filter).find(query, None, Some(sub_prefix));
+                if found_function.len() > 0 {
+                    displays.push(found_function);
+                }
+            }
+        }
+
+        if filter.classes || filter.functions {
+            for class in &self.classes {
+                let found_class =
+                    Self::shown_class(class, filter).find(query, None, Some(sub_prefix));
+                if found_class.len() > 0 {
+                    displays.push(found_class);
+
+                    if filter.parents {
+                        displays.extend(self.parents(
+                            &class,
+                            &format!("{}    ", sub_prefix),
+                            MAX_RESOLVE_DEPTH,
+                        ));
+                    }
+                }
+            }
+        }
+
+        if filter.comments {
+            for comment in &self.comments {
+                let found_comment = comment.find(query, None, Some(sub_prefix));
+                if found_comment.len() > 0 {
+                    displays.push(match filter.context_lines {
+                        0 => found_comment,
+                        n => self.context_display(query, comment.line, n, sub_prefix),
+                    });
+                }
+            }
+        }
+
+        if filter.decorators {
+            for function in &self.functions {
+                if let Some(found_function) = function.find_decorator(query, sub_prefix) {
+                    displays.push(found_function);
+                }
+            }
+
+            for class in &self.classes {
+                if let Some(found_class) = class.find_decorator(query, sub_prefix) {
+                    displays.push(found_class);
+                }
+            }
+        }
+
+        if filter.signatures {
+            let pattern = Self::signature_pattern(query)?;
+
+            for function in &self.functions {
+                if let Some(found_function) = function.find_signature(&pattern, sub_prefix) {
+                    displays.push(found_function);
+                }
+            }
+
+            for class in &self.classes {
+                if let Some(found_class) = class.find_signature(&pattern, sub_prefix) {
+                    displays.push(found_class);
+                }
+            }
+        }
+
+        if filter.follow_reexports {
+            for import in self.imports.clone() {
+                if !import.is_from() || import.name == "*" {
+                    continue;
+                }
+
+                let found_import = import.find(query, None, Some(sub_prefix));
+                if found_import.len() > 0 {
+                    displays.push(found_import);
+
+                    if let Some(definition) =
+                        self.resolve_reexport(&import, &format!("{}    ", sub_prefix))
+                    {
+                        displays.push(definition);
+                    }
+                }
+            }
+        }
+
+        Ok(displays)
+    }
+
+    /// The pattern of a `--signature` search: `query` as a regex or, when it
+    /// is not a valid one, as plain text.
+    fn signature_pattern(query: &str) -> Result<Regex, Error> {
+        let pattern = Regex::new(query)
+            .or_else(|_| Regex::new(&regex::escape(query)))
+            .with_context(|e| format!("Could not create regex: {}", e))?;
+        Ok(pattern)
+    }
+
+    /// The displays of the matches of `query` in the module tree, with
+    /// whether anything matched, as with `--show-empty` the displays are
+    /// never empty.
+    pub fn find(
+        self: &Self,
+        query: &String,
+        prefix: String,
+        filter: &FindFilter,
+    ) -> Result<(Vec<String>, bool), Error> {
+        let sub_prefix = format!("{}│  ", prefix);
+        let own = self.own_matches(query, &sub_prefix, filter)?;
+        let mut found = !own.is_empty();
+        let mut displays = vec![String::new()];
+        displays.extend(own);
+
+        if self.module_type == ModuleType::Directory {
+            for sub_module in &self.sub_modules {
+                let (sub_displays, sub_found) = sub_module
+                    .find(query, format!("{}│  ", prefix), filter)
+                    .with_context(|e| format!("Could not find in sub module: {}", e))?;
+
+                found |= sub_found;
+                displays.extend(sub_displays)
+            }
+
+            if filter.package_ends {
+                displays.push(self.package_end(&prefix));
+            }
+        }
+        displays[0] = self.find_header(&prefix, filter.show_empty && !found);
+
+        return match found || filter.show_empty {
+            true => Ok((displays, found)),
+            false => Ok((Vec::new(), false)),
+        };
+    }
+
+    /// Like `find`, but loads the modules of the tree one at a time and
+    /// passes each display to `emit` as soon as it is known, so results show
+    /// up while the rest of the tree is scanned. The displays and their order
+    /// are the same as with `find`. Returns whether anything was found.
+    pub fn find_streaming(
+        module: &str,
+        module_type: ModuleType,
+        query: &String,
+        filter: &FindFilter,
+        settings: &ProjectSettings,
+        emit: &mut dyn FnMut(String),
+    ) -> Result<bool, Error> {
+        let mut pending = Vec::new();
+        let found = Self::stream_matches(
+            module,
+            module_type,
+            query,
+            String::new(),
+            filter,
+            settings,
+            &mut pending,
+            emit,
+        )?;
+        for display in pending.drain(..) {
+            emit(display);
+        }
+        Ok(found)
+    }
+
+    /// See `find_streaming`. `pending` holds the headers of the modules
+    /// above, only emitted once something is found below them, and under
+    /// `--show-empty` the greyed out modules waiting for them.
+    fn stream_matches(
+        module: &str,
+        module_type: ModuleType,
+        query: &String,
+        prefix: String,
+        filter: &FindFilter,
+        settings: &ProjectSettings,
+        pending: &mut Vec<String>,
+        emit: &mut dyn FnMut(String),
+    ) -> Result<bool, Error> {
+        let mut module_manager = Self::new_with_settings(module, module_type, false, settings)?;
+        module_manager.parse()?;
+
+        let sub_prefix = format!("{}│  ", prefix);
+        let header = pending.len();
+        let own = profile::time(Phase::Render, || {
+            let own = module_manager.own_matches(query, &sub_prefix, filter)?;
+            pending.push(module_manager.find_header(&prefix, false));
+            Ok::<_, Error>(own)
+        })?;
+        let mut found = !own.is_empty();
+        if found {
+            for display in pending.drain(..).chain(own) {
+                emit(display);
+            }
+        }
+
+        if module_manager.module_type == ModuleType::Directory {
+            for (sub_module, sub_module_type) in module_manager.sub_module_names()? {
+                found |= Self::stream_matches(
+                    &sub_module,
+                    sub_module_type,
+                    query,
+                    sub_prefix.clone(),
+                    filter,
+                    settings,
+                    pending,
+                    emit,
+                )
+                .with_context(|e| format!("Could not find in sub module: {}", e))?;
+            }
+
+            if filter.package_ends {
+                pending.push(module_manager.package_end(&prefix));
+            }
+        }
+
+        if found {
+            for display in pending.drain(..) {
+                emit(display);
+            }
+        } else if filter.show_empty {
+            pending[header] = module_manager.find_header(&prefix, true);
+        } else {
+            // Nothing below this module matched, so it is never shown.
+            pending.truncate(header);
+        }
+
+        Ok(found)
+    }
+
+    /// The 1-based lines from `line - n` to `line + n` of `source`, clamped to
+    /// the start and end of the file.
+    pub fn context_lines(source: &str, line: usize, n: usize) -> Vec<(usize, &str)> {
+        let first = line.saturating_sub(n).max(1);
+        source
+            .lines()
+            .enumerate()
+            .map(|(index, text)| (index + 1, text))
+            .skip(first - 1)
+            .take(line + n + 1 - first)
+            .collect()
+    }
+
+    /// Shows `n` lines of source around `line`, like `grep -C`, with the
+    /// matched line marked and the query highlighted.
+    fn context_display(self: &Self, query: &str, line: usize, n: usize, prefix: &String) -> String {
+        let mut display = String::new();
+        for (number, text) in Self::context_lines(&self.source, line, n) {
+            if number == line {
+                let text = match query.len() {
+                    0 => text.to_string(),
+                    _ => text.replace(query, cformat!("<bg:green>{}</bg:green>", query).as_str()),
+                };
+                display.push_str(&cformat!("{}<yellow>{:>4}</yellow> > {}\n", prefix, number, text));
+            } else {
+                display.push_str(&cformat!("{}<dim>{:>4}</dim> | {}\n", prefix, number, text));
+            }
+        }
+
+        display
+    }
+
+    /// Reports names defined more than once at the top level of this module
+    /// and its sub modules, as well as methods repeated within a class.
+    pub fn duplicates(self: &Self) -> Vec<String> {
+        let path = self.path.display().to_string();
+        let mut top_level = Vec::new();
+
+        for var in &self.vars {
+            top_level.push((var.name.as_str(), var.line, false));
+        }
+        for function in &self.functions {
+            if !function.redefines_on_purpose() {
+                top_level.push((function.name.as_str(), function.line, true));
+            }
+        }
+        for class in &self.classes {
+            top_level.push((class.name.as_str(), class.line, true));
+        }
+
+        let mut reports = Self::report_duplicates(&path, "", top_level);
+
+        for class in &self.classes {
+            let methods = class
+                .methods
+                .iter()
+                .filter(|m| !m.redefines_on_purpose())
+                .map(|m| (m.name.as_str(), m.line, true))
+                .collect::<Vec<_>>();
+
+            reports.extend(Self::report_duplicates(
+                &path,
+                &format!("{}.", class.name),
+                methods,
+            ));
+        }
+
+        for sub_module in &self.sub_modules {
+            reports.extend(sub_module.duplicates());
+        }
+
+        reports
+    }
+
+    /// Passes each match of `query` to `emit` as soon as it is found, going
+    /// through the module tree like `find` but without rendering anything.
+    pub fn find_records(
+        self: &Self,
+        query: &str,
+        filter: &FindFilter,
+        emit: &mut dyn FnMut(FindRecord),
+    ) -> Result<(), Error> {
+        let path = self.path.display().to_string();
+        let mut record = |kind: &str, name: &str, line: usize, code: &str| {
+            emit(FindRecord {
+                module: self.module.clone(),
+                path: path.clone(),
+                kind: kind.to_string(),
+                name: name.to_string(),
+                line,
+                code: code.to_string(),
+            })
+        };
+
+        if filter.vars || filter.constants {
+            for var in &self.vars {
+                if (filter.vars || var.is_constant) && !var.find(query, None, None).is_empty() {
+                    let kind = if var.is_constant { "constant" } else { "variable" };
+                    record(kind, &var.name, var.line, &var.definition_code);
+                }
+            }
+        }
+
+        if filter.type_aliases {
+            for type_alias in &self.type_aliases {
+                if !type_alias.find(query, None, None).is_empty() {
+                    record(
+                        "type_alias",
+                        &type_alias.name,
+                        type_alias.line,
+                        &type_alias.definition_code,
+                    );
+                }
+            }
+        }
+
+        if filter.functions {
+            for function in &self.functions {
+                if !function.find(query, None, None).is_empty() {
+                    record("function", &function.name, function.line, &function.definition_code);
+                }
+            }
+        }
+
+        if filter.classes || filter.functions {
+            for class in &self.classes {
+                if !class.find(query, None, None).is_empty() {
+                    record("class", &class.name, class.line, &class.definition_code);
+                }
+            }
+        }
+
+        if filter.comments {
+            for comment in &self.comments {
+                if !comment.find(query, None, None).is_empty() {
+                    record("comment", &comment.text, comment.line, &comment.text);
+                }
+            }
+        }
+
+        if filter.decorators {
+            for function in &self.functions {
+                if function.has_decorator(query) {
+                    record("function", &function.name, function.line, &function.definition_code);
+                }
+            }
+
+            for class in &self.classes {
+                if class.has_decorator(query) {
+                    record("class", &class.name, class.line, &class.definition_code);
+                    continue;
+                }
+
+                for method in &class.methods {
+                    if method.has_decorator(query) {
+                        let name = format!("{}.{}", class.name, method.name);
+                        record("method", &name, method.line, &method.definition_code);
+                    }
+                }
+            }
+        }
+
+        if filter.signatures {
+            let pattern = Self::signature_pattern(query)?;
+
+            for function in &self.functions {
+                if function.find_signature(&pattern, &String::new()).is_some() {
+                    record("function", &function.name, function.line, &function.definition_code);
+                }
+            }
+
+            for class in &self.classes {
+                for method in &class.methods {
+                    if method.find_signature(&pattern, &String::new()).is_some() {
+                        let name = format!("{}.{}", class.name, method.name);
+                        record("method", &name, method.line, &method.definition_code);
+                    }
+                }
+            }
+        }
+
+        if filter.follow_reexports {
+            for import in &self.imports {
+                if import.is_from()
+                    && import.name != "*"
+                    && !import.find(query, None, None).is_empty()
+                {
+                    record("import", import.bound_name(), import.line, &import.definition_code);
+                }
+            }
+        }
+
+        for sub_module in &self.sub_modules {
+            sub_module.find_records(query, filter, emit)?;
+        }
+
+        Ok(())
+    }
+
+    /// The number of `records` of each module, most first, then by name.
+    pub fn count_per_module(records: &[FindRecord]) -> Vec<(String, usize)> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for record in records {
+            *counts.entry(&record.module).or_insert(0) += 1;
+        }
+
+        let mut counts = counts
+            .into_iter()
+            .map(|(module, count)| (module.to_string(), count))
+            .collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts
+    }
+
+    /// Renders `records` grouped under the path of their file, like ripgrep,
+    /// with the line number and first code line of each match. Files appear
+    /// in the order of their first match.
+    pub fn flat_display(records: &[FindRecord]) -> String {
+        let mut paths: Vec<&str> = Vec::new();
+        for record in records {
+            if !paths.contains(&record.path.as_str()) {
+                paths.push(&record.path);
+            }
+        }
+
+        let mut display = String::new();
+        for path in paths {
+            if !display.is_empty() {
+                display.push('\n');
+            }
+            display.push_str(&cformat!("<magenta>{}</magenta>\n", path));
+
+            for record in records.iter().filter(|r| r.path == path) {
+                display.push_str(&cformat!(
+                    "<green>{}</green>:<dim>{}</dim> {}\n",
+                    record.line,
+                    record.kind,
+                    record.code.lines().next().unwrap_or_default().trim()
+                ));
+            }
+        }
+
+        display
+    }
+
+    /// Reports the imports of project modules whose name is never used in
+    /// the importing file, in this module and its sub modules. `__init__.py`
+    /// files and names listed in `__all__` are skipped as re-exports.
+    pub fn unused_imports(self: &Self) -> Result<Vec<String>, Error> {
+        let mut reports = Vec::new();
+
+        if !self.path.ends_with("__init__.py") {
+            let exported = self
+                .vars
+                .iter()
+                .filter(|v| v.name == "__all__")
+                .filter_map(|v| v.default.clone())
+                .collect::<Vec<String>>();
+
+            for import in self
+                .imports
+                .iter()
+                .filter(|i| Self::is_internal(i, &self.settings))
+            {
+                // `import a.b` binds `a`.
+                let name = match &import.alias {
+                    Some(alias) => alias.as_str(),
+                    None => import.name.split(".").next().unwrap(),
+                };
+
+                let quoted = [format!("\"{}\"", name), format!("'{}'", name)];
+                if name == "*" || exported.iter().any(|e| quoted.iter().any(|q| e.contains(q))) {
+                    continue;
+                }
+
+                let used = Self::identifier_ranges(&self.source, name)?
+                    .iter()
+                    .any(|range| {
+                        !self.imports.iter().filter_map(|i| i.span).any(|(start, end)| {
+                            start <= range.start && range.end <= end
+                        })
+                    });
+
+                if !used {
+                    reports.push(format!(
+                        "{}:{}: `{}` is imported but never used",
+                        self.path.display(),
+                        import.line,
+                        name
+                    ));
+                }
+            }
+        }
+
+        for sub_module in &self.sub_modules {
+            reports.extend(sub_module.unused_imports()?);
+        }
+
+        Ok(reports)
+    }
+
+    /// Does `import` refer to a module of the project rather than a third
+    /// party one?
+    fn is_internal(import: &Import, settings: &ProjectSettings) -> bool {
+        if import.level > 0 {
+            return true;
+        }
+
+        let module = import.module.as_ref().unwrap_or(&import.name);
+        Self::resolve(module.split(".").next().unwrap(), settings).is_ok()
+    }
+
+    /// Plain variables may be reassigned freely, so a name only counts as a
+    /// duplicate when at least one of its definitions is a function or class.
+    fn report_duplicates(
+        path: &str,
+        scope: &str,
+        mut names: Vec<(&str, usize, bool)>,
+    ) -> Vec<String> {
+        names.sort_by_key(|(_, line, _)| *line);
+
+        let mut reports = Vec::new();
+        for (i, (name, line, is_def)) in names.iter().enumerate() {
+            let first = names[..i]
+                .iter()
+                .find(|(n, _, d)| n == name && (*d || *is_def));
+
+            if let Some((_, first_line, _)) = first {
+                reports.push(format!(
+                    "{}:{}: `{}{}` is already defined at line {}",
+                    path, line, scope, name, first_line
+                ));
+            }
+        }
+
+        reports
+    }
+
+    /// Serializes the module tree, with its definitions and sub modules, to JSON.
+    pub fn to_json(self: &Self) -> Result<String, Error> {
+        let json = serde_json::to_string_pretty(self)
+            .with_context(|e| format!("Could not serialize {}: {}", self.module, e))?;
+        Ok(json)
+    }
+
+    /// One-line count of the definitions in this module,
+    /// e.g. `(3 classes, 7 functions, 2 vars)`.
+    pub fn summary(self: &Self) -> String {
+        format!(
+            "({} classes, {} functions, {} vars)",
+            self.classes.len(),
+            self.functions.len(),
+            self.vars.len()
+        )
+    }
+
+    /// The code of the definitions selected by `filter`, each line starting with `prefix`.
+    pub fn code_displays(self: &Self, prefix: &String, filter: &FindFilter) -> Vec<String> {
+        if filter.group_by_kind {
+            return self.grouped_displays(prefix, filter);
+        }
+
+        let mut displays = self.var_displays(prefix, filter);
+        displays.extend(self.function_displays(prefix, filter));
+        displays.extend(self.class_displays(prefix, filter));
+        displays.extend(self.type_alias_displays(prefix, filter));
+        if filter.reverse {
+            displays.reverse();
+        }
+
+        displays
+    }
+
+    /// The `code_displays` under labeled sections, classes first, with the
+    /// definitions indented one level under their section.
+    fn grouped_displays(self: &Self, prefix: &String, filter: &FindFilter) -> Vec<String> {
+        let item_prefix = format!("{}│  ", prefix);
+        let sections = [
+            ("Classes", self.class_displays(&item_prefix, filter)),
+            ("Functions", self.function_displays(&item_prefix, filter)),
+            ("Variables", self.var_displays(&item_prefix, filter)),
+            ("Type aliases", self.type_alias_displays(&item_prefix, filter)),
+        ];
+
+        let mut sections = Vec::from(sections);
+        if filter.reverse {
+            sections.reverse();
+        }
+
+        let mut displays = Vec::new();
+        for (label, mut section) in sections {
+            if section.is_empty() {
+                continue;
+            }
+            // The section heading stays on top of its reversed definitions.
+            if filter.reverse {
+                section.reverse();
+            }
+
+            displays.push(cformat!("{}<bold>{}</bold> ({})\n", prefix, label, section.len()));
+            displays.extend(section);
+        }
+
+        displays
+    }
+
+    fn var_displays(self: &Self, prefix: &String, filter: &FindFilter) -> Vec<String> {
+        if !filter.vars && !filter.constants {
+            return Vec::new();
+        }
+
+        let vars = self.vars.iter().filter(|var| filter.vars || var.is_constant);
+        if !filter.compact_vars {
+            return vars.map(|var| var.find("", None, Some(prefix))).collect();
+        }
+
+        let names = vars
+            .map(|var| {
+                let name = match &var.type_ {
+                    Some(type_) => format!("{}: {}", var.name, type_),
+                    None => var.name.clone(),
+                };
+                match var.is_constant {
+                    true => cformat!("<cyan>{}</cyan>", name),
+                    false => name,
+                }
+            })
+            .collect::<Vec<String>>();
+
+        match names.is_empty() {
+            true => Vec::new(),
+            false => vec![format!("{}{}\n", prefix, names.join(", "))],
+        }
+    }
+
+    fn function_displays(self: &Self, prefix: &String, filter: &FindFilter) -> Vec<String> {
+        if !filter.functions {
+            return Vec::new();
+        }
+
+        let conditional = self.conditional_functions.iter().filter(|_| filter.conditional);
+
+        self.functions
+            .iter()
+            .chain(conditional)
+            .map(|function| {
+                let raw = self.raw_display(filter, function.span, prefix);
+                raw.unwrap_or_else(|| {
+                    Self::shown_function(function, filter).find("", None, Some(prefix))
+                })
+            })
+            .collect()
+    }
+
+    fn class_displays(self: &Self, prefix: &String, filter: &FindFilter) -> Vec<String> {
+        if !filter.classes {
+            return Vec::new();
+        }
+
+        let conditional = self.conditional_classes.iter().filter(|_| filter.conditional);
+
+        self.classes
+            .iter()
+            .chain(conditional)
+            .map(|class| {
+                let raw = self.raw_display(filter, class.span, prefix);
+                raw.unwrap_or_else(|| {
+                    Self::shown_class(class, filter).find("", None, Some(prefix))
+                })
+            })
+            .collect()
+    }
+
+    /// A copy of `function` without what `filter` hides from displays.
+    fn shown_function(function: &Method, filter: &FindFilter) -> Method {
+        let function = match filter.hide_private_args {
+            true => function.without_private_args(),
+            false => function.clone(),
+        };
+
+        match filter.hide_decorators {
+            true => function.without_decorators(),
+            false => function,
+        }
+    }
+
+    /// A copy of `class` without what `filter` hides from displays.
+    fn shown_class(class: &Class, filter: &FindFilter) -> Class {
+        let class = match filter.hide_private_args {
+            true => class.without_private_args(),
+            false => class.clone(),
+        };
+
+        match filter.hide_decorators {
+            true => class.without_decorators(),
+            false => class,
+        }
+    }
+
+    fn type_alias_displays(self: &Self, prefix: &String, filter: &FindFilter) -> Vec<String> {
+        if !filter.type_aliases {
+            return Vec::new();
+        }
+
+        self.type_aliases
+            .iter()
+            .map(|type_alias| type_alias.find("", None, Some(prefix)))
+            .collect()
+    }
+
+    /// The verbatim source of a definition with each line behind `prefix`,
+    /// when the filter asks for it and the span is known.
+    fn raw_display(
+        self: &Self,
+        filter: &FindFilter,
+        span: Option<(usize, usize)>,
+        prefix: &String,
+    ) -> Option<String> {
+        if !filter.raw {
+            return None;
+        }
+
+        let (start, end) = span?;
+        let code = self.source.get(start..end)?;
+        Some(code.lines().map(|line| format!("{}{}\n", prefix, line)).collect())
+    }
+
+    /// The line closing the children of a package, aligned with them.
+    fn package_end(self: &Self, prefix: &String) -> String {
+        cformat!("{}│  <dim>╰─ end of {}</dim>\n", prefix, self.module)
+    }
+
+    /// Renders the module tree. With `show_code`, the definitions selected by
+    /// `filter` are listed under each module.
+    pub fn tree_display(
+        self: &Self,
+        prefix: String,
+        show_code: bool,
+        summary: bool,
+        filter: &FindFilter,
+    ) -> String {
+        let mut display = String::new();
+        display.push_str(&prefix);
+        display.push_str("│――");
+        let display_name = &self.module.split(".").last().unwrap();
+        match self.module_type {
+            ModuleType::File => {
+                display.push_str(cformat!("📄 <green>{}</green>", display_name).as_str())
+            }
+            ModuleType::Directory => {
+                display.push_str(cformat!("📁 <blue>{}</blue>", display_name).as_str())
+            }
+        }
+        display.push_str(&self.parse_error_badge());
+
+        if summary {
+            display.push_str(" ");
+            display.push_str(&self.summary());
+        } else if filter.counts {
+            display.push_str(&cformat!(
+                " <dim>({} classes, {} functions)</dim>",
+                self.classes.len(),
+                self.functions.len()
+            ));
+        }
+
+        display.push_str("\n");
+
+        if show_code && !summary {
+            let sub_prefix = format!("{}│  ", prefix);
+
+            for code_display in self.code_displays(&sub_prefix, filter) {
+                display.push_str(&code_display);
+            }
+        }
+
+        if self.module_type == ModuleType::Directory {
+            let mut sub_modules = self.sub_modules.iter().collect::<Vec<_>>();
+            if filter.reverse {
+                sub_modules.reverse();
+            }
+
+            for sub_module in sub_modules {
+                display.push_str(&sub_module.tree_display(
+                    format!("{}│  ", prefix),
+                    show_code,
+                    summary,
+                    filter,
+                ));
+            }
+
+            if filter.package_ends {
+                display.push_str(&self.package_end(&prefix));
+            }
+        }
+
+        display
+    }
+
+    /// Renders the module tree as Markdown: a heading per module, one level
+    /// deeper per package, and a bullet per definition selected by `filter`.
+    /// With `show_code`, each bullet is followed by its code in a fence.
+    pub fn markdown_display(
+        self: &Self,
+        level: usize,
+        show_code: bool,
+        summary: bool,
+        filter: &FindFilter,
+    ) -> String {
+        let mut display = format!("{} `{}`", "#".repeat(level.min(6)), self.module);
+        if self.parse_error.is_some() {
+            display.push_str(" (parse error)");
+        }
+        if summary {
+            display.push_str(" ");
+            display.push_str(&self.summary());
+        }
+        display.push_str("\n\n");
+
+        if !summary {
+            let items = self.markdown_items(filter);
+            for (kind, name, code) in &items {
+                display.push_str(&format!("- {} `{}`\n", kind, name));
+                if show_code && !code.is_empty() {
+                    display.push_str("\n  ```python\n");
+                    for line in code.trim_end().lines() {
+                        display.push_str(format!("  {}", line).trim_end());
+                        display.push_str("\n");
+                    }
+                    display.push_str("  ```\n\n");
+                }
+            }
+
+            // A fence already ends with a blank line.
+            let fenced = |(_, _, code): &(&str, String, String)| show_code && !code.is_empty();
+            if items.last().is_some_and(|item| !fenced(item)) {
+                display.push_str("\n");
+            }
+        }
+
+        let mut sub_modules = self.sub_modules.iter().collect::<Vec<_>>();
+        if filter.reverse {
+            sub_modules.reverse();
+        }
+
+        for sub_module in sub_modules {
+            display.push_str(&sub_module.markdown_display(level + 1, show_code, summary, filter));
+        }
+
+        display
+    }
+
+    /// The kind, name and code of the definitions selected by `filter`, in
+    /// the order of `code_displays`. Compact variables come as a single
+    /// item without code.
+    fn markdown_items(self: &Self, filter: &FindFilter) -> Vec<(&'static str, String, String)> {
+        let raw = |span: Option<(usize, usize)>| match filter.raw {
+            true => span.and_then(|(start, end)| self.source.get(start..end)),
+            false => None,
+        };
+        let decorated = |decorators: &[String], code: &str| {
+            let mut lines = decorators.iter().map(|d| format!("@{}\n", d)).collect::<String>();
+            lines.push_str(code);
+            lines
+        };
+
+        let mut items = Vec::new();
+        if filter.vars || filter.constants {
+            let vars = self.vars.iter().filter(|var| filter.vars || var.is_constant);
+            match filter.compact_vars {
+                true => {
+                    let names = vars
+                        .map(|var| match &var.type_ {
+                            Some(type_) => format!("{}: {}", var.name, type_),
+                            None => var.name.clone(),
+                        })
+                        .collect::<Vec<String>>();
+                    if !names.is_empty() {
+                        items.push(("variables", names.join(", "), String::new()));
+                    }
+                }
+                false => {
+                    for var in vars {
+                        items.push(("variable", var.name.clone(), var.definition_code.clone()));
+                    }
+                }
+            }
+        }
+
+        if filter.functions {
+            let conditional = self.conditional_functions.iter().filter(|_| filter.conditional);
+            for function in self.functions.iter().chain(conditional) {
+                let code = match raw(function.span) {
+                    Some(code) => code.to_string(),
+                    None => {
+                        let function = Self::shown_function(function, filter);
+                        decorated(&function.decorators, &function.definition_code)
+                    }
+                };
+                items.push(("function", function.name.clone(), code));
+            }
+        }
+
+        if filter.classes {
+            let conditional = self.conditional_classes.iter().filter(|_| filter.conditional);
+            for class in self.classes.iter().chain(conditional) {
+                let code = match raw(class.span) {
+                    Some(code) => code.to_string(),
+                    None => {
+                        let class = Self::shown_class(class, filter);
+                        decorated(&class.decorators, &class.definition_code)
+                    }
+                };
+                items.push(("class", class.name.clone(), code));
+            }
+        }
+
+        if filter.type_aliases {
+            for type_alias in &self.type_aliases {
+                items.push((
+                    "type",
+                    type_alias.name.clone(),
+                    type_alias.definition_code.clone(),
+                ));
+            }
+        }
+
+        if filter.reverse {
+            items.reverse();
+        }
+
+        items
+    }
+
+    /// Prints the module tree, see `tree_display`.
+    pub fn mprint(self: &Self, prefix: String, show_code: bool, summary: bool, filter: &FindFilter) {
+        print!("{}", self.tree_display(prefix, show_code, summary, filter));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{remove_dir_all, remove_file};
+
+    use super::*;
+
+    #[test]
+    fn test_create() {
+        let module_manager =
+            ModuleManager::new("tests_data.test_create", ModuleType::File, false).unwrap();
+        assert_eq!(module_manager.module, "tests_data.test_create");
+        assert_eq!(module_manager.path, PathBuf::from("tests_data/test_create.py"));
+
+        let module_manager =
+            ModuleManager::new("tests_data.test_create", ModuleType::Directory, false).unwrap();
+        assert_eq!(module_manager.module, "tests_data.test_create");
+        assert_eq!(
+            module_manager.path,
+            PathBuf::from("tests_data/test_create/__init__.py")
+        );
+    }
+
+    #[test]
+    fn test_build() {
+        let module_manager =
+            ModuleManager::new("tests_data.test_build_file", ModuleType::File, false).unwrap();
+        module_manager.build().unwrap();
+        assert!(module_manager.path.exists());
+
+        let module_manager =
+            ModuleManager::new("tests_data.test_build_dir", ModuleType::Directory, false).unwrap();
+        module_manager.build().unwrap();
+        assert!(module_manager.path.exists());
+    }
+
+    #[test]
+    fn test_follow_reexports() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_reexport", ModuleType::Directory, false).unwrap();
+        module_manager.reload().unwrap();
+
+        let import = module_manager
+            .imports
+            .iter()
+            .find(|i| i.name == "Foo")
+            .unwrap()
+            .clone();
+        let definition = module_manager
+            .resolve_reexport(&import, &String::new())
+            .unwrap();
+        assert!(definition.contains("tests_data/test_reexport/impl.py"));
+        assert!(definition.contains("Foo"));
+
+        let external = module_manager
+            .imports
+            .iter()
+            .find(|i| i.name == "path")
+            .unwrap()
+            .clone();
+        assert!(module_manager
+            .resolve_reexport(&external, &String::new())
+            .is_none());
+    }
+
+    #[test]
+    fn test_export_sub_module() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_export", ModuleType::Directory, true).unwrap();
+        module_manager
+            .add_sub_module("sub_module", ModuleType::File, true)
+            .unwrap();
+
+        module_manager.export_sub_module("sub_module").unwrap();
+        module_manager.export_sub_module("sub_module").unwrap();
+
+        let content = ModuleManager::read_file(&module_manager.path).unwrap();
+        remove_dir_all("tests_data/test_export").unwrap();
+        assert_eq!(content, "from .sub_module import *\n");
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_duplicates", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
+        let reports = module_manager.duplicates();
+        assert_eq!(
+            reports,
+            vec![
+                "tests_data/test_duplicates.py:9: `foo` is already defined at line 1",
+                "tests_data/test_duplicates.py:25: `Bar.run` is already defined at line 22",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_module", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
+        assert_eq!(module_manager.summary(), "(2 classes, 3 functions, 3 vars)");
+    }
+
+    #[test]
+    fn test_batch_moves() {
+        let contents = ModuleManager::read_file(Path::new("tests_data/test_moves.txt")).unwrap();
+        let moves = ModuleManager::parse_moves(&contents).unwrap();
+        assert_eq!(
+            moves,
+            vec![
+                (String::from("pkg.a"), String::from("pkg.b")),
+                (String::from("pkg.b"), String::from("pkg.c")),
+            ]
+        );
+
+        // `pkg.b` has to move away before `pkg.a` takes its place.
+        let ordered = ModuleManager::order_moves(moves).unwrap();
+        assert_eq!(ordered[0].0, "pkg.b");
+        assert_eq!(ordered[1].0, "pkg.a");
+
+        let cycle = vec![
+            (String::from("pkg.a"), String::from("pkg.b")),
+            (String::from("pkg.b"), String::from("pkg.a")),
+        ];
+        assert!(ModuleManager::order_moves(cycle).is_err());
+        assert!(ModuleManager::parse_moves("pkg.a").is_err());
+    }
+
+    #[test]
+    fn test_missing_init_files() {
+        create_dir_all("tests_data/test_missing_init/sub").unwrap();
+        create_dir_all("tests_data/test_missing_init/.hidden").unwrap();
+        std::fs::write("tests_data/test_missing_init/__init__.py", "").unwrap();
+        std::fs::write("tests_data/test_missing_init/sub/a.py", "").unwrap();
+        std::fs::write("tests_data/test_missing_init/.hidden/b.py", "").unwrap();
+
+        let prefix = Some(String::from("tests_data/test_missing_init"));
+        let settings = ProjectSettings::default();
+        let missing = ModuleManager::missing_init_files(prefix.clone(), &settings).unwrap();
+        ModuleManager::create_init_files(&missing).unwrap();
+        let created = Path::new("tests_data/test_missing_init/sub/__init__.py").exists();
+        let remaining = ModuleManager::missing_init_files(prefix, &settings).unwrap();
+
+        remove_dir_all("tests_data/test_missing_init").unwrap();
+        assert_eq!(missing, vec![PathBuf::from("./tests_data/test_missing_init/sub")]);
+        assert!(created);
+        assert!(remaining.is_empty());
+    }
+
+    #[test]
+    fn test_empty_segments() {
+        let settings = ProjectSettings::default();
+        for module in ["a..b", ".a", "a."] {
+            let error =
+                ModuleManager::module_2_path(module, &ModuleType::File, &settings).unwrap_err();
+            assert!(error.to_string().contains("empty dotted segment"));
+            assert!(ModuleManager::new(module, ModuleType::Directory, false).is_err());
+        }
+
+        assert!(ModuleManager::is_sub_module("a.b", "a"));
+        assert!(!ModuleManager::is_sub_module("a", "a"));
+        assert!(!ModuleManager::is_sub_module("ab.c", "a"));
+        assert!(!ModuleManager::is_sub_module("a..b", "a"));
+    }
+
+    #[test]
+    fn test_classify_path() {
+        assert_eq!(
+            ModuleManager::classify_path(Path::new("./main.py")),
+            (ModuleType::File, true)
+        );
+        assert_eq!(
+            ModuleManager::classify_path(Path::new("tests_data/__init__.py")),
+            (ModuleType::Directory, true)
+        );
+        assert_eq!(
+            ModuleManager::classify_path(Path::new("./tests_data/test_module.py")),
+            (ModuleType::File, false)
+        );
+        assert_eq!(
+            ModuleManager::classify_path(Path::new("./tests_data/test_inheritance/__init__.py")),
+            (ModuleType::Directory, false)
+        );
+        // The root's own __init__.py does not make the root a module.
+        assert_eq!(
+            ModuleManager::classify_path(Path::new("./__init__.py")),
+            (ModuleType::Directory, false)
+        );
+    }
+
+    #[test]
+    fn test_list_display() {
+        let names = ModuleManager::module_names(&ProjectSettings::default()).unwrap();
+        assert!(names.contains(&(String::from("tests_data.test_module"), ModuleType::File)));
+        assert!(names.contains(&(
+            String::from("tests_data.test_inheritance"),
+            ModuleType::Directory
+        )));
+
+        let display = ModuleManager::list_display(&names);
+        assert!(display.contains("tests_data.test_module"));
+        assert_eq!(display.lines().count(), names.len());
+    }
+
+    #[test]
+    fn test_top_level_type() {
+        assert_eq!(
+            ModuleManager::top_level_type(Path::new("./main.py")),
+            Some(ModuleType::File)
+        );
+        assert_eq!(
+            ModuleManager::top_level_type(Path::new("./tests_data/__init__.py")),
+            Some(ModuleType::Directory)
+        );
+        assert_eq!(ModuleManager::top_level_type(Path::new("./__init__.py")), None);
+        assert_eq!(
+            ModuleManager::top_level_type(Path::new("./tests_data/test_module.py")),
+            None
+        );
+
+        // Nested packages are not roots, they are reached through their parent.
+        assert_eq!(
+            ModuleManager::top_level_type(Path::new("./tests_data/test_inheritance/__init__.py")),
+            None
+        );
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_inheritance", ModuleType::Directory, false)
+                .unwrap();
+        module_manager.reload().unwrap();
+        let sub_modules = module_manager
+            .sub_modules()
+            .iter()
+            .map(|m| m.module())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sub_modules,
+            vec!["tests_data.test_inheritance.base", "tests_data.test_inheritance.child"]
+        );
+    }
+
+    #[test]
+    fn test_code_displays_kinds() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_module", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
+        let filter = FindFilter::kinds(false, false, false, true, false);
+        let displays = module_manager.code_displays(&String::new(), &filter);
+        assert_eq!(displays.len(), 2);
+        assert!(displays.iter().all(|d| d.contains("class")));
+
+        let filter = FindFilter::kinds(false, false, false, false, false);
+        let displays = module_manager.code_displays(&String::new(), &filter);
+        assert_eq!(displays.len(), 8);
+    }
+
+    #[test]
+    fn test_type_aliases() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_type_alias", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
+        let aliases = module_manager
+            .type_aliases
+            .iter()
+            .map(|t| t.definition_code.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            aliases,
+            vec!["type UserId = int", "type Point = tuple[float, float]"]
+        );
+        assert_eq!(module_manager.vars.len(), 1);
+        assert_eq!(module_manager.vars[0].name, "count");
+    }
+
+    #[test]
+    fn test_parents() {
+        let mut module_manager = ModuleManager::new(
+            "tests_data.test_inheritance.child",
+            ModuleType::File,
+            false,
+        )
+        .unwrap();
+        module_manager.reload().unwrap();
+
+        let child = module_manager.classes[0].clone();
+        let parents = module_manager.parents(&child, &String::new(), MAX_RESOLVE_DEPTH);
+        assert_eq!(parents.len(), 2);
+        assert!(parents[0].contains("Base"));
+        assert!(parents[0].contains("tests_data.test_inheritance.base"));
+        assert!(parents[1].starts_with("    ↳"));
+        assert!(parents[1].contains("Root"));
+
+        let other = module_manager.classes[1].clone();
+        let parents = module_manager.parents(&other, &String::new(), MAX_RESOLVE_DEPTH);
+        assert_eq!(parents.len(), 3);
+        assert!(parents[0].contains("tests_data.test_inheritance.base"));
+        assert!(parents[2].contains("dict"));
+        assert!(!parents[2].contains("("));
+    }
+
+    #[test]
+    fn test_subclasses() {
+        let subclasses = ModuleManager::subclasses("Root", &ProjectSettings::default())
+            .unwrap()
+            .into_iter()
+            .filter(|(module, _)| module.starts_with("tests_data.test_inheritance"))
+            .map(|(module, class)| format!("{}.{}", module, class.name))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            subclasses,
+            vec![
+                "tests_data.test_inheritance.base.Base",
+                "tests_data.test_inheritance.child.Child",
+                "tests_data.test_inheritance.child.Other",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_inheritance", ModuleType::Directory, false)
+                .unwrap();
+        module_manager.reload().unwrap();
+
+        let json = module_manager.to_json().unwrap();
+        assert!(!json.contains('\u{1b}'));
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["module"], "tests_data.test_inheritance");
+        assert_eq!(value["module_type"], "Directory");
+        assert_eq!(value["sub_modules"].as_array().unwrap().len(), 2);
+        assert_eq!(json, module_manager.to_json().unwrap());
+    }
+
+    #[test]
+    fn test_expand_pattern() {
+        let names = |pattern: &str| {
+            ModuleManager::expand_pattern(pattern, &ProjectSettings::default())
+                .unwrap()
+                .into_iter()
+                .map(|(module, _)| module)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names("tests_data.test_inheritance.*"),
+            vec![
+                "tests_data.test_inheritance.base",
+                "tests_data.test_inheritance.child"
+            ]
+        );
+        assert_eq!(
+            names("tests_data.**.ba*"),
+            vec!["tests_data.test_inheritance.base"]
+        );
+        assert!(names("tests_data.**").contains(&String::from("tests_data.test_inheritance.child")));
+        assert!(!ModuleManager::is_pattern("tests_data.test_module"));
+    }
+
+    #[test]
+    fn test_write_shim() {
+        let path = ModuleManager::write_shim(
+            "tests_data.test_shim",
+            "tests_data.test_module",
+            &ModuleType::File,
+            &ProjectSettings::default(),
+        )
+        .unwrap();
+
+        let content = ModuleManager::read_file(&path).unwrap();
+        remove_file(&path).unwrap();
+
+        assert_eq!(path, PathBuf::from("tests_data/test_shim.py"));
+        assert_eq!(
+            content,
+            "import warnings\n\
+             \n\
+             from tests_data.test_module import *  # noqa: F401,F403\n\
+             \n\
+             warnings.warn(\n    \
+             \"tests_data.test_shim has moved to tests_data.test_module, import it from there instead\",\n    \
+             DeprecationWarning,\n    \
+             stacklevel=2,\n\
+             )\n"
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        let path = Path::new("tests_data/test_annotate.py");
+        std::fs::write(path, "x = 1\ny: str = 'a'\nx = 2\n").unwrap();
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_annotate", ModuleType::File, false).unwrap();
+        module_manager.parse().unwrap();
+        module_manager.annotate("x", "int").unwrap();
+        let refused = module_manager.annotate("y", "int");
+
+        let content = ModuleManager::read_file(path).unwrap();
+        remove_file(path).unwrap();
+
+        assert_eq!(content, "x: int = 1\ny: str = 'a'\nx = 2\n");
+        assert!(refused.is_err());
+    }
+
+    #[test]
+    fn test_rewrite_third_party_imports() {
+        let contents = "import requests\nimport requests.adapters as a\nfrom requests import get\n\
+                        import requests_mock\nx = requests.get('requests.get')  # requests.get\n\
+                        y = my_requests.get(), self.requests.get\nimport requests";
+        let expected = "import httpx\nimport httpx.adapters as a\nfrom httpx import get\n\
+                        import requests_mock\nx = httpx.get('requests.get')  # requests.get\n\
+                        y = my_requests.get(), self.requests.get\nimport httpx";
+
+        assert_eq!(
+            ModuleManager::rewrite_imports(contents, "requests", "httpx").unwrap(),
+            expected
+        );
+
+        // The new name containing the old one is not rewritten twice.
+        assert_eq!(
+            ModuleManager::rewrite_imports("import foo.x\nfoo.y()\n", "foo", "foo.bar").unwrap(),
+            "import foo.bar.x\nfoo.bar.y()\n"
+        );
+    }
+
+    #[test]
+    fn test_planned_paths() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_dry_run", ModuleType::Directory, false).unwrap();
+        module_manager.add_sub_module("a", ModuleType::File, false).unwrap();
+        module_manager.add_sub_module("b", ModuleType::File, false).unwrap();
+
+        assert_eq!(
+            module_manager.planned_paths(),
+            vec![
+                PathBuf::from("tests_data/test_dry_run"),
+                PathBuf::from("tests_data/test_dry_run/__init__.py"),
+                PathBuf::from("tests_data/test_dry_run/a.py"),
+                PathBuf::from("tests_data/test_dry_run/b.py"),
+            ]
+        );
+        assert!(!Path::new("tests_data/test_dry_run").exists());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let settings = ProjectSettings::default();
+        assert!(
+            ModuleManager::resolve("tests_data.test_module", &settings).unwrap()
+                == ModuleType::File
+        );
+        assert!(
+            ModuleManager::resolve("tests_data.test_reexport", &settings).unwrap()
+                == ModuleType::Directory
+        );
+
+        let missing = ModuleManager::resolve("tests_data.test_missing", &settings).unwrap_err();
+        assert!(missing.to_string().contains("not found"));
+
+        create_dir_all("tests_data/test_ambiguous").unwrap();
+        File::create("tests_data/test_ambiguous/__init__.py").unwrap();
+        File::create("tests_data/test_ambiguous.py").unwrap();
+
+        let ambiguous = ModuleManager::resolve("tests_data.test_ambiguous", &settings);
+
+        remove_dir_all("tests_data/test_ambiguous").unwrap();
+        remove_file("tests_data/test_ambiguous.py").unwrap();
+
+        assert!(ambiguous.unwrap_err().to_string().contains("ambiguous"));
+    }
+
+    #[test]
+    fn test_context_lines() {
+        let source = "a\nb\nc\nd\n";
+        assert_eq!(ModuleManager::context_lines(source, 1, 1), vec![(1, "a"), (2, "b")]);
+        assert_eq!(
+            ModuleManager::context_lines(source, 3, 1),
+            vec![(2, "b"), (3, "c"), (4, "d")]
+        );
+        assert_eq!(ModuleManager::context_lines(source, 4, 0), vec![(4, "d")]);
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_comments", ModuleType::File, false).unwrap();
+        module_manager.parse().unwrap();
+
+        let filter = FindFilter {
+            comments: true,
+            context_lines: 1,
+            ..FindFilter::default()
+        };
+        let (displays, _) = module_manager
+            .find(&String::from("TODO"), String::new(), &filter)
+            .unwrap();
+        let displays = displays.concat();
+        assert!(displays.contains("x = 1"));
+        assert!(displays.contains("return x"));
+        assert!(!displays.contains("def f"));
+    }
+
+    #[test]
+    fn test_tree_display() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_inheritance", ModuleType::Directory, false)
+                .unwrap();
+        module_manager.reload().unwrap();
+
+        let tree = module_manager.tree_display(String::new(), false, false, &FindFilter::all());
+        assert_eq!(
+            tree,
+            cformat!(
+                "│――📁 <blue>test_inheritance</blue>\n\
+                 │  │――📄 <green>base</green>\n\
+                 │  │――📄 <green>child</green>\n"
+            )
+        );
+
+        let filter = FindFilter {
+            package_ends: true,
+            ..FindFilter::all()
+        };
+        let tree = module_manager.tree_display(String::new(), false, false, &filter);
+        assert!(tree.ends_with(&cformat!(
+            "│  <dim>╰─ end of tests_data.test_inheritance</dim>\n"
+        )));
+    }
+
+    #[test]
+    fn test_find_streaming() {
+        create_dir_all("tests_data/test_streaming").unwrap();
+        std::fs::write("tests_data/test_streaming/__init__.py", "").unwrap();
+        std::fs::write("tests_data/test_streaming/a.py", "def needle_a():\n    pass\n").unwrap();
+        std::fs::write("tests_data/test_streaming/b.py", "").unwrap();
+
+        let query = String::from("needle");
+        let filter = FindFilter::all();
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_streaming", ModuleType::Directory, false).unwrap();
+        module_manager.reload().unwrap();
+        let (collected, _) = module_manager.find(&query, String::new(), &filter).unwrap();
+
+        // b.py only gets a match once the first result is out: it is seen
+        // only if the results stream before the rest of the tree is read.
+        let mut streamed = Vec::new();
+        let found = ModuleManager::find_streaming(
+            "tests_data.test_streaming",
+            ModuleType::Directory,
+            &query,
+            &filter,
+            &ProjectSettings::default(),
+            &mut |display| {
+                if streamed.is_empty() {
+                    std::fs::write("tests_data/test_streaming/b.py", "needle_b = 1\n").unwrap();
+                }
+                streamed.push(display);
+            },
+        )
+        .unwrap();
+
+        remove_dir_all("tests_data/test_streaming").unwrap();
+        assert!(found);
+        assert_eq!(streamed[..collected.len()], collected[..]);
+        assert!(streamed.concat().contains("needle_b"));
+        assert!(!collected.concat().contains("needle_b"));
+    }
+
+    #[test]
+    fn test_show_empty() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_inheritance", ModuleType::Directory, false)
+                .unwrap();
+        module_manager.reload().unwrap();
+        let query = String::from("no_such_name");
+        let mut filter = FindFilter::all();
+
+        let (hidden, hidden_found) = module_manager.find(&query, String::new(), &filter).unwrap();
+        filter.show_empty = true;
+        let (shown, shown_found) = module_manager.find(&query, String::new(), &filter).unwrap();
+
+        assert!(hidden.is_empty());
+        assert!(!hidden_found && !shown_found);
+        assert_eq!(shown.len(), 1 + module_manager.sub_modules.len());
+        assert!(shown.iter().all(|display| display.contains(&cformat!("<dim>"))));
+        assert!(shown[0].contains("tests_data/test_inheritance/__init__.py"));
+
+        // The package has a match below it, so its header is the usual one
+        // with or without --show-empty.
+        let query = String::from("Child");
+        let (shown, found) = module_manager.find(&query, String::new(), &filter).unwrap();
+        filter.show_empty = false;
+        let (default, _) = module_manager.find(&query, String::new(), &filter).unwrap();
+
+        assert!(found);
+        assert_eq!(default[0], module_manager.find_header(&String::new(), false));
+        assert!(default[0].contains("📁"));
+        assert_eq!(shown[0], default[0]);
+    }
+
+    #[test]
+    fn test_explain_display() {
+        let settings = ProjectSettings::default();
+        let display = ModuleManager::explain_display("tests_data.test_module", &settings).unwrap();
+        assert!(display.contains(&cformat!(
+            "<s>file candidate:</s> tests_data/test_module.py (<g>exists</g>)"
+        )));
+        assert!(display.contains(&cformat!(
+            "<s>package candidate:</s> tests_data/test_module/__init__.py (<r>missing</r>)"
+        )));
+        assert!(display.ends_with(&cformat!("<s>resolves to:</s> File\n")));
+
+        let missing =
+            ModuleManager::explain_display("tests_data.no_such_module", &settings).unwrap();
+        assert!(missing.contains("nothing, Module tests_data.no_such_module not found"));
+        assert!(ModuleManager::explain_display("tests_data..test_module", &settings).is_err());
+    }
+
+    #[test]
+    fn test_stub_files() {
+        create_dir_all("tests_data/test_stubs").unwrap();
+        std::fs::write("tests_data/test_stubs/api.pyi", "def get(url: str) -> bytes: ...\n").unwrap();
+        std::fs::write("tests_data/test_stubs/impl.py", "def run():\n    return 1\n").unwrap();
+        std::fs::write("tests_data/test_stubs/impl.pyi", "def run() -> int: ...\n").unwrap();
+
+        let walk = |stubs: bool| {
+            let mut paths = ProjectWalker::new()
+                .root(Some(String::from("tests_data/test_stubs")))
+                .stubs(stubs)
+                .walk()
+                .map(|e| e.path.display().to_string())
+                .collect::<Vec<_>>();
+            paths.sort();
+            paths
+        };
+        let (without, with) = (walk(false), walk(true));
+        let settings = ProjectSettings::default();
+        let stub = ModuleManager::from_path("tests_data/test_stubs/api.pyi", &settings);
+        remove_dir_all("tests_data/test_stubs").unwrap();
+
+        assert_eq!(without, vec!["./tests_data/test_stubs/impl.py"]);
+        assert_eq!(
+            with,
+            vec!["./tests_data/test_stubs/api.pyi", "./tests_data/test_stubs/impl.py"]
+        );
+        assert!(ModuleManager::is_path("api.pyi"));
+        assert_eq!(
+            ModuleManager::path_2_module("./tests_data/test_stubs/api.pyi", &settings).unwrap(),
+            "tests_data.test_stubs.api"
+        );
+
+        let stub = stub.unwrap();
+        assert_eq!(stub.functions[0].name, "get");
+        assert!(stub.functions[0].is_stub);
+    }
+
+    #[test]
+    fn test_compact_vars() {
+        let module_manager = ModuleManager::from_source(
+            Path::new("tests_data/test_compact.py"),
+            String::from("HOST = \"localhost\"\nPORT: int = 8080\nDEBUG = False\nname = \"app\"\n"),
+        )
+        .unwrap();
+        let mut filter = FindFilter::kinds(true, false, false, false, false);
+
+        let full = module_manager.code_displays(&String::new(), &filter);
+        filter.compact_vars = true;
+        let compact = module_manager.code_displays(&String::new(), &filter);
+
+        assert_eq!(full.len(), 4);
+        assert!(full[1].contains("PORT: int = 8080"));
+        assert_eq!(
+            compact,
+            vec![cformat!(
+                "<cyan>HOST</cyan>, <cyan>PORT: int</cyan>, <cyan>DEBUG</cyan>, name\n"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_root_package() {
+        let root_package = Some("tests_data");
+        let module = ModuleManager::qualified("test_module", root_package);
+        let defaults = ProjectSettings::default();
+        let path = ModuleManager::module_2_path(&module, &ModuleType::File, &defaults).unwrap();
+
+        assert_eq!(module, "tests_data.test_module");
+        assert_eq!(path, PathBuf::from("tests_data/test_module.py"));
+        assert_eq!(
+            ModuleManager::path_2_module(path.to_str().unwrap(), &defaults).unwrap(),
+            "tests_data.test_module"
+        );
+
+        let settings = ProjectSettings {
+            root_package: root_package.map(String::from),
+            ..ProjectSettings::default()
+        };
+        assert_eq!(settings.qualify("test_module"), "tests_data.test_module");
+        assert_eq!(
+            ModuleManager::module_2_path("test_module", &ModuleType::File, &settings).unwrap(),
+            path
+        );
+        assert_eq!(
+            ModuleManager::path_2_module(path.to_str().unwrap(), &settings).unwrap(),
+            "test_module"
+        );
+        assert_eq!(defaults.qualify("test_module"), "test_module");
+        assert_eq!(
+            ModuleManager::qualified("tests_data.test_module", root_package),
+            "tests_data.test_module"
+        );
+        assert_eq!(ModuleManager::qualified("tests_data", root_package), "tests_data");
+        assert_eq!(
+            ModuleManager::qualified("tests_data_other", root_package),
+            "tests_data.tests_data_other"
+        );
+        assert_eq!(ModuleManager::qualified("test_module", None), "test_module");
+        assert_eq!(
+            ModuleManager::unqualified("tests_data.test_module", root_package),
+            "test_module"
+        );
+        assert_eq!(ModuleManager::unqualified("test_module", root_package), "test_module");
+        assert_eq!(
+            ModuleManager::path_2_full_module("tests_data/test_module.py").unwrap(),
+            "tests_data.test_module"
+        );
+    }
+
+    #[test]
+    fn test_tree_reverse() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_inheritance", ModuleType::Directory, false)
+                .unwrap();
+        module_manager.reload().unwrap();
+        let mut filter = FindFilter::default();
+
+        let forward = module_manager.tree_display(String::new(), false, false, &filter);
+        filter.reverse = true;
+        let reversed = module_manager.tree_display(String::new(), false, false, &filter);
+
+        let position = |display: &String, name: &str| display.find(name).unwrap();
+        assert!(position(&forward, "base") < position(&forward, "child"));
+        assert!(position(&reversed, "child") < position(&reversed, "base"));
+        assert!(position(&reversed, "test_inheritance") < position(&reversed, "child"));
+    }
+
+    #[test]
+    fn test_tree_counts() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_module", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+        let mut filter = FindFilter::all();
+        filter.counts = true;
+
+        let display = module_manager.tree_display(String::new(), false, false, &filter);
+        assert_eq!(display.lines().count(), 1);
+        assert!(display.contains(&cformat!("<dim>(2 classes, 3 functions)</dim>")));
+    }
+
+    #[test]
+    fn test_keyword_only_method_code() {
+        let code = "def f(*, x):\n    pass\n\n\nclass A:\n    def m(self, *, x):\n        pass\n";
+        let module_manager =
+            ModuleManager::from_source(Path::new("test.py"), String::from(code)).unwrap();
+        let filter = FindFilter::all();
+
+        let displays = module_manager.code_displays(&String::new(), &filter).concat();
+        assert!(displays.contains(&cformat!("(<red>*</red>, x):")));
+        assert!(displays.contains(&cformat!("(<red>self</red>, <red>*</red>, x):")));
+
+        let items = module_manager.markdown_items(&filter);
+        assert!(items[0].2.contains("def f(*, x):"));
+        assert!(items[1].2.contains("    def m(self, *, x):\n"));
+    }
+
+    #[test]
+    fn test_raw_code_displays() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_many_args", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
+        let filter = FindFilter {
+            raw: true,
+            ..FindFilter::kinds(false, false, true, true, false)
+        };
+        let displays = module_manager.code_displays(&String::new(), &filter);
+        let source = std::fs::read_to_string("tests_data/test_many_args.py").unwrap();
+
+        assert_eq!(displays.len(), 3);
+        assert_eq!(
+            displays[0],
+            source[..source.find("\n\n\n").unwrap() + 1]
+        );
+        assert!(displays[2].starts_with("class Builder:\n    def configure"));
+        for display in &displays {
+            assert!(source.contains(display.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_import_edges() {
+        create_dir_all("tests_data/test_edges").unwrap();
+        std::fs::write("tests_data/test_edges/__init__.py", "def helper():\n    pass\n").unwrap();
+        std::fs::write("tests_data/test_edges/sub.py", "").unwrap();
+        std::fs::write(
+            "tests_data/test_edges/user.py",
+            "from tests_data.test_edges import sub, helper\nfrom . import sub as again\n",
+        )
+        .unwrap();
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_edges.user", ModuleType::File, false).unwrap();
+        let parsed = module_manager.reload();
+        let edges = module_manager.import_edges();
+
+        remove_dir_all("tests_data/test_edges").unwrap();
+        parsed.unwrap();
+        assert_eq!(edges, vec!["tests_data.test_edges.sub", "tests_data.test_edges"]);
+    }
+
+    #[test]
+    fn test_orphans() {
+        create_dir_all("tests_data/test_orphans").unwrap();
+        std::fs::write("tests_data/test_orphans/__init__.py", "from . import a\n").unwrap();
+        std::fs::write("tests_data/test_orphans/a.py", "from .b import run\n").unwrap();
+        std::fs::write("tests_data/test_orphans/b.py", "def run():\n    pass\n").unwrap();
+        std::fs::write("tests_data/test_orphans/c.py", "import os\n").unwrap();
+
+        let in_package = |orphans: Vec<(String, PathBuf)>| {
+            let mut orphans = orphans
+                .into_iter()
+                .filter(|(module, _)| module.starts_with("tests_data.test_orphans"))
+                .collect::<Vec<_>>();
+            orphans.sort();
+            orphans
+        };
+        let settings = ProjectSettings::default();
+        let orphans = in_package(ModuleManager::orphans(&[], &settings).unwrap());
+        let ignored = in_package(
+            ModuleManager::orphans(&[String::from("tests_data.*.c")], &settings).unwrap(),
+        );
+
+        remove_dir_all("tests_data/test_orphans").unwrap();
+        // The package is only imported by its own children.
+        assert_eq!(
+            orphans,
+            vec![
+                (
+                    String::from("tests_data.test_orphans"),
+                    PathBuf::from("tests_data/test_orphans/__init__.py")
+                ),
+                (
+                    String::from("tests_data.test_orphans.c"),
+                    PathBuf::from("tests_data/test_orphans/c.py")
+                ),
+            ]
+        );
+        assert_eq!(ignored.len(), 1);
+    }
+
+    #[test]
+    fn test_conditional_defs() {
+        std::fs::write(
+            "tests_data/test_conditional.py",
+            "import sys\n\nif sys.platform == \"win32\":\n    def clear():\n        pass\n\n\
+             def helper():\n    pass\n\ndel helper\n",
+        )
+        .unwrap();
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_conditional", ModuleType::File, false).unwrap();
+        let parsed = module_manager.reload();
+        remove_file("tests_data/test_conditional.py").unwrap();
+        parsed.unwrap();
+
+        assert!(module_manager.functions.is_empty());
+        let filter = FindFilter::kinds(false, false, true, false, false);
+        assert!(module_manager.code_displays(&String::new(), &filter).is_empty());
+
+        let filter = FindFilter {
+            conditional: true,
+            ..filter
+        };
+        let displays = module_manager.code_displays(&String::new(), &filter);
+        assert_eq!(displays.len(), 1);
+        assert!(displays[0].contains("clear"));
+        assert!(displays[0].contains(&cformat!("<cyan>[conditional]</cyan>")));
+    }
+
+    #[test]
+    fn test_hide_decorators() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_duplicates", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
+        let filter = FindFilter::kinds(false, false, false, true, false);
+        let shown = module_manager.code_displays(&String::new(), &filter).concat();
+        assert!(shown.contains(&cformat!("    <red>@</red>property\n")));
+        assert!(shown.contains(&cformat!("    <red>@</red>value.setter\n")));
+
+        let filter = FindFilter {
+            hide_decorators: true,
+            ..filter
+        };
+        let hidden = module_manager.code_displays(&String::new(), &filter).concat();
+        assert!(!hidden.contains(&cformat!("<red>@</red>")));
+        assert_eq!(hidden.lines().count(), shown.lines().count() - 2);
+
+        // Hidden decorators can still be searched.
+        let filter = FindFilter {
+            decorators: true,
+            ..filter
+        };
+        let (found, _) = module_manager
+            .find(&String::from("property"), String::new(), &filter)
+            .unwrap();
+        assert!(found.concat().contains("property"));
+    }
+
+    #[test]
+    fn test_markdown_display() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_many_args", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
+        let markdown = module_manager.markdown_display(1, true, false, &FindFilter::all());
+        assert!(markdown.starts_with("# `tests_data.test_many_args`\n\n"));
+        assert!(markdown.contains(
+            "- function `small`\n\n  ```python\n  def small(a, b):\n  ```\n"
+        ));
+        assert!(markdown.contains("- class `Builder`\n"));
+        assert!(!markdown.contains('\x1b'));
+
+        let names = module_manager.markdown_display(2, false, false, &FindFilter::all());
+        assert!(names.starts_with("## `tests_data.test_many_args`\n\n- function `build`\n"));
+        assert!(!names.contains("```"));
+    }
+
+    #[test]
+    fn test_markdown_filters() {
+        let code = "A = 1\nb: int = 2\n\n\n@cache\ndef f():\n    pass\n\n\n\
+                    def g():\n    pass\n\n\nif DEBUG:\n    def h():\n        pass\n";
+        let module_manager =
+            ModuleManager::from_source(Path::new("test.py"), String::from(code)).unwrap();
+        let names = |filter: &FindFilter| {
+            let items = module_manager.markdown_items(filter);
+            items.into_iter().map(|(_, name, _)| name).collect::<Vec<_>>()
+        };
+
+        let filter = FindFilter::kinds(true, false, true, false, false);
+        assert_eq!(names(&filter), vec!["A", "b", "f", "g"]);
+        assert!(module_manager.markdown_items(&filter)[2].2.starts_with("@cache\ndef f"));
+
+        let filter = FindFilter {
+            hide_decorators: true,
+            conditional: true,
+            compact_vars: true,
+            reverse: true,
+            ..filter
+        };
+        assert_eq!(names(&filter), vec!["h", "g", "f", "A, b: int"]);
+        assert!(module_manager.markdown_items(&filter)[2].2.starts_with("def f"));
+
+        let markdown = module_manager.markdown_display(1, true, false, &filter);
+        assert!(markdown.ends_with("- variables `A, b: int`\n\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlinks() {
+        create_dir_all("tests_data/test_symlinks").unwrap();
+        File::create("tests_data/test_symlinks/__init__.py").unwrap();
+        std::os::unix::fs::symlink("../test_reexport", "tests_data/test_symlinks/linked").unwrap();
+        // Following a link to the parent directory must still end. The files
+        // reached through it are all of tests_data, left out of the comparison.
+        std::os::unix::fs::symlink("..", "tests_data/test_symlinks/loop").unwrap();
+
+        let walk = |follow_links| {
+            let mut files = ProjectWalker::new()
+                .root(Some(String::from("tests_data/test_symlinks")))
+                .follow_symlinks(follow_links)
+                .walk()
+                .map(|entry| entry.path)
+                .filter(|f| !f.starts_with("./tests_data/test_symlinks/loop"))
+                .map(|f| f.display().to_string())
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        };
+        let skipped = walk(false);
+        let followed = walk(true);
+
+        remove_dir_all("tests_data/test_symlinks").unwrap();
+
+        assert_eq!(skipped, vec!["./tests_data/test_symlinks/__init__.py"]);
+        assert_eq!(
+            followed,
+            vec![
+                "./tests_data/test_symlinks/__init__.py",
+                "./tests_data/test_symlinks/linked/__init__.py",
+                "./tests_data/test_symlinks/linked/impl.py",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_refresh() {
+        create_dir_all("tests_data/test_refresh").unwrap();
+        for file in ["__init__.py", "a.py", "b.py"] {
+            std::fs::write(format!("tests_data/test_refresh/{}", file), "x = 1\n").unwrap();
+        }
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_refresh", ModuleType::Directory, false).unwrap();
+        module_manager.reload().unwrap();
+        let unchanged = module_manager.refresh().unwrap();
+
+        // Touch a.py, moving its mtime forward so it differs even on coarse clocks.
+        let a = File::options()
+            .write(true)
+            .open("tests_data/test_refresh/a.py")
+            .unwrap();
+        a.set_modified(SystemTime::now() + std::time::Duration::from_secs(60)).unwrap();
+        let touched = module_manager.refresh().unwrap();
+
+        remove_dir_all("tests_data/test_refresh").unwrap();
+
+        assert!(unchanged.is_empty());
+        assert_eq!(touched, vec!["tests_data.test_refresh.a"]);
+        assert_eq!(module_manager.sub_modules.len(), 2);
+    }
+
+    #[test]
+    fn test_refresh_no_cache() {
+        create_dir_all("tests_data/test_refresh_no_cache").unwrap();
+        std::fs::write("tests_data/test_refresh_no_cache/__init__.py", "").unwrap();
+        std::fs::write("tests_data/test_refresh_no_cache/a.py", "x = 1\n").unwrap();
+
+        let load = |no_cache| {
+            let mut module_manager = ModuleManager::new(
+                "tests_data.test_refresh_no_cache",
+                ModuleType::Directory,
+                false,
+            )
+            .unwrap()
+            .with_no_cache(no_cache);
+            module_manager.reload().unwrap();
+            module_manager
+        };
+        let (mut cached, mut uncached) = (load(false), load(true));
+
+        // Edit a.py but keep its mtime, so the cached definitions look fresh.
+        let path = "tests_data/test_refresh_no_cache/a.py";
+        let modified = std::fs::metadata(path).unwrap().modified().unwrap();
+        std::fs::write(path, "y = 1\n").unwrap();
+        File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+
+        let stale = cached.refresh().unwrap();
+        let fresh = uncached.refresh().unwrap();
+        remove_dir_all("tests_data/test_refresh_no_cache").unwrap();
+
+        assert!(stale.is_empty());
+        assert_eq!(cached.sub_modules[0].vars[0].name, "x");
+        assert!(fresh.contains(&String::from("tests_data.test_refresh_no_cache.a")));
+        assert_eq!(uncached.sub_modules[0].vars[0].name, "y");
+    }
+
+    #[test]
+    fn test_rewrite_keeps_shebang() {
+        let contents = ModuleManager::read_file(Path::new("tests_data/test_shebang.py")).unwrap();
+
+        let rewritten = ModuleManager::rewrite_imports(&contents, "requests", "httpx").unwrap();
+        assert!(rewritten.starts_with("#!/usr/bin/env python\n# -*- coding: utf-8 -*-\n"));
+        assert!(rewritten.contains("import httpx\n"));
+
+        let round_trip = ModuleManager::rewrite_imports(&rewritten, "httpx", "requests").unwrap();
+        assert_eq!(round_trip, contents);
+    }
+
+    #[test]
+    fn test_rewrite_keeps_blank_lines() {
+        let contents = "import old.a\n\n\n\
+                        from old.b import (\n    x,\n\n    y,\n)\n\t\n\n\
+                        def f():\n    import  old.c  as c\n\n\n    return old.a.x   \r\n";
+
+        let rewritten = ModuleManager::rewrite_imports(contents, "old", "much_longer").unwrap();
+        assert_eq!(
+            rewritten,
+            "import much_longer.a\n\n\n\
+             from much_longer.b import (\n    x,\n\n    y,\n)\n\t\n\n\
+             def f():\n    import  much_longer.c  as c\n\n\n    return much_longer.a.x   \r\n"
+        );
+
+        let blank_lines = |code: &str| {
+            code.lines()
+                .enumerate()
+                .filter(|(_, line)| line.trim().is_empty())
+                .map(|(number, line)| (number, line.to_string()))
+                .collect::<Vec<(usize, String)>>()
+        };
+        assert_eq!(blank_lines(&rewritten), blank_lines(contents));
+
+        let shorter = ModuleManager::rewrite_imports(&rewritten, "much_longer", "o").unwrap();
+        assert_eq!(blank_lines(&shorter), blank_lines(contents));
+    }
+
+    #[test]
+    fn test_rewrite_keeps_line_endings() {
+        create_dir_all("tests_data/test_endings").unwrap();
+        std::fs::write("tests_data/test_endings/crlf.py", "import old\r\n\r\nold.f()\r\n").unwrap();
+        std::fs::write("tests_data/test_endings/no_newline.py", "from old import f\nf()").unwrap();
+
+        let scope = Some("tests_data/test_endings");
+        let files =
+            ModuleManager::replace_in_root("old", "new", scope, &ProjectSettings::default())
+                .unwrap();
+        let crlf = ModuleManager::read_file(Path::new("tests_data/test_endings/crlf.py")).unwrap();
+        let no_newline =
+            ModuleManager::read_file(Path::new("tests_data/test_endings/no_newline.py")).unwrap();
+
+        remove_dir_all("tests_data/test_endings").unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(crlf, "import new\r\n\r\nnew.f()\r\n");
+        assert_eq!(no_newline, "from new import f\nf()");
+
+        // Inserted line breaks follow the file's.
+        let edited = ModuleManager::apply_edits("a\r\nb", vec![(0..1, String::from("x\ny\n"))]);
+        assert_eq!(edited.unwrap(), "x\r\ny\r\n\r\nb");
+        let edited = ModuleManager::apply_edits("a\nb", vec![(2..3, String::from("c\n"))]);
+        assert_eq!(edited.unwrap(), "a\nc");
+    }
+
+    #[test]
+    fn test_parse_times() {
+        let recorded = |path: &Path| {
+            let slowest = ModuleManager::slowest_parses(usize::MAX);
+            slowest.iter().any(|(parsed, _)| parsed == path)
+        };
+        let (skipped, kept) = (Path::new("test_skipped.py"), Path::new("test_kept.py"));
+
+        ModuleManager::record_parse_time(skipped, Duration::from_millis(1), false);
+        ModuleManager::record_parse_time(kept, Duration::from_millis(1), true);
+        ModuleManager::log_slowest_parses(5);
+
+        assert!(!recorded(skipped));
+        assert!(recorded(kept));
+        assert!(ModuleManager::slowest_parses(1).len() <= 1);
+    }
+
+    #[test]
+    fn test_module_references() {
+        let contents = "import old\n# old.x\nprint('old.y')\nold.run()\n";
+        let references = ModuleManager::module_references(contents, "old").unwrap();
+
+        let lines = references
+            .iter()
+            .map(|r| line_of(&contents.to_string(), r.start))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![1, 4]);
+        assert!(ModuleManager::module_references(contents, "older").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rewrite_aliases() {
+        let aliased = "import old as o\n\no.func()\nold_name.x = 1\n";
+        assert_eq!(
+            ModuleManager::rewrite_imports(aliased, "old", "new").unwrap(),
+            "import new as o\n\no.func()\nold_name.x = 1\n"
+        );
+
+        let plain = "import old\n\nold.func()\n";
+        assert_eq!(
+            ModuleManager::rewrite_imports(plain, "old", "new").unwrap(),
+            "import new\n\nnew.func()\n"
+        );
+
+        // `old` is another module here, only the import of the real one changes.
+        let shadowed = "import other as old\nimport old.sub\n\nold.func()\n";
+        assert_eq!(
+            ModuleManager::rewrite_imports(shadowed, "old", "new").unwrap(),
+            "import other as old\nimport new.sub\n\nold.func()\n"
+        );
+
+        let from_import = "from pkg import old\n\nold.func()\n";
+        assert_eq!(
+            ModuleManager::rewrite_imports(from_import, "old", "new").unwrap(),
+            from_import
+        );
+    }
+
+    #[test]
+    fn test_rename_def() {
+        let path = Path::new("tests_data/test_rename_def.py");
+        std::fs::write(
+            path,
+            "def old(x):\n    return x\n\n\
+             y = old(1) + old(2)\n\
+             print('old', obj.old, f(old=3))  # old\n",
+        )
+        .unwrap();
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_rename_def", ModuleType::File, false).unwrap();
+        module_manager.parse().unwrap();
+        let count = module_manager.rename_def("old", "new").unwrap();
+        let taken = module_manager.rename_def("old", "y");
+
+        let content = ModuleManager::read_file(path).unwrap();
+        remove_file(path).unwrap();
+
+        assert_eq!(count, 3);
+        assert_eq!(
+            content,
+            "def new(x):\n    return x\n\n\
+             y = new(1) + new(2)\n\
+             print('old', obj.old, f(old=3))  # old\n"
+        );
+        assert!(taken.is_err());
+    }
+
+    #[test]
+    fn test_rename_def_scopes() {
+        let path = Path::new("tests_data/test_rename_def_scopes.py");
+        std::fs::write(
+            path,
+            "def old():\n    return 1\n\n\n\
+             def shadowing(old):\n    return old\n\n\n\
+             def local():\n    old = 2\n    return old\n\n\n\
+             class A:\n    def old(self):\n        return old() + obj.old()\n\n\n\
+             print(f\"{old()}\")\n",
+        )
+        .unwrap();
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_rename_def_scopes", ModuleType::File, false)
+                .unwrap();
+        module_manager.parse().unwrap();
+        let count = module_manager.rename_def("old", "new").unwrap();
+
+        let content = ModuleManager::read_file(path).unwrap();
+        remove_file(path).unwrap();
+
+        assert_eq!(count, 3);
+        assert_eq!(
+            content,
+            "def new():\n    return 1\n\n\n\
+             def shadowing(old):\n    return old\n\n\n\
+             def local():\n    old = 2\n    return old\n\n\n\
+             class A:\n    def old(self):\n        return new() + obj.old()\n\n\n\
+             print(f\"{new()}\")\n"
+        );
+    }
+
+    #[test]
+    fn test_namespace_packages() {
+        let namespace = Path::new("tests_data/test_namespace");
+        assert!(!ProjectSettings::default().is_namespace_package(namespace));
+
+        let settings = ProjectSettings {
+            namespace_packages: true,
+            ..ProjectSettings::default()
+        };
+        assert!(settings.is_namespace_package(namespace));
+        assert!(
+            ModuleManager::resolve("tests_data.test_namespace", &settings).unwrap()
+                == ModuleType::Directory
+        );
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_namespace", ModuleType::Directory, false)
+                .unwrap()
+                .with_namespace_packages(true);
+        module_manager.reload().unwrap();
+
+        let sub_modules = module_manager
+            .sub_modules()
+            .iter()
+            .map(|m| m.module())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sub_modules,
+            vec!["tests_data.test_namespace.b", "tests_data.test_namespace.sub"]
+        );
+        assert_eq!(
+            module_manager.sub_modules()[1].sub_modules()[0].module(),
+            "tests_data.test_namespace.sub.a"
+        );
+    }
+
+    #[test]
+    fn test_check_scope() {
+        let module_manager =
+            ModuleManager::new("tests_data.test_module", ModuleType::File, false).unwrap();
+
+        let absolute = std::env::current_dir().unwrap().join("tests_data");
+        let absolute = absolute.to_str().unwrap();
+
+        let inside = [".", "./tests_data", "tests_data//", "tests_data/../tests_data", absolute];
+        for scope in inside {
+            assert!(module_manager.check_scope(Some(scope)).is_ok(), "{}", scope);
+        }
+        for scope in ["tests_data/test_inheritance", "src"] {
+            assert!(module_manager.check_scope(Some(scope)).is_err(), "{}", scope);
+        }
+    }
+
+    #[test]
+    fn test_mv_scope() {
+        let import = "import tests_data.test_scope.inside.old_mod\n";
+        create_dir_all("tests_data/test_scope/inside").unwrap();
+        create_dir_all("tests_data/test_scope/outside").unwrap();
+        std::fs::write("tests_data/test_scope/inside/old_mod.py", "x = 1\n").unwrap();
+        std::fs::write("tests_data/test_scope/inside/user.py", import).unwrap();
+        std::fs::write("tests_data/test_scope/outside/user.py", import).unwrap();
 
-        if show_code {
-            let sub_prefix = format!("{}│  ", prefix);
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_scope.inside.old_mod", ModuleType::File, false)
+                .unwrap();
+        module_manager.parse().unwrap();
+        let outside = module_manager.clone().mv("x", Some("tests_data/test_scope/outside"));
+        module_manager
+            .mv(
+                "tests_data.test_scope.inside.new_mod",
+                Some("tests_data/test_scope/inside"),
+            )
+            .unwrap();
 
-            for function in self.functions.clone() {
-                print!("{}", function.find("", None, Some(&sub_prefix)))
-            }
+        let inside_user = ModuleManager::read_file(Path::new("tests_data/test_scope/inside/user.py"));
+        let outside_user =
+            ModuleManager::read_file(Path::new("tests_data/test_scope/outside/user.py"));
+        remove_dir_all("tests_data/test_scope").unwrap();
+
+        assert!(outside.is_err());
+        assert_eq!(
+            inside_user.unwrap(),
+            "import tests_data.test_scope.inside.new_mod\n"
+        );
+        assert_eq!(outside_user.unwrap(), import);
+    }
+
+    #[test]
+    fn test_collisions() {
+        create_dir_all("tests_data/test_collisions/sub").unwrap();
+        std::fs::write(
+            "tests_data/test_collisions/__init__.py",
+            "from os import path as helpers\n\n\ndef target():\n    pass\n",
+        )
+        .unwrap();
+        std::fs::write("tests_data/test_collisions/sub/__init__.py", "").unwrap();
+
+        let settings = ProjectSettings::default();
+        let target = ModuleManager::collisions("tests_data.test_collisions.target", &settings);
+        let helpers = ModuleManager::collisions("tests_data.test_collisions.helpers", &settings);
+        let sub = ModuleManager::collisions("tests_data.test_collisions.sub", &settings);
+        let free = ModuleManager::collisions("tests_data.test_collisions.free", &settings);
+        let missing = ModuleManager::collisions("tests_data.test_missing.target", &settings);
+        remove_dir_all("tests_data/test_collisions").unwrap();
+
+        assert_eq!(
+            target.unwrap(),
+            vec!["tests_data.test_collisions already defines target"]
+        );
+        assert_eq!(
+            helpers.unwrap(),
+            vec!["tests_data.test_collisions already imports helpers"]
+        );
+        assert_eq!(
+            sub.unwrap(),
+            vec!["module tests_data.test_collisions.sub already exists"]
+        );
+        assert!(free.unwrap().is_empty());
+        assert!(missing.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_walk_defs() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_inheritance", ModuleType::Directory, false)
+                .unwrap();
+        module_manager.reload().unwrap();
+
+        let defs = module_manager.walk_defs().collect::<Vec<DefRef>>();
+        assert_eq!(defs.len(), 4);
+        assert_eq!(
+            defs.iter()
+                .map(|d| format!("{}.{}", d.module().module(), d.name()))
+                .collect::<Vec<String>>(),
+            vec![
+                "tests_data.test_inheritance.base.Root",
+                "tests_data.test_inheritance.base.Base",
+                "tests_data.test_inheritance.child.Child",
+                "tests_data.test_inheritance.child.Other",
+            ]
+        );
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_many_args", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
 
-            for class in self.classes.clone() {
-                print!("{}", class.find("", None, Some(&sub_prefix)))
+        let defs = module_manager.walk_defs().collect::<Vec<DefRef>>();
+        assert_eq!(defs.len(), 4);
+        match defs[1] {
+            DefRef::Method { class, method, .. } => {
+                assert_eq!(class.name, "Builder");
+                assert_eq!(method.name, "configure");
             }
+            _ => panic!("expected the method of Builder"),
         }
+    }
 
-        if self.module_type == ModuleType::Directory {
-            for sub_module in &self.sub_modules {
-                sub_module.mprint(format!("{}│  ", prefix), show_code);
-            }
+    #[test]
+    fn test_mv_as() {
+        create_dir_all("tests_data/test_convert/a").unwrap();
+        std::fs::write("tests_data/test_convert/__init__.py", "").unwrap();
+        std::fs::write("tests_data/test_convert/a/__init__.py", "").unwrap();
+        std::fs::write("tests_data/test_convert/a/helpers.py", "x = 1\n").unwrap();
+        std::fs::write(
+            "tests_data/test_convert/a/b.py",
+            "from .helpers import x\nfrom .. import a\n",
+        )
+        .unwrap();
+        std::fs::write(
+            "tests_data/test_convert/user.py",
+            "from tests_data.test_convert.a.b import x\n",
+        )
+        .unwrap();
+        let scope = Some("tests_data/test_convert");
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_convert.a.b", ModuleType::File, false).unwrap();
+        module_manager.parse().unwrap();
+        module_manager
+            .mv_as("tests_data.test_convert.c", ModuleType::Directory, scope)
+            .unwrap();
+        let package = ModuleManager::read_file(Path::new("tests_data/test_convert/c/__init__.py"));
+        let package_user = ModuleManager::read_file(Path::new("tests_data/test_convert/user.py"));
+        let file_left = Path::new("tests_data/test_convert/a/b.py").exists();
+
+        module_manager
+            .mv_as("tests_data.test_convert.d", ModuleType::File, scope)
+            .unwrap();
+        let file = ModuleManager::read_file(Path::new("tests_data/test_convert/d.py"));
+        let file_user = ModuleManager::read_file(Path::new("tests_data/test_convert/user.py"));
+        let package_left = Path::new("tests_data/test_convert/c").exists();
+
+        let mut crowded =
+            ModuleManager::new("tests_data.test_convert.a", ModuleType::Directory, false).unwrap();
+        crowded.parse().unwrap();
+        let refused = crowded.mv_as("tests_data.test_convert.e", ModuleType::File, scope);
+        remove_dir_all("tests_data/test_convert").unwrap();
+
+        assert_eq!(
+            package.unwrap(),
+            "from tests_data.test_convert.a.helpers import x\nfrom tests_data.test_convert import a\n"
+        );
+        assert_eq!(package_user.unwrap(), "from tests_data.test_convert.c import x\n");
+        assert!(!file_left);
+        assert_eq!(module_manager.module_type(), &ModuleType::File);
+        assert_eq!(
+            file.unwrap(),
+            "from tests_data.test_convert.a.helpers import x\nfrom tests_data.test_convert import a\n"
+        );
+        assert_eq!(file_user.unwrap(), "from tests_data.test_convert.d import x\n");
+        assert!(!package_left);
+        assert!(refused.is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        create_dir_all("tests_data/test_merge/a").unwrap();
+        create_dir_all("tests_data/test_merge/b").unwrap();
+        std::fs::write("tests_data/test_merge/__init__.py", "").unwrap();
+        std::fs::write("tests_data/test_merge/a/__init__.py", "").unwrap();
+        std::fs::write("tests_data/test_merge/a/x.py", "x = 1\n").unwrap();
+        std::fs::write("tests_data/test_merge/b/__init__.py", "").unwrap();
+        std::fs::write("tests_data/test_merge/b/y.py", "y = 1\n").unwrap();
+        std::fs::write(
+            "tests_data/test_merge/user.py",
+            "from tests_data.test_merge.a.x import x\n",
+        )
+        .unwrap();
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_merge.a", ModuleType::Directory, false).unwrap();
+        module_manager.parse().unwrap();
+        let merged = module_manager.merge("tests_data.test_merge.b", None);
+
+        let a_exists = Path::new("tests_data/test_merge/a").exists();
+        let x_exists = Path::new("tests_data/test_merge/b/x.py").exists();
+        let y_exists = Path::new("tests_data/test_merge/b/y.py").exists();
+        let user = ModuleManager::read_file(Path::new("tests_data/test_merge/user.py"));
+
+        // Only the conflicting file stays behind, the rest is merged.
+        create_dir_all("tests_data/test_merge/c").unwrap();
+        std::fs::write("tests_data/test_merge/c/__init__.py", "").unwrap();
+        std::fs::write("tests_data/test_merge/c/y.py", "y = 2\n").unwrap();
+        std::fs::write("tests_data/test_merge/c/z.py", "z = 1\n").unwrap();
+        std::fs::write(
+            "tests_data/test_merge/user.py",
+            "from tests_data.test_merge.c.y import y\nfrom tests_data.test_merge.c.z import z\n",
+        )
+        .unwrap();
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_merge.c", ModuleType::Directory, false).unwrap();
+        module_manager.parse().unwrap();
+        let conflicts = module_manager.merge("tests_data.test_merge.b", None);
+
+        let mut left = ModuleManager::package_files(Path::new("tests_data/test_merge/c"));
+        left.sort();
+        let z_exists = Path::new("tests_data/test_merge/b/z.py").exists();
+        let y_merged = ModuleManager::read_file(Path::new("tests_data/test_merge/b/y.py"));
+        let partial_user = ModuleManager::read_file(Path::new("tests_data/test_merge/user.py"));
+        remove_dir_all("tests_data/test_merge").unwrap();
+
+        assert!(merged.unwrap().is_empty());
+        assert!(!a_exists);
+        assert!(x_exists);
+        assert!(y_exists);
+        assert_eq!(user.unwrap(), "from tests_data.test_merge.b.x import x\n");
+
+        assert_eq!(conflicts.unwrap(), vec![PathBuf::from("y.py")]);
+        assert_eq!(
+            left,
+            vec![
+                PathBuf::from("tests_data/test_merge/c/__init__.py"),
+                PathBuf::from("tests_data/test_merge/c/y.py")
+            ]
+        );
+        assert!(z_exists);
+        assert_eq!(y_merged.unwrap(), "y = 1\n");
+        assert_eq!(
+            partial_user.unwrap(),
+            "from tests_data.test_merge.c.y import y\nfrom tests_data.test_merge.b.z import z\n"
+        );
+    }
 
-            println!("{}│  *", prefix);
+    #[test]
+    fn test_find_project_root() {
+        create_dir_all("tests_data/test_project_root/pkg/deep").unwrap();
+        File::create("tests_data/test_project_root/pyproject.toml").unwrap();
+
+        let nested = ModuleManager::find_project_root(Path::new(
+            "tests_data/test_project_root/pkg/deep",
+        ));
+        let root = ModuleManager::find_project_root(Path::new("tests_data/test_project_root"));
+        remove_dir_all("tests_data/test_project_root").unwrap();
+
+        assert_eq!(nested, Some(PathBuf::from("tests_data/test_project_root")));
+        assert_eq!(root, Some(PathBuf::from("tests_data/test_project_root")));
+    }
+
+    #[test]
+    fn test_is_wide_root() {
+        assert!(ModuleManager::is_wide_root(Path::new("/")));
+        assert!(!ModuleManager::is_wide_root(Path::new("tests_data")));
+        if let Some(home) = std::env::var_os("HOME") {
+            assert!(ModuleManager::is_wide_root(Path::new(&home)));
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::fs::{remove_dir_all, remove_file};
+    #[test]
+    fn test_changed_python_files() {
+        let diff = "README.md\npkg/a.py\npkg/removed.py\n\nscripts/run.py\n";
+        let files = vec![
+            PathBuf::from("./pkg/a.py"),
+            PathBuf::from("./pkg/b.py"),
+            PathBuf::from("./scripts/run.py"),
+        ];
 
-    use super::*;
+        let changed = ModuleManager::changed_python_files(diff, files.into_iter());
+        assert_eq!(
+            changed,
+            HashSet::from([PathBuf::from("pkg/a.py"), PathBuf::from("scripts/run.py")])
+        );
+    }
 
     #[test]
-    fn test_create() {
+    fn test_diff_file_pairs() {
+        let diff = "M\tpkg/a.py\nA\tpkg/new.py\nD\tpkg/old.py\nR093\tpkg/b.py\tpkg/c.py\n\
+                    M\tREADME.md\nR100\tnotes.txt\tpkg/notes.py\n";
+
+        let pairs = ModuleManager::diff_file_pairs(diff);
+        let path = |path: &str| Some(PathBuf::from(path));
+        assert_eq!(
+            pairs,
+            vec![
+                (path("pkg/a.py"), path("pkg/a.py")),
+                (None, path("pkg/new.py")),
+                (path("pkg/old.py"), None),
+                (path("pkg/b.py"), path("pkg/c.py")),
+                (None, path("pkg/notes.py")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_template() {
+        create_dir_all("tests_data/test_template/tests").unwrap();
+        create_dir_all("tests_data/test_templated").unwrap();
+        std::fs::write(
+            "tests_data/test_template/__init__.py",
+            "\"\"\"The {module} package.\"\"\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            "tests_data/test_template/tests/test_{module}.py",
+            "import {module}\n",
+        )
+        .unwrap();
+        std::fs::write("tests_data/test_templated/__init__.py", "x = 1\n").unwrap();
+
         let module_manager =
-            ModuleManager::new("tests_data.test_create", ModuleType::File, false).unwrap();
-        assert_eq!(module_manager.module, "tests_data.test_create");
-        assert_eq!(module_manager.path, PathBuf::from("tests_data/test_create.py"));
+            ModuleManager::new("tests_data.test_templated", ModuleType::Directory, false)
+                .unwrap();
+        let applied = module_manager.apply_template(Path::new("tests_data/test_template"), false);
+
+        let init = ModuleManager::read_file(Path::new("tests_data/test_templated/__init__.py"));
+        let test = ModuleManager::read_file(Path::new(
+            "tests_data/test_templated/tests/test_tests_data_test_templated.py",
+        ));
+        remove_dir_all("tests_data/test_template").unwrap();
+        remove_dir_all("tests_data/test_templated").unwrap();
+
+        let (created, skipped) = applied.unwrap();
+        assert_eq!(
+            created,
+            vec![PathBuf::from(
+                "tests_data/test_templated/tests/test_tests_data_test_templated.py"
+            )]
+        );
+        assert_eq!(skipped, vec![PathBuf::from("tests_data/test_templated/__init__.py")]);
+        assert_eq!(init.unwrap(), "x = 1\n");
+        assert_eq!(test.unwrap(), "import tests_data.test_templated\n");
+    }
 
+    #[test]
+    fn test_create_test_file() {
         let module_manager =
-            ModuleManager::new("tests_data.test_create", ModuleType::Directory, false).unwrap();
-        assert_eq!(module_manager.module, "tests_data.test_create");
+            ModuleManager::new("tests_data.test_with_test", ModuleType::File, true).unwrap();
+        let tests_dir = Path::new("tests_data/test_with_test_tests");
+
+        let created = module_manager.create_test_file(tests_dir).unwrap();
+        let contents = std::fs::read_to_string(tests_dir.join("test_test_with_test.py")).unwrap();
+        let module_exists = module_manager.path.is_file();
+        let again = module_manager.create_test_file(tests_dir).unwrap();
+
+        remove_file("tests_data/test_with_test.py").unwrap();
+        remove_dir_all(tests_dir).unwrap();
+        assert!(module_exists);
+        assert_eq!(created, Some(tests_dir.join("test_test_with_test.py")));
+        assert!(contents.starts_with("from tests_data import test_with_test\n"));
+        assert!(contents.contains("def test_test_with_test():"));
+        assert_eq!(again, None);
+    }
+
+    #[test]
+    fn test_force_template() {
+        create_dir_all("tests_data/test_force_template").unwrap();
+        create_dir_all("tests_data/test_forced").unwrap();
+        std::fs::write(
+            "tests_data/test_force_template/__init__.py",
+            "name = '{module}'\n",
+        )
+        .unwrap();
+        std::fs::write("tests_data/test_force_template/other.py", "y = 2\n").unwrap();
+        std::fs::write("tests_data/test_forced/__init__.py", "x = 1\n").unwrap();
+        std::fs::write("tests_data/test_forced/other.py", "x = 1\n").unwrap();
+
+        let module_manager =
+            ModuleManager::new("tests_data.test_forced", ModuleType::Directory, false).unwrap();
+        let template = Path::new("tests_data/test_force_template");
+        let (kept_created, _) = module_manager.apply_template(template, false).unwrap();
+        let kept = ModuleManager::read_file(&module_manager.path).unwrap();
+        let (forced_created, forced_skipped) =
+            module_manager.apply_template(template, true).unwrap();
+        let forced = ModuleManager::read_file(&module_manager.path).unwrap();
+        let other = ModuleManager::read_file(Path::new("tests_data/test_forced/other.py"));
+        let reset = module_manager.reset().unwrap();
+        let emptied = ModuleManager::read_file(&module_manager.path).unwrap();
+
+        remove_dir_all("tests_data/test_force_template").unwrap();
+        remove_dir_all("tests_data/test_forced").unwrap();
+        assert!(kept_created.is_empty());
+        assert_eq!(kept, "x = 1\n");
+        assert_eq!(forced_created, vec![PathBuf::from("tests_data/test_forced/__init__.py")]);
+        assert_eq!(forced_skipped, vec![PathBuf::from("tests_data/test_forced/other.py")]);
+        assert_eq!(forced, "name = 'tests_data.test_forced'\n");
+        assert_eq!(other.unwrap(), "x = 1\n");
+        assert!(reset);
+        assert_eq!(emptied, "");
+    }
+
+    #[test]
+    fn test_unused_imports() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_unused_imports", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
         assert_eq!(
-            module_manager.path,
-            PathBuf::from("tests_data/test_create/__init__.py")
+            module_manager.unused_imports().unwrap(),
+            vec!["tests_data/test_unused_imports.py:1: `Root` is imported but never used"]
         );
     }
 
     #[test]
-    fn test_build() {
+    fn test_find_records() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_many_args", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
+        let mut lines = Vec::new();
+        module_manager
+            .find_records("build", &FindFilter::all(), &mut |record| {
+                lines.push(serde_json::to_string(&record).unwrap())
+            })
+            .unwrap();
+
+        assert_eq!(lines.len(), 1);
+        assert!(!lines[0].contains('\u{1b}'));
+        let record: FindRecord = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(record.kind, "function");
+        assert_eq!(record.name, "build");
+        assert_eq!(record.line, 1);
+        assert_eq!(record.path, "tests_data/test_many_args.py");
+    }
+
+    #[test]
+    fn test_find_records_signatures() {
+        let code = "def view(request: Request):\n    pass\n\n\ndef other(x):\n    pass\n\n\n\
+                    class Api:\n    def get(self, request: Request, id: int):\n        pass\n";
         let module_manager =
-            ModuleManager::new("tests_data.test_build_file", ModuleType::File, false).unwrap();
-        module_manager.build().unwrap();
-        assert!(module_manager.path.exists());
+            ModuleManager::from_source(Path::new("test.py"), String::from(code)).unwrap();
+        let filter = FindFilter {
+            signatures: true,
+            ..FindFilter::default()
+        };
+
+        let mut records = Vec::new();
+        module_manager
+            .find_records("request: Request", &filter, &mut |record| records.push(record))
+            .unwrap();
+
+        let found = records
+            .iter()
+            .map(|r| (r.kind.as_str(), r.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![("function", "view"), ("method", "Api.get")]);
+    }
 
+    #[test]
+    fn test_find_records_decorators() {
+        let code = "@app.route(\"/\")\ndef index():\n    pass\n\n\ndef plain():\n    pass\n\n\n\
+                    class Api:\n    @app.route(\"/api\")\n    def get(self):\n        pass\n";
         let module_manager =
-            ModuleManager::new("tests_data.test_build_dir", ModuleType::Directory, false).unwrap();
-        module_manager.build().unwrap();
-        assert!(module_manager.path.exists());
+            ModuleManager::from_source(Path::new("test.py"), String::from(code)).unwrap();
+        let filter = FindFilter {
+            decorators: true,
+            ..FindFilter::default()
+        };
+
+        let mut records = Vec::new();
+        module_manager
+            .find_records("app.route", &filter, &mut |record| records.push(record))
+            .unwrap();
+
+        let found = records
+            .iter()
+            .map(|r| (r.kind.as_str(), r.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![("function", "index"), ("method", "Api.get")]);
+    }
+
+    #[test]
+    fn test_count_per_module() {
+        let mut records = Vec::new();
+        for module in ["tests_data.test_module", "tests_data.test_many_args"] {
+            let mut module_manager = ModuleManager::new(module, ModuleType::File, false).unwrap();
+            module_manager.reload().unwrap();
+            module_manager
+                .find_records("test_function", &FindFilter::all(), &mut |record| {
+                    records.push(record)
+                })
+                .unwrap();
+            module_manager
+                .find_records("build", &FindFilter::all(), &mut |record| records.push(record))
+                .unwrap();
+        }
+
+        let counts = ModuleManager::count_per_module(&records);
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[0].0, "tests_data.test_module");
+        assert_eq!(counts[1], (String::from("tests_data.test_many_args"), 1));
+        assert_eq!(counts.iter().map(|(_, count)| count).sum::<usize>(), records.len());
+    }
+
+    #[test]
+    fn test_flat_display() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_module", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
+        let mut records = Vec::new();
+        module_manager
+            .find_records("test_function", &FindFilter::all(), &mut |record| records.push(record))
+            .unwrap();
+
+        let display = ModuleManager::flat_display(&records);
+        assert!(records.len() >= 2);
+        assert_eq!(display.matches("tests_data/test_module.py").count(), 1);
+        assert!(display.contains("def test_function2(a, b: str, *, d: int):"));
+        assert_eq!(display.lines().count(), records.len() + 1);
+    }
+
+    #[test]
+    fn test_create_tree() {
+        let spec = "tests_data/  # the tests\n\
+                    \x20   test_spec/\n\
+                    \x20       models/\n\
+                    \x20           user\n\
+                    \x20       views\n\
+                    \x20   test_module\n";
+
+        let modules = ModuleManager::parse_spec(spec).unwrap();
+        assert_eq!(
+            modules
+                .iter()
+                .map(|(module, _)| module.as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "tests_data",
+                "tests_data.test_spec",
+                "tests_data.test_spec.models",
+                "tests_data.test_spec.models.user",
+                "tests_data.test_spec.views",
+                "tests_data.test_module",
+            ]
+        );
+
+        let tree = ModuleManager::create_tree(&modules, &ProjectSettings::default());
+        let user_exists = Path::new("tests_data/test_spec/models/user.py").exists();
+        let models_exists = Path::new("tests_data/test_spec/models/__init__.py").exists();
+        let views_exists = Path::new("tests_data/test_spec/views.py").exists();
+        remove_dir_all("tests_data/test_spec").unwrap();
+
+        let (created, existing) = tree.unwrap();
+        assert_eq!(created.len(), 4);
+        assert_eq!(
+            existing,
+            vec![
+                PathBuf::from("tests_data/__init__.py"),
+                PathBuf::from("tests_data/test_module.py"),
+            ]
+        );
+        assert!(user_exists);
+        assert!(models_exists);
+        assert!(views_exists);
+
+        assert!(ModuleManager::parse_spec("pkg/\n    class\n").is_err());
+        assert!(ModuleManager::parse_spec("file\n    child\n").is_err());
+    }
+
+    #[test]
+    fn test_group_by_kind() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_module", ModuleType::File, false).unwrap();
+        module_manager.reload().unwrap();
+
+        let filter = FindFilter {
+            group_by_kind: true,
+            ..FindFilter::all()
+        };
+        let prefix = String::from("│  ");
+        let displays = module_manager.code_displays(&prefix, &filter);
+
+        assert_eq!(displays[0], cformat!("│  <bold>Classes</bold> (2)\n"));
+        assert!(displays[1].starts_with("│  │  "));
+        assert!(displays[1].contains("TestClass"));
+        assert_eq!(displays[3], cformat!("│  <bold>Functions</bold> (3)\n"));
+        assert!(displays[4].starts_with("│  │  "));
+        assert_eq!(displays[7], cformat!("│  <bold>Variables</bold> (3)\n"));
+        assert_eq!(displays.len(), 11);
+    }
+
+    #[test]
+    fn test_parse_error_badge() {
+        create_dir_all("tests_data/test_broken").unwrap();
+        std::fs::write("tests_data/test_broken/__init__.py", "").unwrap();
+        std::fs::write("tests_data/test_broken/broken.py", "def f(:\n").unwrap();
+        std::fs::write("tests_data/test_broken/ok.py", "x = 1\n").unwrap();
+
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_broken", ModuleType::Directory, false).unwrap();
+        let reloaded = module_manager.reload();
+        remove_dir_all("tests_data/test_broken").unwrap();
+        reloaded.unwrap();
+
+        let tree = module_manager.tree_display(String::new(), false, false, &FindFilter::all());
+        assert!(tree.contains(&cformat!(
+            "📄 <green>broken</green> <yellow>⚠ parse error</yellow>\n"
+        )));
+        assert!(tree.contains(&cformat!("📄 <green>ok</green>\n")));
+        assert!(module_manager.parse_error().is_none());
+        assert!(module_manager.sub_modules()[0].parse_error().is_some());
+    }
+
+    #[test]
+    fn test_apply_edits() {
+        let contents = "import  old.sub   # keep  this\n\n\tx = old.f( 1 )\n";
+        let edits = vec![(37..40, String::from("new")), (8..11, String::from("new"))];
+
+        assert_eq!(
+            ModuleManager::apply_edits(contents, edits).unwrap(),
+            "import  new.sub   # keep  this\n\n\tx = new.f( 1 )\n"
+        );
+        assert_eq!(
+            ModuleManager::rewrite_imports(contents, "old", "new").unwrap(),
+            "import  new.sub   # keep  this\n\n\tx = new.f( 1 )\n"
+        );
+
+        let overlapping = vec![(0..5, String::new()), (3..8, String::new())];
+        assert!(ModuleManager::apply_edits(contents, overlapping).is_err());
     }
 
     #[test]
@@ -602,7 +5925,7 @@ mod tests {
     fn test_mv() {
         let mut module_manager =
             ModuleManager::new("tests_data.test_mv", ModuleType::File, true).unwrap();
-        module_manager.mv("tests_data.test_mv2").unwrap();
+        module_manager.mv("tests_data.test_mv2", None).unwrap();
         assert_eq!(module_manager.module, "tests_data.test_mv2");
         assert_eq!(module_manager.path, PathBuf::from("tests_data/test_mv2.py"));
 
@@ -610,7 +5933,7 @@ mod tests {
             .expect("Could not read file");
         assert_eq!(check_content, "from tests_data.test_mv2 import *\nimport tests_data.test_mv2.abc as abc\ntest_var:tests_data.test_mv2.abc.ABC = tests_data.test_mv2.abc.ABC()");
 
-        module_manager.mv("tests_data.test_mv").unwrap();
+        module_manager.mv("tests_data.test_mv", None).unwrap();
         assert_eq!(module_manager.module, "tests_data.test_mv");
         assert_eq!(module_manager.path, PathBuf::from("tests_data/test_mv.py"));
 
@@ -620,7 +5943,7 @@ mod tests {
 
         let mut module_manager =
             ModuleManager::new("tests_data.test_mv", ModuleType::Directory, true).unwrap();
-        module_manager.mv("tests_data.test_mv2").unwrap();
+        module_manager.mv("tests_data.test_mv2", None).unwrap();
         assert_eq!(module_manager.module, "tests_data.test_mv2");
         assert_eq!(
             module_manager.path,
@@ -631,7 +5954,7 @@ mod tests {
             .expect("Could not read file");
         assert_eq!(check_content, "from tests_data.test_mv2 import *\nimport tests_data.test_mv2.abc as abc\ntest_var:tests_data.test_mv2.abc.ABC = tests_data.test_mv2.abc.ABC()");
 
-        module_manager.mv("tests_data.test_mv").unwrap();
+        module_manager.mv("tests_data.test_mv", None).unwrap();
         assert_eq!(module_manager.module, "tests_data.test_mv");
         assert_eq!(
             module_manager.path,
@@ -1,10 +1,16 @@
 use std::process::Command;
 use regex::Regex;
 
-fn extract_version(output: String) -> String {
-    let re = Regex::new(r"(\d+\.\d+\.\d+)").unwrap();
-    let caps = re.captures(&output).unwrap();
-    return caps[1].to_string();
+/// Finds the version in the `--version` output of a tool, e.g. `3.12.0` or
+/// a pre-release such as `3.12.0rc1`. `None` if there is no version in it.
+fn extract_version(output: &str) -> Option<String> {
+    let re = Regex::new(r"\d+\.\d+(?:\.\d+)?(?:(?:a|b|rc|\.?dev|\.?post)\d*)?").ok()?;
+    re.find(output).map(|m| m.as_str().to_string())
+}
+
+/// The version of a tool, or "unknown" when it cannot be read.
+fn version_or_unknown(output: &str) -> String {
+    extract_version(output).unwrap_or("unknown".to_string())
 }
 
 pub fn check_poetry() -> (bool, String) {
@@ -15,7 +21,7 @@ pub fn check_poetry() -> (bool, String) {
             let output = String::from_utf8_lossy(&output.stdout);
 
 
-            (output.contains("Poetry"), extract_version(output.to_string()))
+            (output.contains("Poetry"), version_or_unknown(&output))
         }
         Err(_) => (false, "Not installed".to_string()),
     };
@@ -27,8 +33,41 @@ pub fn check_python() -> (bool, String) {
     return match output {
         Ok(output) => {
             let output = String::from_utf8_lossy(&output.stdout);
-            (output.contains("Python"), extract_version(output.to_string()))
+            (output.contains("Python"), version_or_unknown(&output))
         }
         Err(_) => (false, "Not installed".to_string()),
     };
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_version() {
+        assert_eq!(extract_version("Python 3.11.4\n"), Some("3.11.4".to_string()));
+        assert_eq!(
+            extract_version("Poetry (version 1.8.2)\n"),
+            Some("1.8.2".to_string())
+        );
+        assert_eq!(extract_version("Python 3.12"), Some("3.12".to_string()));
+    }
+
+    #[test]
+    fn test_extract_pre_release_version() {
+        assert_eq!(extract_version("Python 3.12.0rc1"), Some("3.12.0rc1".to_string()));
+        assert_eq!(extract_version("Python 3.13.0a2+"), Some("3.13.0a2".to_string()));
+        assert_eq!(
+            extract_version("Poetry (version 2.0.0.dev0)"),
+            Some("2.0.0.dev0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_malformed_version() {
+        assert_eq!(extract_version(""), None);
+        assert_eq!(extract_version("Befehl nicht gefunden"), None);
+        assert_eq!(extract_version("Python version 3"), None);
+        assert_eq!(version_or_unknown("Python ?"), "unknown");
+    }
+}
@@ -1,2 +1,3 @@
 pub mod command;
-pub mod check;
\ No newline at end of file
+pub mod check;
+pub mod pyproject;
\ No newline at end of file
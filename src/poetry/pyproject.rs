@@ -0,0 +1,132 @@
+use failure::{Error, ResultExt};
+use std::path::Path;
+use toml_edit::{Document, Value};
+
+/// Renames a package path (`old/pkg`) or any path below it.
+fn rename_path(value: &str, old: &str, new: &str) -> Option<String> {
+    if value == old {
+        return Some(new.to_string());
+    }
+
+    match value.strip_prefix(&format!("{}/", old)) {
+        Some(rest) => Some(format!("{}/{}", new, rest)),
+        None => None,
+    }
+}
+
+/// Renames a string value in place, keeping its surrounding formatting.
+fn rename_value(value: &mut Value, old: &str, new: &str) -> bool {
+    let renamed = match value.as_str() {
+        Some(current) => rename_path(current, old, new),
+        None => None,
+    };
+
+    match renamed {
+        Some(renamed) => {
+            let decor = value.decor().clone();
+            *value = Value::from(renamed);
+            *value.decor_mut() = decor;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Updates the `[tool.poetry]` `packages` and `include` entries of a
+/// `pyproject.toml` that point at the `old` module so they point at `new`.
+/// Returns whether the file was changed.
+pub fn update_packages(pyproject: &Path, old: &str, new: &str) -> Result<bool, Error> {
+    let contents = std::fs::read_to_string(pyproject)
+        .with_context(|e| format!("Could not read file {}: {}", pyproject.display(), e))?;
+
+    let mut document = contents
+        .parse::<Document>()
+        .with_context(|e| format!("Could not parse {}: {}", pyproject.display(), e))?;
+
+    let old = old.replace(".", "/");
+    let new = new.replace(".", "/");
+    let mut changed = false;
+
+    let poetry = match document.get_mut("tool").and_then(|t| t.get_mut("poetry")) {
+        Some(poetry) => poetry,
+        None => return Ok(false),
+    };
+
+    if let Some(packages) = poetry.get_mut("packages") {
+        if let Some(packages) = packages.as_array_mut() {
+            for package in packages.iter_mut() {
+                if let Some(include) = package
+                    .as_inline_table_mut()
+                    .and_then(|t| t.get_mut("include"))
+                {
+                    changed |= rename_value(include, &old, &new);
+                }
+            }
+        } else if let Some(packages) = packages.as_array_of_tables_mut() {
+            for package in packages.iter_mut() {
+                if let Some(include) = package.get_mut("include").and_then(|i| i.as_value_mut()) {
+                    changed |= rename_value(include, &old, &new);
+                }
+            }
+        }
+    }
+
+    if let Some(includes) = poetry.get_mut("include").and_then(|i| i.as_array_mut()) {
+        for include in includes.iter_mut() {
+            match include.as_inline_table_mut() {
+                Some(table) => {
+                    if let Some(path) = table.get_mut("path") {
+                        changed |= rename_value(path, &old, &new);
+                    }
+                }
+                None => changed |= rename_value(include, &old, &new),
+            }
+        }
+    }
+
+    if changed {
+        std::fs::write(pyproject, document.to_string())
+            .with_context(|e| format!("Could not write to file {}: {}", pyproject.display(), e))?;
+    }
+
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_packages() {
+        let path = Path::new("tests_data/test_pyproject.toml");
+        std::fs::write(
+            path,
+            r#"[tool.poetry]
+name = "demo"
+packages = [
+    { include = "old_pkg" },  # the main package
+    { include = "other" },
+]
+include = ["old_pkg/data/*.json"]
+"#,
+        )
+        .unwrap();
+
+        let changed = update_packages(path, "old_pkg", "new_pkg").unwrap();
+        let content = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert!(changed);
+        assert_eq!(
+            content,
+            r#"[tool.poetry]
+name = "demo"
+packages = [
+    { include = "new_pkg" },  # the main package
+    { include = "other" },
+]
+include = ["new_pkg/data/*.json"]
+"#
+        );
+    }
+}
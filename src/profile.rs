@@ -0,0 +1,101 @@
+//! The time spent in each phase of a run, printed with `--profile`.
+//!
+//! Nothing is measured unless `enable` was called, so the timers cost a
+//! single atomic load otherwise.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// Listing the Python files of the project.
+    Walk,
+    /// Reading the files.
+    Read,
+    /// Parsing the source into definitions.
+    Parse,
+    /// Rendering the results.
+    Render,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [Phase::Walk, Phase::Read, Phase::Parse, Phase::Render];
+
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            Phase::Walk => "walk",
+            Phase::Read => "read",
+            Phase::Parse => "parse",
+            Phase::Render => "render",
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Nanoseconds spent in each phase, in the order of `Phase::ALL`.
+static TOTALS: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Runs `f`, adding the time it takes to `phase` when profiling.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !is_enabled() {
+        return f();
+    }
+
+    let started = Instant::now();
+    let result = f();
+    add(phase, started.elapsed());
+    result
+}
+
+pub fn add(phase: Phase, elapsed: Duration) {
+    TOTALS[phase as usize].fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+}
+
+pub fn total(phase: Phase) -> Duration {
+    Duration::from_nanos(TOTALS[phase as usize].load(Ordering::Relaxed))
+}
+
+/// The time of each phase and their sum, one per line.
+pub fn report() -> String {
+    let mut report = format!("{:<8} {:>12}\n", "phase", "time");
+    let mut sum = Duration::ZERO;
+    for phase in Phase::ALL {
+        sum += total(phase);
+        report.push_str(&format!("{:<8} {:>12.3?}\n", phase.name(), total(phase)));
+    }
+    report.push_str(&format!("{:<8} {:>12.3?}\n", "total", sum));
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        enable();
+        time(Phase::Parse, || std::thread::sleep(Duration::from_millis(2)));
+
+        let report = report();
+        for phase in Phase::ALL {
+            assert!(report.lines().any(|l| l.starts_with(phase.name())));
+        }
+        assert!(total(Phase::Parse) >= Duration::from_millis(2));
+        assert!(report.lines().last().unwrap().starts_with("total"));
+    }
+}
@@ -0,0 +1,255 @@
+//! The traversal of the project's Python files, shared by every command so
+//! depth, include and exclude globs, symbolic links and `.gitignore` apply
+//! the same way everywhere.
+
+use std::path::{Component, Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::module_manager::{ModuleManager, ModuleType};
+use crate::profile::{self, Phase};
+
+/// A Python file found by a `ProjectWalker`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalkEntry {
+    pub path: PathBuf,
+    pub module_type: ModuleType,
+    /// Does the module sit directly under the project root?
+    pub is_top_level: bool,
+}
+
+/// Walks the Python files under the current directory, the project root.
+///
+/// Globs are matched against the paths relative to the project root, such
+/// as `pkg/sub/mod.py`: `*` matches within a path segment and `**` any
+/// number of segments. A directory matching an exclude glob is skipped whole.
+#[derive(Debug, Clone, Default)]
+pub struct ProjectWalker {
+    root: Option<String>,
+    max_depth: Option<usize>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    follow_symlinks: bool,
+    gitignore: bool,
+    stubs: bool,
+}
+
+impl ProjectWalker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only walk this directory of the project, e.g. `pkg/sub`.
+    pub fn root(mut self: Self, root: Option<String>) -> Self {
+        self.root = root;
+        self
+    }
+
+    /// How deep to descend, 1 being the files directly inside the root.
+    pub fn max_depth(mut self: Self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Only yield the files matching this glob, or one of the other included ones.
+    pub fn include(mut self: Self, glob: &str) -> Self {
+        self.include.push(glob.to_string());
+        self
+    }
+
+    /// Skip the files and directories matching this glob.
+    pub fn exclude(mut self: Self, glob: &str) -> Self {
+        self.exclude.push(glob.to_string());
+        self
+    }
+
+    /// Descend into symbolic links. Links looping back to a parent are
+    /// reported as errors by walkdir and skipped like unreadable entries.
+    pub fn follow_symlinks(mut self: Self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Also skip what the `.gitignore` of the project root ignores.
+    pub fn gitignore(mut self: Self, gitignore: bool) -> Self {
+        self.gitignore = gitignore;
+        self
+    }
+
+    /// Also yield the `.pyi` stubs, except those next to the `.py` file
+    /// they describe, since the implementation is what gets loaded.
+    pub fn stubs(mut self: Self, stubs: bool) -> Self {
+        self.stubs = stubs;
+        self
+    }
+
+    /// The Python files, classified as with `ModuleManager::classify_path`.
+    pub fn walk(self: Self) -> impl Iterator<Item = WalkEntry> {
+        let mut exclude = self.exclude;
+        if self.gitignore {
+            // A project without a readable .gitignore ignores nothing.
+            let contents = std::fs::read_to_string(".gitignore").unwrap_or_default();
+            exclude.extend(Self::gitignore_globs(&contents));
+        }
+
+        let prefix = match self.root {
+            Some(root) => "./".to_owned() + &root,
+            None => String::from("./"),
+        };
+
+        let mut walk = WalkDir::new(prefix).follow_links(self.follow_symlinks);
+        if let Some(max_depth) = self.max_depth {
+            walk = walk.max_depth(max_depth);
+        }
+
+        let include = self.include;
+        let stubs = self.stubs;
+        let mut entries = walk.into_iter().filter_entry(move |e| {
+            !exclude.iter().any(|glob| Self::glob_matches(glob, e.path()))
+        });
+        std::iter::from_fn(move || profile::time(Phase::Walk, || entries.next()))
+            .filter_map(|e| e.ok())
+            .filter(move |e| {
+                e.file_type().is_file()
+                    && match e.path().extension() {
+                        Some(extension) if extension == "pyi" => {
+                            stubs && !e.path().with_extension("py").exists()
+                        }
+                        Some(extension) => extension == "py",
+                        None => false,
+                    }
+            })
+            .filter(move |e| {
+                include.is_empty() || include.iter().any(|glob| Self::glob_matches(glob, e.path()))
+            })
+            .map(|e| {
+                let path = e.into_path();
+                let (module_type, is_top_level) = ModuleManager::classify_path(&path);
+                WalkEntry {
+                    path,
+                    module_type,
+                    is_top_level,
+                }
+            })
+    }
+
+    /// Does `glob` match `path`, relative to the project root?
+    pub fn glob_matches(glob: &str, path: &Path) -> bool {
+        let segments = path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(segment) => segment.to_str(),
+                _ => None,
+            })
+            .collect::<Vec<&str>>();
+        let pattern = glob.trim_matches('/').split("/").collect::<Vec<&str>>();
+
+        !segments.is_empty() && ModuleManager::match_segments(&pattern, &segments)
+    }
+
+    /// The exclude globs of a `.gitignore`. Patterns without a slash match at
+    /// any depth, the others from the root. Negations (`!`) are not supported
+    /// and are left out.
+    pub fn gitignore_globs(contents: &str) -> Vec<String> {
+        contents
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+            .map(|line| {
+                let pattern = line.trim_end_matches('/');
+                match pattern.contains('/') {
+                    true => pattern.trim_start_matches('/').to_string(),
+                    false => format!("**/{}", pattern),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    use super::*;
+
+    fn paths(walker: ProjectWalker) -> Vec<String> {
+        let mut paths = walker
+            .root(Some(String::from("tests_data/test_walker")))
+            .walk()
+            .map(|e| e.path.display().to_string())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_walk_filters() {
+        create_dir_all("tests_data/test_walker/build").unwrap();
+        create_dir_all("tests_data/test_walker/pkg").unwrap();
+        for file in ["a.py", "notes.txt", "build/b.py", "pkg/__init__.py", "pkg/c.py"] {
+            std::fs::write(format!("tests_data/test_walker/{}", file), "").unwrap();
+        }
+
+        let all = paths(ProjectWalker::new());
+        let shallow = paths(ProjectWalker::new().max_depth(Some(1)));
+        let excluded = paths(
+            ProjectWalker::new()
+                .exclude("tests_data/test_walker/build")
+                .exclude("**/c.py"),
+        );
+        let included = paths(ProjectWalker::new().include("**/pkg/*"));
+        let both = paths(ProjectWalker::new().include("**/pkg/*").exclude("**/__init__.py"));
+        let package = ProjectWalker::new()
+            .root(Some(String::from("tests_data/test_walker/pkg")))
+            .include("**/__init__.py")
+            .walk()
+            .collect::<Vec<_>>();
+
+        remove_dir_all("tests_data/test_walker").unwrap();
+        assert_eq!(
+            all,
+            vec![
+                "./tests_data/test_walker/a.py",
+                "./tests_data/test_walker/build/b.py",
+                "./tests_data/test_walker/pkg/__init__.py",
+                "./tests_data/test_walker/pkg/c.py",
+            ]
+        );
+        assert_eq!(shallow, vec!["./tests_data/test_walker/a.py"]);
+        assert_eq!(
+            excluded,
+            vec![
+                "./tests_data/test_walker/a.py",
+                "./tests_data/test_walker/pkg/__init__.py",
+            ]
+        );
+        assert_eq!(
+            included,
+            vec![
+                "./tests_data/test_walker/pkg/__init__.py",
+                "./tests_data/test_walker/pkg/c.py",
+            ]
+        );
+        assert_eq!(both, vec!["./tests_data/test_walker/pkg/c.py"]);
+        assert_eq!(
+            package,
+            vec![WalkEntry {
+                path: PathBuf::from("./tests_data/test_walker/pkg/__init__.py"),
+                module_type: ModuleType::Directory,
+                is_top_level: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_gitignore_globs() {
+        let gitignore = "# build output\n/build/\n*.pyc\n!keep.py\n\nsub/dir\n";
+        let globs = ProjectWalker::gitignore_globs(gitignore);
+        assert_eq!(globs, vec!["build", "**/*.pyc", "sub/dir"]);
+
+        assert!(ProjectWalker::glob_matches("build", Path::new("./build")));
+        assert!(!ProjectWalker::glob_matches("build", Path::new("./src/build")));
+        assert!(ProjectWalker::glob_matches("**/*.pyc", Path::new("./src/a.pyc")));
+        assert!(!ProjectWalker::glob_matches("**", Path::new("./")));
+    }
+}
@@ -1,4 +1,8 @@
 use color_print::cformat;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::hash::{Hash, Hasher};
 
 pub trait PythonDef {
     fn get_type(&self) -> String;
@@ -13,13 +17,23 @@ pub trait PythonDef {
     ) -> String;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Class {
     pub path: String,
     pub name: String,
     pub methods: Vec<Method>,
-    base_classes: Vec<String>,
+    pub base_classes: Vec<String>,
     pub definition_code: String,
+    pub line: usize,
+    /// Byte range of the definition in the source, decorators included.
+    pub span: Option<(usize, usize)>,
+    pub decorators: Vec<String>,
+    /// Defined under a top-level `if`, e.g. for one platform only.
+    pub is_conditional: bool,
+    /// The attributes assigned in the class body, such as dataclass fields.
+    pub attributes: Vec<Attribute>,
+    /// Decorated with `@dataclass`, kept when the decorators are hidden.
+    pub is_dataclass: bool,
 }
 
 impl Class {
@@ -35,11 +49,136 @@ impl Class {
             methods: methods,
             base_classes: base_classes,
             definition_code: String::from(""),
+            line: 0,
+            span: None,
+            decorators: Vec::new(),
+            is_conditional: false,
+            attributes: Vec::new(),
+            is_dataclass: false,
         };
 
         class.definition_code = class.get_definition_code();
         class
     }
+
+    pub fn set_line(&mut self, line: usize) {
+        self.line = line;
+    }
+
+    pub fn set_span(&mut self, span: (usize, usize)) {
+        self.span = Some(span);
+    }
+
+    pub fn set_decorators(&mut self, decorators: Vec<String>) {
+        self.is_dataclass = decorators.iter().any(|d| {
+            let name = d.split('(').next().unwrap();
+            name == "dataclass" || name.ends_with(".dataclass")
+        });
+        self.decorators = decorators;
+    }
+
+    pub fn set_conditional(&mut self, is_conditional: bool) {
+        self.is_conditional = is_conditional;
+    }
+
+    pub fn set_attributes(&mut self, attributes: Vec<Attribute>) {
+        self.attributes = attributes;
+        self.definition_code = self.get_definition_code();
+    }
+
+    /// Does one of the class's own decorators match `query`?
+    pub fn has_decorator(&self, query: &str) -> bool {
+        self.decorators.iter().any(|d| decorator_matches(d, query))
+    }
+
+    /// Shows the class when one of its decorators matches `query`, or the
+    /// class line followed by its methods having a matching decorator.
+    pub fn find_decorator(&self, query: &str, print_prefix: &String) -> Option<String> {
+        let class_line = cformat!("{}<red>class</red> <yellow>{}</yellow>:\n", print_prefix, self.name);
+
+        if self.has_decorator(query) {
+            let mut result = decorators_display(&self.decorators, query, print_prefix);
+            result.push_str(&class_line);
+            return Some(result);
+        }
+
+        let method_prefix = format!("{}    ", print_prefix);
+        let methods = self
+            .methods
+            .iter()
+            .filter_map(|m| m.find_decorator(query, &method_prefix))
+            .collect::<Vec<String>>();
+
+        match methods.is_empty() {
+            true => None,
+            false => Some(class_line + &methods.concat()),
+        }
+    }
+
+    /// Shows the class line followed by its methods whose signature matches
+    /// `pattern`, see `Method::find_signature`.
+    pub fn find_signature(&self, pattern: &Regex, print_prefix: &String) -> Option<String> {
+        let method_prefix = format!("{}    ", print_prefix);
+        let methods = self
+            .methods
+            .iter()
+            .filter_map(|m| m.find_signature(pattern, &method_prefix))
+            .collect::<Vec<String>>();
+
+        match methods.is_empty() {
+            true => None,
+            false => Some(
+                cformat!("{}<red>class</red> <yellow>{}</yellow>:\n", print_prefix, self.name)
+                    + &methods.concat(),
+            ),
+        }
+    }
+
+    /// A copy for display without the `@decorator` lines of the class and
+    /// of its methods.
+    pub fn without_decorators(&self) -> Class {
+        let mut class = self.clone();
+        class.decorators.clear();
+        class.methods = self.methods.iter().map(|m| m.without_decorators()).collect();
+        class
+    }
+
+    /// A copy for display whose methods hide their private arguments, see
+    /// `Method::without_private_args`.
+    pub fn without_private_args(&self) -> Class {
+        let mut class = self.clone();
+        class.methods = self.methods.iter().map(|m| m.without_private_args()).collect();
+        class.definition_code = class.get_definition_code();
+        class
+    }
+}
+
+// Definitions are equal when they declare the same thing at the same path.
+// The rendered `definition_code`, the line and the span are left out.
+impl PartialEq for Class {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.name == other.name
+            && self.base_classes == other.base_classes
+            && self.methods == other.methods
+    }
+}
+
+impl Eq for Class {}
+
+impl Hash for Class {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+        self.name.hash(state);
+        self.base_classes.hash(state);
+        self.methods.hash(state);
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_definition_code())
+    }
 }
 
 impl PythonDef for Class {
@@ -57,9 +196,17 @@ impl PythonDef for Class {
         }
         code.push_str(":\n");
 
+        for a in &self.attributes {
+            code.push_str("    ");
+            code.push_str(&a.definition_code);
+            code.push_str("\n");
+        }
+
+        // Rendered again rather than read from `definition_code`, so the methods
+        // always get the same `/` and `*` markers as top-level functions.
         for m in &self.methods {
             code.push_str("    ");
-            code.push_str(&m.definition_code);
+            code.push_str(&m.get_definition_code());
             code.push_str("\n");
         }
 
@@ -88,15 +235,26 @@ impl PythonDef for Class {
         if self.base_classes.len() > 0 {
             class_def_str.push_str(&cformat!("(<blue>{}</blue>)", self.base_classes.join(", ")));
         }
-        class_def_str.push_str(":\n");
+        class_def_str.push_str(":");
+        if self.is_conditional {
+            class_def_str.push_str(&cformat!(" <cyan>[conditional]</cyan>"));
+        }
+        if self.is_dataclass {
+            class_def_str.push_str(&cformat!(" <cyan>[dataclass]</cyan>"));
+        }
+        class_def_str.push_str("\n");
         if query.len() > 0 {
             class_def_str =
                 class_def_str.replace(query, cformat!("<bg:green>{}</bg:green>", query).as_str());
         }
 
+        let member_prefix = format!("{}    ", print_prefix);
         let mut function_defs = String::new();
+        for a in &self.attributes {
+            function_defs.push_str(&a.find(query, Some(false), Some(&member_prefix)));
+        }
         for m in &self.methods {
-            let function_def = m.find(query, Some(false), Some(&format!("{}    ", print_prefix)));
+            let function_def = m.find(query, Some(false), Some(&member_prefix));
             if function_def.len() > 0 {
                 function_defs.push_str(&function_def);
             }
@@ -111,6 +269,7 @@ impl PythonDef for Class {
                     self.path
                 ));
             }
+            result.push_str(&decorators_display(&self.decorators, "", print_prefix));
             result.push_str(&class_def_str);
             result.push_str(&function_defs);
         }
@@ -119,14 +278,50 @@ impl PythonDef for Class {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Displayed signatures longer than this, indentation included, are wrapped
+/// one argument per line, with a trailing comma, the way Black formats them.
+const WRAP_WIDTH: usize = 88;
+
+/// Does the decorator match `query`? Only the decorator itself is matched,
+/// not its arguments: `app.route` for `@app.route("/x")`.
+fn decorator_matches(decorator: &str, query: &str) -> bool {
+    decorator.split('(').next().unwrap().contains(query)
+}
+
+/// The `@decorator` lines, with `query` highlighted in the matching ones.
+fn decorators_display(decorators: &[String], query: &str, print_prefix: &str) -> String {
+    decorators
+        .iter()
+        .map(|d| {
+            let decorator = match !query.is_empty() && decorator_matches(d, query) {
+                true => d.replacen(query, &cformat!("<bg:green>{}</bg:green>", query), 1),
+                false => d.clone(),
+            };
+            cformat!("{}<red>@</red>{}\n", print_prefix, decorator)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Method {
     pub path: String,
     pub name: String,
-    return_type: Option<String>,
-    arguments: Vec<Attribute>,
+    pub return_type: Option<String>,
+    pub arguments: Vec<Attribute>,
     pub definition_code: String,
-    pub is_async: bool
+    pub is_async: bool,
+    pub decorators: Vec<String>,
+    pub line: usize,
+    /// The `@overload` signatures declared before this implementation.
+    pub overloads: Vec<Method>,
+    /// Does the body `yield`?
+    pub is_generator: bool,
+    /// Byte range of the definition in the source, decorators included.
+    pub span: Option<(usize, usize)>,
+    /// Defined under a top-level `if`, e.g. for one platform only.
+    pub is_conditional: bool,
+    /// The body is only `...`, `pass` or a docstring, as in stubs and protocols.
+    pub is_stub: bool,
 }
 
 impl Method {
@@ -142,7 +337,14 @@ impl Method {
             return_type: return_type,
             arguments: arguments,
             definition_code: String::from(""),
-            is_async: false
+            is_async: false,
+            decorators: Vec::new(),
+            line: 0,
+            overloads: Vec::new(),
+            is_generator: false,
+            span: None,
+            is_conditional: false,
+            is_stub: false,
         };
 
         method.definition_code = method.get_definition_code();
@@ -153,6 +355,218 @@ impl Method {
         self.is_async = is_async;
         self.definition_code = self.get_definition_code();
     }
+
+    pub fn set_decorators(&mut self, decorators: Vec<String>) {
+        self.decorators = decorators;
+    }
+
+    pub fn set_line(&mut self, line: usize) {
+        self.line = line;
+    }
+
+    pub fn set_overloads(&mut self, overloads: Vec<Method>) {
+        self.overloads = overloads;
+    }
+
+    pub fn set_generator(&mut self, is_generator: bool) {
+        self.is_generator = is_generator;
+    }
+
+    pub fn set_span(&mut self, span: (usize, usize)) {
+        self.span = Some(span);
+    }
+
+    pub fn set_conditional(&mut self, is_conditional: bool) {
+        self.is_conditional = is_conditional;
+    }
+
+    pub fn set_stub(&mut self, is_stub: bool) {
+        self.is_stub = is_stub;
+    }
+
+    /// Is the method decorated with `@abstractmethod`?
+    pub fn is_abstract(&self) -> bool {
+        self.decorators.iter().any(|d| d.ends_with("abstractmethod"))
+    }
+
+    /// Is this an `@overload` signature?
+    pub fn is_overload(&self) -> bool {
+        self.decorators
+            .iter()
+            .any(|d| d == "overload" || d.ends_with(".overload"))
+    }
+
+    /// Is this method allowed to reuse the name of an earlier definition?
+    /// Property setters/deleters and `@overload` signatures do so on purpose.
+    pub fn redefines_on_purpose(&self) -> bool {
+        self.is_overload()
+            || self
+                .decorators
+                .iter()
+                .any(|d| d.ends_with(".setter") || d.ends_with(".deleter"))
+    }
+
+    /// Does one of the method's decorators match `query`?
+    pub fn has_decorator(&self, query: &str) -> bool {
+        self.decorators.iter().any(|d| decorator_matches(d, query))
+    }
+
+    /// Shows the method with its decorators when one of them matches `query`.
+    pub fn find_decorator(&self, query: &str, print_prefix: &String) -> Option<String> {
+        if !self.has_decorator(query) {
+            return None;
+        }
+
+        let mut result = decorators_display(&self.decorators, query, print_prefix);
+        result.push_str(&self.without_decorators().find("", None, Some(print_prefix)));
+        Some(result)
+    }
+
+    /// Shows the signature, as in `definition_code`, when `pattern` matches
+    /// it, with the first match highlighted. Matching the uncolored code
+    /// lets patterns span names, annotations and defaults alike.
+    pub fn find_signature(&self, pattern: &Regex, print_prefix: &String) -> Option<String> {
+        let code = self.definition_code.trim_end();
+        let found = pattern.find(code)?;
+
+        Some(cformat!(
+            "{}{}<bg:green>{}</bg:green>{}\n",
+            print_prefix,
+            &code[..found.start()],
+            found.as_str(),
+            &code[found.end()..]
+        ))
+    }
+
+    /// A copy for display without the `@decorator` lines.
+    pub fn without_decorators(&self) -> Method {
+        let mut method = self.clone();
+        method.decorators.clear();
+        method
+    }
+
+    /// A copy for display without the arguments named with a leading `_`,
+    /// such as `_internal=None`.
+    pub fn without_private_args(&self) -> Method {
+        let mut method = self.clone();
+        method.arguments.retain(|a| !a.name.starts_with('_'));
+        method.overloads = self.overloads.iter().map(|o| o.without_private_args()).collect();
+        method.definition_code = method.get_definition_code();
+        method
+    }
+
+    /// Joins the rendered arguments, on one line or, when the signature
+    /// shown under `print_prefix` is too long, one per line with a trailing
+    /// comma.
+    fn join_arguments(
+        &self,
+        print_prefix: &str,
+        render: impl Fn(&Attribute) -> String,
+        marker: impl Fn(&str) -> String,
+    ) -> String {
+        let parts = self.argument_parts(render, marker);
+        if !self.is_wrapped(print_prefix) {
+            return parts.join(", ");
+        }
+
+        let mut arguments = String::from("\n");
+        for part in parts {
+            arguments.push_str(&format!("{}    {},\n", print_prefix, part));
+        }
+        arguments.push_str(print_prefix);
+        arguments
+    }
+
+    /// Is the signature, on one line after `print_prefix`, longer than
+    /// `WRAP_WIDTH`?
+    fn is_wrapped(&self, print_prefix: &str) -> bool {
+        let line = format!("{}{}", print_prefix, self.get_definition_code().trim_end());
+        !self.arguments.is_empty() && line.chars().count() > WRAP_WIDTH
+    }
+
+    /// The rendered arguments, adding the `/` after positional-only
+    /// arguments and the bare `*` before keyword-only ones when there is no
+    /// `*args` to mark them.
+    fn argument_parts(
+        &self,
+        render: impl Fn(&Attribute) -> String,
+        marker: impl Fn(&str) -> String,
+    ) -> Vec<String> {
+        let mut parts = Vec::new();
+        let mut keyword_only_marked = self.arguments.iter().any(|a| a.arg_type == ArgType::VarArg);
+
+        for (index, argument) in self.arguments.iter().enumerate() {
+            if argument.arg_type == ArgType::KeywordOnly && !keyword_only_marked {
+                parts.push(marker("*"));
+                keyword_only_marked = true;
+            }
+
+            parts.push(render(argument));
+
+            let next_is_positional_only = self
+                .arguments
+                .get(index + 1)
+                .map_or(false, |a| a.arg_type == ArgType::PositionalOnly);
+            if argument.arg_type == ArgType::PositionalOnly && !next_is_positional_only {
+                parts.push(marker("/"));
+            }
+        }
+
+        parts
+    }
+
+    /// The colored `def name(args) -> type:` line, without a line break,
+    /// wrapped like `join_arguments` when too long.
+    fn signature_display(&self, print_prefix: &str, name: &str) -> String {
+        let def_str = if self.is_async { "async def" } else { "def" };
+
+        let mut signature = cformat!(
+            "{}<red>{}</red> <magenta>{}</magenta>",
+            print_prefix,
+            def_str,
+            name
+        );
+        signature.push_str("(");
+        signature.push_str(&self.join_arguments(
+            print_prefix,
+            |a| a.argument_display(),
+            |m| cformat!("<red>{}</red>", m),
+        ));
+        signature.push_str(")");
+        if self.return_type.is_some() {
+            signature.push_str(&format!(" -> {}", self.return_type.clone().unwrap()));
+        }
+        signature.push_str(":");
+        signature
+    }
+}
+
+impl PartialEq for Method {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.name == other.name
+            && self.arguments == other.arguments
+            && self.return_type == other.return_type
+            && self.is_async == other.is_async
+    }
+}
+
+impl Eq for Method {}
+
+impl Hash for Method {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+        self.name.hash(state);
+        self.arguments.hash(state);
+        self.return_type.hash(state);
+        self.is_async.hash(state);
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_definition_code())
+    }
 }
 
 impl PythonDef for Method {
@@ -166,10 +580,7 @@ impl PythonDef for Method {
         code.push_str("(");
         code.push_str(
             &self
-                .arguments
-                .iter()
-                .map(|a| a.definition_code.clone())
-                .collect::<Vec<String>>()
+                .argument_parts(|a| a.definition_code.clone(), |m| m.to_string())
                 .join(", "),
         );
         code.push_str(")");
@@ -194,38 +605,37 @@ impl PythonDef for Method {
         }
         .as_str();
         let mut result = String::new();
-        let def_str = if self.is_async { "async def" } else { "def" };
 
-        let mut method_def_str = cformat!(
-            "{}<red>{}</red> <magenta>{}</magenta>",
-            print_prefix,
-            def_str,
-            self.name.clone()
-        );
-        method_def_str.push_str("(");
-        method_def_str.push_str(
-            &self
-                .arguments
-                .iter()
-                .map(|a| {
-                    a.definition_code
-                        .clone()
-                        .replace("self", cformat!("<red>self</red>").as_str())
-                        .replace("cls", cformat!("<red>cls</red>").as_str())
-                        .replace("...", cformat!("<red>...</red>").as_str())
-                        .replace("*", cformat!("<red>*</red>").as_str())
-                })
-                .collect::<Vec<String>>()
-                .join(", "),
-        );
-        method_def_str.push_str(")");
-        if self.return_type.is_some() {
-            method_def_str.push_str(&format!(" -> {}", self.return_type.clone().unwrap()));
+        // Only the name is matched against the query, so only the name is
+        // highlighted; defaults and annotations are left untouched.
+        let name = match query.len() {
+            0 => self.name.clone(),
+            _ => self
+                .name
+                .replace(query, cformat!("<bg:green>{}</bg:green>", query).as_str()),
+        };
+
+        let mut method_def_str = self.signature_display(print_prefix, &name);
+        match (self.is_generator, self.is_async) {
+            (true, true) => method_def_str.push_str(&cformat!(" <cyan>[async generator]</cyan>")),
+            (true, false) => method_def_str.push_str(&cformat!(" <cyan>[generator]</cyan>")),
+            _ => {}
         }
-        method_def_str.push_str(":\n");
-        if query.len() > 0 {
-            method_def_str =
-                method_def_str.replace(query, cformat!("<bg:green>{}</bg:green>", query).as_str());
+        if self.is_conditional {
+            method_def_str.push_str(&cformat!(" <cyan>[conditional]</cyan>"));
+        }
+        if self.is_abstract() {
+            method_def_str.push_str(&cformat!(" <cyan>[abstract]</cyan>"));
+        } else if self.is_stub {
+            method_def_str.push_str(&cformat!(" <cyan>[stub]</cyan>"));
+        }
+        method_def_str.push_str("\n");
+
+        // Overload signatures are listed under their implementation.
+        let overload_prefix = format!("{}│  ", print_prefix);
+        for overload in &self.overloads {
+            method_def_str.push_str(&overload.signature_display(&overload_prefix, &name));
+            method_def_str.push_str(&cformat!(" <cyan>[overload]</cyan>\n"));
         }
 
         if self.name.contains(query) || query.len() == 0 {
@@ -237,6 +647,7 @@ impl PythonDef for Method {
                     self.path
                 ));
             }
+            result.push_str(&decorators_display(&self.decorators, "", print_prefix));
             result.push_str(&method_def_str);
         }
 
@@ -244,23 +655,31 @@ impl PythonDef for Method {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ArgType {
     Not,
+    PositionalOnly,
     Arg,
     Keyword,
     KeywordOnly,
     VarArg,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Attribute {
     pub path: String,
     pub name: String,
-    type_: Option<String>,
-    default: Option<String>,
+    pub type_: Option<String>,
+    pub default: Option<String>,
     pub definition_code: String,
     pub arg_type: ArgType,
+    pub line: usize,
+    /// Module-level name following the UPPER_CASE constant convention.
+    pub is_constant: bool,
+    /// Byte range of the assigned name in the source, for in-place edits.
+    pub name_span: Option<(usize, usize)>,
+    /// Bound by a starred unpacking target, as `rest` in `first, *rest = items`.
+    pub is_starred: bool,
 }
 
 impl Attribute {
@@ -278,11 +697,94 @@ impl Attribute {
             default: default,
             definition_code: String::from(""),
             arg_type: arg_type,
+            line: 0,
+            is_constant: false,
+            name_span: None,
+            is_starred: false,
         };
 
         attribute.definition_code = attribute.get_definition_code();
         attribute
     }
+
+    pub fn set_line(&mut self, line: usize) {
+        self.line = line;
+    }
+
+    pub fn set_constant(&mut self, is_constant: bool) {
+        self.is_constant = is_constant;
+    }
+
+    pub fn set_name_span(&mut self, name_span: (usize, usize)) {
+        self.name_span = Some(name_span);
+    }
+
+    pub fn set_starred(&mut self, is_starred: bool) {
+        self.is_starred = is_starred;
+        self.definition_code = self.get_definition_code();
+    }
+
+    /// Does the name follow the UPPER_CASE convention for constants?
+    pub fn is_constant_name(name: &str) -> bool {
+        name.chars().any(|c| c.is_alphabetic()) && !name.chars().any(|c| c.is_lowercase())
+    }
+
+    /// Renders the attribute as a colored function argument. Each part is
+    /// colored on its own, so stars or names inside annotations and defaults
+    /// are left as they are.
+    pub fn argument_display(&self) -> String {
+        let name = match self.name.as_str() {
+            "self" | "cls" => cformat!("<red>{}</red>", self.name),
+            _ => self.name.clone(),
+        };
+
+        match self.arg_type {
+            ArgType::VarArg => return cformat!("<red>*</red>{}", name),
+            ArgType::Keyword => return cformat!("<red>**</red>{}", name),
+            _ => {}
+        }
+
+        let mut display = name;
+        if let Some(type_) = &self.type_ {
+            display.push_str(&cformat!(": <blue>{}</blue>", type_));
+        }
+        if let Some(default) = &self.default {
+            match default.as_str() {
+                "..." => display.push_str(&cformat!(" = <red>...</red>")),
+                _ => display.push_str(&cformat!(" = <yellow>{}</yellow>", default)),
+            }
+        }
+
+        display
+    }
+}
+
+impl PartialEq for Attribute {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.name == other.name
+            && self.type_ == other.type_
+            && self.default == other.default
+            && self.arg_type == other.arg_type
+    }
+}
+
+impl Eq for Attribute {}
+
+impl Hash for Attribute {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+        self.name.hash(state);
+        self.type_.hash(state);
+        self.default.hash(state);
+        self.arg_type.hash(state);
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_definition_code())
+    }
 }
 
 impl PythonDef for Attribute {
@@ -295,7 +797,10 @@ impl PythonDef for Attribute {
             ArgType::VarArg => String::from("*") + &self.name,
             ArgType::Keyword => String::from("**") + &self.name,
             _ => {
-                let mut code = String::from(&self.name);
+                let mut code = match self.is_starred {
+                    true => String::from("*") + &self.name,
+                    false => String::from(&self.name),
+                };
                 if self.type_.is_some() {
                     code.push_str(": ");
                     code.push_str(&self.type_.clone().unwrap());
@@ -323,11 +828,15 @@ impl PythonDef for Attribute {
         .as_str();
         let mut result = String::new();
 
-        let mut arg_def_str = format!("{}{}", print_prefix, self.definition_code.clone());
+        let mut arg_def_str = self.definition_code.clone();
         if query.len() > 0 {
             arg_def_str =
                 arg_def_str.replace(query, cformat!("<bg:green>{}</bg:green>", query).as_str());
         }
+        arg_def_str = match self.is_constant {
+            true => cformat!("{}<cyan>{}</cyan>", print_prefix, arg_def_str),
+            false => format!("{}{}", print_prefix, arg_def_str),
+        };
 
         if self.name.contains(query) || query.len() == 0 {
             if include_file_name.is_some() && include_file_name.unwrap() {
@@ -345,3 +854,286 @@ impl PythonDef for Attribute {
         result
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Import {
+    pub path: String,
+    pub module: Option<String>,
+    pub name: String,
+    pub alias: Option<String>,
+    pub level: u32,
+    pub definition_code: String,
+    pub line: usize,
+    /// Byte range of the whole import statement in the source.
+    pub span: Option<(usize, usize)>,
+}
+
+impl Import {
+    pub fn new(
+        path: String,
+        module: Option<String>,
+        name: String,
+        alias: Option<String>,
+        level: u32,
+    ) -> Import {
+        let mut import = Import {
+            path: path,
+            module: module,
+            name: name,
+            alias: alias,
+            level: level,
+            definition_code: String::from(""),
+            line: 0,
+            span: None,
+        };
+
+        import.definition_code = import.get_definition_code();
+        import
+    }
+
+    pub fn set_line(&mut self, line: usize) {
+        self.line = line;
+    }
+
+    pub fn set_span(&mut self, span: (usize, usize)) {
+        self.span = Some(span);
+    }
+
+    /// The name this import binds in the importing module.
+    pub fn bound_name(&self) -> &str {
+        match &self.alias {
+            Some(alias) => alias,
+            None => &self.name,
+        }
+    }
+
+    /// Is this a `from ... import name` import (as opposed to `import name`)?
+    pub fn is_from(&self) -> bool {
+        self.module.is_some() || self.level > 0
+    }
+}
+
+impl fmt::Display for Import {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_definition_code())
+    }
+}
+
+impl PythonDef for Import {
+    fn get_type(&self) -> String {
+        String::from("IMPORT")
+    }
+
+    fn get_definition_code(&self) -> String {
+        let mut code = String::new();
+        if self.is_from() {
+            code.push_str("from ");
+            code.push_str(&".".repeat(self.level as usize));
+            if self.module.is_some() {
+                code.push_str(&self.module.clone().unwrap());
+            }
+            code.push_str(" ");
+        }
+        code.push_str("import ");
+        code.push_str(&self.name);
+        if self.alias.is_some() {
+            code.push_str(" as ");
+            code.push_str(&self.alias.clone().unwrap());
+        }
+        code
+    }
+
+    fn find(
+        &self,
+        query: &str,
+        include_file_name: Option<bool>,
+        print_prefix: Option<&String>,
+    ) -> String {
+        let binding = String::new();
+        let print_prefix = match print_prefix {
+            Some(p) => p,
+            None => &binding,
+        }
+        .as_str();
+        let mut result = String::new();
+
+        let mut import_def_str = format!("{}{}", print_prefix, self.definition_code.clone());
+        if query.len() > 0 {
+            import_def_str =
+                import_def_str.replace(query, cformat!("<bg:green>{}</bg:green>", query).as_str());
+        }
+
+        if self.bound_name().contains(query) || query.len() == 0 {
+            if include_file_name.is_some() && include_file_name.unwrap() {
+                result.push_str(&cformat!(
+                    "{}<yellow><bg:blue> [{}/{}]</bg:blue></yellow>\n",
+                    print_prefix,
+                    std::env::current_dir().unwrap().display(),
+                    self.path
+                ));
+            }
+            result.push_str(&import_def_str);
+            result.push('\n');
+        }
+
+        result
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypeAlias {
+    pub path: String,
+    pub name: String,
+    pub value: String,
+    pub definition_code: String,
+    pub line: usize,
+}
+
+impl TypeAlias {
+    pub fn new(path: String, name: String, value: String) -> TypeAlias {
+        let mut type_alias = TypeAlias {
+            path: path,
+            name: name,
+            value: value,
+            definition_code: String::from(""),
+            line: 0,
+        };
+
+        type_alias.definition_code = type_alias.get_definition_code();
+        type_alias
+    }
+
+    pub fn set_line(&mut self, line: usize) {
+        self.line = line;
+    }
+}
+
+impl fmt::Display for TypeAlias {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_definition_code())
+    }
+}
+
+impl PythonDef for TypeAlias {
+    fn get_type(&self) -> String {
+        String::from("TYPE_ALIAS")
+    }
+
+    fn get_definition_code(&self) -> String {
+        format!("type {} = {}", self.name, self.value)
+    }
+
+    fn find(
+        &self,
+        query: &str,
+        include_file_name: Option<bool>,
+        print_prefix: Option<&String>,
+    ) -> String {
+        let binding = String::new();
+        let print_prefix = match print_prefix {
+            Some(p) => p,
+            None => &binding,
+        }
+        .as_str();
+        let mut result = String::new();
+
+        let mut alias_def_str = cformat!(
+            "{}<red>type</red> <cyan>{}</cyan> = {}\n",
+            print_prefix,
+            self.name.clone(),
+            self.value.clone()
+        );
+        if query.len() > 0 {
+            alias_def_str =
+                alias_def_str.replace(query, cformat!("<bg:green>{}</bg:green>", query).as_str());
+        }
+
+        if self.name.contains(query) || query.len() == 0 {
+            if include_file_name.is_some() && include_file_name.unwrap() {
+                result.push_str(&cformat!(
+                    "{}<yellow><bg:blue> [{}/{}]</bg:blue></yellow>\n",
+                    print_prefix,
+                    std::env::current_dir().unwrap().display(),
+                    self.path
+                ));
+            }
+            result.push_str(&alias_def_str);
+        }
+
+        result
+    }
+}
+
+/// A `#` comment, found by scanning the raw source rather than the AST.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comment {
+    pub path: String,
+    /// The comment text, including the leading `#`.
+    pub text: String,
+    pub line: usize,
+}
+
+impl Comment {
+    pub fn new(path: String, text: String, line: usize) -> Comment {
+        Comment { path, text, line }
+    }
+}
+
+impl fmt::Display for Comment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_definition_code())
+    }
+}
+
+impl PythonDef for Comment {
+    fn get_type(&self) -> String {
+        String::from("COMMENT")
+    }
+
+    fn get_definition_code(&self) -> String {
+        self.text.clone()
+    }
+
+    fn find(
+        &self,
+        query: &str,
+        include_file_name: Option<bool>,
+        print_prefix: Option<&String>,
+    ) -> String {
+        let binding = String::new();
+        let print_prefix = match print_prefix {
+            Some(p) => p,
+            None => &binding,
+        }
+        .as_str();
+        let mut result = String::new();
+
+        if !self.text.contains(query) {
+            return result;
+        }
+
+        let text = match query.len() {
+            0 => self.text.clone(),
+            _ => self
+                .text
+                .replace(query, cformat!("<bg:green>{}</bg:green>", query).as_str()),
+        };
+
+        if include_file_name.is_some() && include_file_name.unwrap() {
+            result.push_str(&cformat!(
+                "{}<yellow><bg:blue> [{}/{}]</bg:blue></yellow>\n",
+                print_prefix,
+                std::env::current_dir().unwrap().display(),
+                self.path
+            ));
+        }
+        result.push_str(&cformat!(
+            "{}<yellow>{}</yellow>: <dim>{}</dim>\n",
+            print_prefix,
+            self.line,
+            text
+        ));
+
+        result
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::ast::literal_ranges;
+use crate::module_manager::ModuleManager;
+use crate::python_def::Method;
+
+/// Definition counts of a single module, or of the whole project for totals.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ModuleStats {
+    pub module: String,
+    pub classes: usize,
+    pub functions: usize,
+    pub vars: usize,
+    /// Synchronous top-level functions and class methods.
+    pub sync_functions: usize,
+    /// Asynchronous top-level functions and class methods.
+    pub async_functions: usize,
+    /// Physical lines of the module's file.
+    pub lines: usize,
+    pub blank_lines: usize,
+    /// Lines holding only a comment.
+    pub comment_lines: usize,
+}
+
+impl ModuleStats {
+    pub fn from_module(module_manager: &ModuleManager) -> ModuleStats {
+        let methods = module_manager
+            .functions()
+            .iter()
+            .chain(module_manager.classes().iter().flat_map(|c| c.methods.iter()))
+            .collect::<Vec<&Method>>();
+        let async_functions = methods.iter().filter(|m| m.is_async).count();
+        let (lines, blank_lines, comment_lines) = count_lines(module_manager.source());
+
+        ModuleStats {
+            module: module_manager.module().to_string(),
+            classes: module_manager.classes().len(),
+            functions: module_manager.functions().len(),
+            vars: module_manager.vars().len(),
+            sync_functions: methods.len() - async_functions,
+            async_functions,
+            lines,
+            blank_lines,
+            comment_lines,
+        }
+    }
+
+    fn add(&mut self, other: &ModuleStats) {
+        self.classes += other.classes;
+        self.functions += other.functions;
+        self.vars += other.vars;
+        self.sync_functions += other.sync_functions;
+        self.async_functions += other.async_functions;
+        self.lines += other.lines;
+        self.blank_lines += other.blank_lines;
+        self.comment_lines += other.comment_lines;
+    }
+
+    /// Lines that are neither blank nor only a comment.
+    pub fn code_lines(&self) -> usize {
+        self.lines - self.blank_lines - self.comment_lines
+    }
+
+    /// Has functions, none of which are async.
+    pub fn is_fully_sync(&self) -> bool {
+        self.sync_functions > 0 && self.async_functions == 0
+    }
+}
+
+/// Counts the `(physical, blank, comment only)` lines of Python source.
+/// A `#` inside a string literal does not start a comment.
+pub fn count_lines(source: &str) -> (usize, usize, usize) {
+    let (_, comments) = literal_ranges(source);
+    let comment_starts = comments.iter().map(|c| c.start).collect::<HashSet<usize>>();
+
+    let mut lines = 0;
+    let mut blank_lines = 0;
+    let mut comment_lines = 0;
+    let mut offset = 0;
+
+    for line in source.split_inclusive('\n') {
+        lines += 1;
+
+        let indent = line.len() - line.trim_start().len();
+        if line.trim().is_empty() {
+            blank_lines += 1;
+        } else if comment_starts.contains(&(offset + indent)) {
+            comment_lines += 1;
+        }
+
+        offset += line.len();
+    }
+
+    (lines, blank_lines, comment_lines)
+}
+
+/// A function or method taking more arguments than allowed.
+#[derive(Debug, Clone, Serialize)]
+pub struct ArgCount {
+    pub module: String,
+    /// `function` or `Class.method`.
+    pub name: String,
+    pub arguments: usize,
+}
+
+/// The number of arguments a caller passes, counting `*args` and `**kwargs`
+/// as one each. The `self`/`cls` of methods are not counted.
+fn argument_count(method: &Method, is_method: bool) -> usize {
+    match method.arguments.first() {
+        Some(first) if is_method && (first.name == "self" || first.name == "cls") => {
+            method.arguments.len() - 1
+        }
+        _ => method.arguments.len(),
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub modules: Vec<ModuleStats>,
+    pub total: ModuleStats,
+    /// Functions over the `--max-args` threshold, if one was given.
+    pub too_many_args: Vec<ArgCount>,
+}
+
+impl Stats {
+    /// Collects the stats of each module, which should not be nested
+    /// (see `ModuleManager::all_modules`). Functions taking more than
+    /// `max_args` arguments are listed in `too_many_args`.
+    pub fn collect(module_managers: &[ModuleManager], max_args: Option<usize>) -> Stats {
+        let mut stats = Stats::default();
+        stats.total.module = String::from("total");
+
+        for module_manager in module_managers {
+            let module_stats = ModuleStats::from_module(module_manager);
+            stats.total.add(&module_stats);
+            stats.modules.push(module_stats);
+
+            if let Some(max_args) = max_args {
+                stats
+                    .too_many_args
+                    .extend(Self::too_many_args(module_manager, max_args));
+            }
+        }
+
+        stats
+    }
+
+    fn too_many_args(module_manager: &ModuleManager, max_args: usize) -> Vec<ArgCount> {
+        let functions = module_manager
+            .functions()
+            .iter()
+            .map(|f| (f.name.clone(), argument_count(f, false)));
+        let methods = module_manager.classes().iter().flat_map(|c| {
+            c.methods
+                .iter()
+                .map(move |m| (format!("{}.{}", c.name, m.name), argument_count(m, true)))
+        });
+
+        functions
+            .chain(methods)
+            .filter(|(_, arguments)| *arguments > max_args)
+            .map(|(name, arguments)| ArgCount {
+                module: module_manager.module().to_string(),
+                name,
+                arguments,
+            })
+            .collect()
+    }
+
+    pub fn fully_sync_modules(&self) -> Vec<&str> {
+        self.modules
+            .iter()
+            .filter(|m| m.is_fully_sync())
+            .map(|m| m.module.as_str())
+            .collect()
+    }
+}
+
+/// How the definitions of a module changed between two versions of its
+/// file, see `StatsDelta::compare`.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct StatsDelta {
+    pub module: String,
+    pub classes: isize,
+    pub functions: isize,
+    pub vars: isize,
+    /// The definitions only in the head version, as `function name`.
+    pub added: Vec<String>,
+    /// The definitions only in the base version, as `class Name`.
+    pub removed: Vec<String>,
+}
+
+impl StatsDelta {
+    /// Compares the definitions of a module at `base` and `head`. A module
+    /// missing on one side, because the file was added or deleted, counts as
+    /// empty there.
+    pub fn compare(base: Option<&ModuleManager>, head: Option<&ModuleManager>) -> StatsDelta {
+        let definitions = |module_manager: Option<&ModuleManager>| -> Vec<String> {
+            let module_manager = match module_manager {
+                Some(module_manager) => module_manager,
+                None => return Vec::new(),
+            };
+
+            let classes = module_manager.classes().iter().map(|c| format!("class {}", c.name));
+            let functions = module_manager
+                .functions()
+                .iter()
+                .map(|f| format!("function {}", f.name));
+            let vars = module_manager.vars().iter().map(|v| format!("variable {}", v.name));
+
+            classes.chain(functions).chain(vars).collect()
+        };
+        let stats = |module_manager: Option<&ModuleManager>| match module_manager {
+            Some(module_manager) => ModuleStats::from_module(module_manager),
+            None => ModuleStats::default(),
+        };
+
+        let (base_defs, head_defs) = (definitions(base), definitions(head));
+        let (base_stats, head_stats) = (stats(base), stats(head));
+
+        StatsDelta {
+            module: head.or(base).map(|m| m.module().to_string()).unwrap_or_default(),
+            classes: head_stats.classes as isize - base_stats.classes as isize,
+            functions: head_stats.functions as isize - base_stats.functions as isize,
+            vars: head_stats.vars as isize - base_stats.vars as isize,
+            added: head_defs.iter().filter(|d| !base_defs.contains(d)).cloned().collect(),
+            removed: base_defs.iter().filter(|d| !head_defs.contains(d)).cloned().collect(),
+        }
+    }
+
+    /// Did the definitions change at all?
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module_manager::{ModuleType, ProjectSettings};
+
+    #[test]
+    fn test_async_inventory() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_async", ModuleType::File, false).unwrap();
+        module_manager.parse().unwrap();
+
+        let mut sync_module =
+            ModuleManager::new("tests_data.test_duplicates", ModuleType::File, false).unwrap();
+        sync_module.parse().unwrap();
+
+        let stats = Stats::collect(&[module_manager, sync_module], None);
+        assert_eq!(stats.modules[0].sync_functions, 2);
+        assert_eq!(stats.modules[0].async_functions, 2);
+        assert_eq!(stats.total.async_functions, 2);
+        assert_eq!(stats.fully_sync_modules(), vec!["tests_data.test_duplicates"]);
+    }
+
+    #[test]
+    fn test_scoped_stats() {
+        let settings = ProjectSettings::default();
+        let module_managers =
+            ModuleManager::modules_under("tests_data.test_inheritance", &settings).unwrap();
+        let stats = Stats::collect(&module_managers, None);
+
+        let modules = stats.modules.iter().map(|m| m.module.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            modules,
+            vec![
+                "tests_data.test_inheritance",
+                "tests_data.test_inheritance.base",
+                "tests_data.test_inheritance.child",
+            ]
+        );
+        assert!(ModuleManager::modules_under("tests_data.test_missing", &settings).is_err());
+    }
+
+    #[test]
+    fn test_count_lines() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_lines", ModuleType::File, false).unwrap();
+        module_manager.parse().unwrap();
+
+        let stats = Stats::collect(&[module_manager], None);
+        assert_eq!(stats.modules[0].lines, 12);
+        assert_eq!(stats.modules[0].blank_lines, 4);
+        assert_eq!(stats.modules[0].comment_lines, 3);
+        assert_eq!(stats.modules[0].code_lines(), 5);
+        assert_eq!(stats.total.lines, 12);
+
+        assert_eq!(count_lines(""), (0, 0, 0));
+        assert_eq!(count_lines("x = 1"), (1, 0, 0));
+    }
+
+    #[test]
+    fn test_too_many_args() {
+        let mut module_manager =
+            ModuleManager::new("tests_data.test_many_args", ModuleType::File, false).unwrap();
+        module_manager.parse().unwrap();
+
+        let stats = Stats::collect(&[module_manager], Some(5));
+        let flagged = stats
+            .too_many_args
+            .iter()
+            .map(|a| (a.name.as_str(), a.arguments))
+            .collect::<Vec<_>>();
+        assert_eq!(flagged, vec![("build", 7)]);
+    }
+
+    #[test]
+    fn test_compare() {
+        let path = std::path::Path::new("pkg/api.py");
+        let base = ModuleManager::from_source(path, String::from("def get():\n    pass\n"))
+            .unwrap();
+        let head = ModuleManager::from_source(
+            path,
+            String::from("def get():\n    pass\n\n\ndef post():\n    pass\n"),
+        )
+        .unwrap();
+
+        let delta = StatsDelta::compare(Some(&base), Some(&head));
+        assert_eq!(delta.module, "pkg.api");
+        assert_eq!(delta.functions, 1);
+        assert_eq!(delta.classes, 0);
+        assert_eq!(delta.added, vec!["function post"]);
+        assert!(delta.removed.is_empty());
+
+        let deleted = StatsDelta::compare(Some(&head), None);
+        assert_eq!(deleted.functions, -2);
+        assert_eq!(deleted.removed, vec!["function get", "function post"]);
+    }
+}
@@ -0,0 +1,349 @@
+use assert_cmd::Command;
+use predicates::str::contains;
+
+#[test]
+fn test_not_found_module() {
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["view", "tests_data.test_missing"])
+        .assert()
+        .code(2);
+
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "x", "tests_data.test_missing"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_find_no_matches() {
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "no_such_name", "tests_data.test_module"])
+        .assert()
+        .code(6);
+
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "TestClass", "tests_data.test_module"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_find_fail_on_match() {
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "TestClass", "tests_data.test_module", "--fail-on-match"])
+        .assert()
+        .code(7);
+
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "TestClass", "tests_data.test_module", "-f", "--fail-on-match"])
+        .assert()
+        .success();
+
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "no_such_name", "tests_data.test_module", "--fail-on-match"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_parse_error() {
+    std::fs::write("tests_data/test_parse_error.py", "def broken(:\n").unwrap();
+
+    let viewed = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["view", "tests_data.test_parse_error"])
+        .assert();
+    let found = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "broken", "tests_data/test_parse_error.py"])
+        .assert();
+    std::fs::remove_file("tests_data/test_parse_error.py").unwrap();
+
+    viewed.code(3).stderr(contains("Failed to parse"));
+    found.code(3);
+
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "broken", "-"])
+        .write_stdin("def broken(:\n")
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn test_file_path() {
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["view", "tests_data/test_module.py", "-c"])
+        .assert()
+        .success()
+        .stdout(contains("TestClass"))
+        .stdout(contains("test_function2"));
+
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "TestClass", "-"])
+        .write_stdin("class TestClass:\n    pass\n")
+        .assert()
+        .success()
+        .stdout(contains("TestClass"));
+
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["view", "tests_data/test_missing.py"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_project_root() {
+    std::fs::create_dir_all("tests_data/test_root/pkg/deep").unwrap();
+    std::fs::write("tests_data/test_root/pyproject.toml", "").unwrap();
+    std::fs::write("tests_data/test_root/pkg/__init__.py", "class Found:\n    pass\n").unwrap();
+
+    let nested = Command::cargo_bin("rp")
+        .unwrap()
+        .current_dir("tests_data/test_root/pkg/deep")
+        .args(["view", "pkg", "-c"])
+        .assert();
+    let overridden = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["--root", "tests_data/test_root", "view", "pkg", "-c"])
+        .assert();
+    std::fs::remove_dir_all("tests_data/test_root").unwrap();
+
+    nested.success().stdout(contains("Found"));
+    overridden.success().stdout(contains("Found"));
+}
+
+#[test]
+fn test_find_jsonl() {
+    let output = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "test_function", "tests_data.test_module", "--jsonl"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let records = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(records.len(), 3);
+    assert!(!stdout.contains('\u{1b}'));
+    assert_eq!(records[0]["kind"], "function");
+    assert_eq!(records[0]["name"], "test_function");
+
+    let output = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["find", "Root", "--subclasses", "--jsonl"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let names = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|record| record["module"] == "tests_data.test_inheritance.child")
+        .map(|record| record["name"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(names, vec!["Child", "Other"]);
+}
+
+#[test]
+fn test_verify_imports() {
+    std::fs::create_dir_all("tests_data/test_verify").unwrap();
+    std::fs::write("tests_data/test_verify/pyproject.toml", "").unwrap();
+    std::fs::write("tests_data/test_verify/stale.py", "import os\nfrom old.sub import f\n").unwrap();
+
+    let stale = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["--root", "tests_data/test_verify", "verify-imports", "old"])
+        .assert();
+    let clean = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["--root", "tests_data/test_verify", "verify-imports", "new"])
+        .assert();
+    std::fs::remove_dir_all("tests_data/test_verify").unwrap();
+
+    stale
+        .code(7)
+        .stdout(contains("stale.py:2"))
+        .stdout(contains("from old.sub import f"));
+    clean.success();
+}
+
+#[test]
+fn test_wide_scan() {
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["--root", "/", "find", "x"])
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .code(1)
+        .stderr(contains("--allow-wide-scan"));
+}
+
+#[test]
+fn test_root_package() {
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["--root-package", "tests_data", "view", "test_module", "-c"])
+        .assert()
+        .success()
+        .stdout(contains("TestClass"));
+
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["--root-package", "tests_data", "explain", "test_module"])
+        .assert()
+        .success()
+        .stdout(contains("tests_data/test_module.py"));
+}
+
+#[test]
+fn test_root_package_mv() {
+    std::fs::create_dir_all("tests_data/test_root_mv/pkg").unwrap();
+    std::fs::write("tests_data/test_root_mv/pyproject.toml", "").unwrap();
+    std::fs::write("tests_data/test_root_mv/pkg/__init__.py", "").unwrap();
+    std::fs::write("tests_data/test_root_mv/pkg/a.py", "x = 1\n").unwrap();
+    std::fs::write("tests_data/test_root_mv/user.py", "from pkg.a import x\n").unwrap();
+
+    // Without a terminal or --yes, the move stops at the confirmation.
+    let refused = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["--root", "tests_data/test_root_mv", "--root-package", "pkg", "mv", "a", "b"])
+        .assert();
+    let kept = std::path::Path::new("tests_data/test_root_mv/pkg/a.py").exists();
+    std::fs::remove_dir_all("tests_data/test_root_mv").unwrap();
+
+    refused
+        .code(1)
+        .stdout(contains("Refusing to rewrite imports in 1 files without --yes"));
+    assert!(kept);
+}
+
+#[test]
+fn test_add_force() {
+    std::fs::write("tests_data/test_add_force.py", "x = 1\n").unwrap();
+
+    let template = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["add", "tests_data.test_add_force", "-f", "--force", "--template-dir", "tests_data"])
+        .assert();
+    let dry_run = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["add", "tests_data.test_add_force", "-f", "--force", "--dry-run"])
+        .assert();
+    let contents = std::fs::read_to_string("tests_data/test_add_force.py").unwrap();
+    std::fs::remove_file("tests_data/test_add_force.py").unwrap();
+
+    template.failure().stderr(contains("Templates can only be applied to packages"));
+    dry_run.success().stdout(contains("Would reset"));
+    assert_eq!(contents, "x = 1\n");
+
+    Command::cargo_bin("rp")
+        .unwrap()
+        .args(["add", "--from-spec", "spec.txt", "--force"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_anchored_template_dir() {
+    std::fs::create_dir_all("tests_data/test_anchor/deep").unwrap();
+    std::fs::create_dir_all("tests_data/test_anchor/template").unwrap();
+    std::fs::write("tests_data/test_anchor/pyproject.toml", "").unwrap();
+    std::fs::write("tests_data/test_anchor/template/README.md", "# {module}\n").unwrap();
+
+    // `../template` is relative to where rp runs, not to the project root.
+    let added = Command::cargo_bin("rp")
+        .unwrap()
+        .current_dir("tests_data/test_anchor/deep")
+        .args(["add", "pkg", "--template-dir", "../template"])
+        .assert();
+    let readme = std::fs::read_to_string("tests_data/test_anchor/pkg/README.md");
+    std::fs::remove_dir_all("tests_data/test_anchor").unwrap();
+
+    added.success();
+    assert_eq!(readme.unwrap(), "# pkg\n");
+}
+
+#[test]
+fn test_count_per_module_decorators() {
+    std::fs::create_dir_all("tests_data/test_count_decorators/pkg").unwrap();
+    std::fs::write("tests_data/test_count_decorators/pyproject.toml", "").unwrap();
+    std::fs::write("tests_data/test_count_decorators/pkg/__init__.py", "").unwrap();
+    std::fs::write(
+        "tests_data/test_count_decorators/pkg/views.py",
+        "@app.route(\"/\")\ndef index():\n    pass\n\n\n@app.route(\"/about\")\ndef about():\n    pass\n",
+    )
+    .unwrap();
+
+    let counted = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["--root", "tests_data/test_count_decorators"])
+        .args(["find", "app.route", "pkg", "--decorator", "--count-per-module"])
+        .assert();
+    std::fs::remove_dir_all("tests_data/test_count_decorators").unwrap();
+
+    counted
+        .success()
+        .stdout(contains("pkg.views"))
+        .stdout(contains("total: 2"));
+}
+
+#[test]
+fn test_flat_signature() {
+    std::fs::create_dir_all("tests_data/test_flat_signature/pkg").unwrap();
+    std::fs::write("tests_data/test_flat_signature/pyproject.toml", "").unwrap();
+    std::fs::write("tests_data/test_flat_signature/pkg/__init__.py", "").unwrap();
+    std::fs::write(
+        "tests_data/test_flat_signature/pkg/views.py",
+        "def index(request: Request):\n    pass\n\n\ndef helper(x):\n    pass\n",
+    )
+    .unwrap();
+
+    let flat = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["--root", "tests_data/test_flat_signature"])
+        .args(["find", "request: Request", "pkg", "--signature", "--flat"])
+        .assert();
+    std::fs::remove_dir_all("tests_data/test_flat_signature").unwrap();
+
+    flat.success()
+        .stdout(contains("pkg/views.py"))
+        .stdout(contains("def index(request: Request):"));
+}
+
+#[test]
+fn test_show_empty_decorator() {
+    std::fs::create_dir_all("tests_data/test_show_empty_decorator/pkg").unwrap();
+    std::fs::write("tests_data/test_show_empty_decorator/pyproject.toml", "").unwrap();
+    std::fs::write("tests_data/test_show_empty_decorator/pkg/__init__.py", "").unwrap();
+    std::fs::write(
+        "tests_data/test_show_empty_decorator/pkg/views.py",
+        "@app.route(\"/\")\ndef index():\n    pass\n",
+    )
+    .unwrap();
+
+    let shown = Command::cargo_bin("rp")
+        .unwrap()
+        .args(["--root", "tests_data/test_show_empty_decorator"])
+        .args(["find", "app.route", "pkg/views.py", "--decorator", "--show-empty"])
+        .assert();
+    std::fs::remove_dir_all("tests_data/test_show_empty_decorator").unwrap();
+
+    shown.success().stdout(contains("index"));
+}
@@ -0,0 +1,14 @@
+def fetch():
+    pass
+
+
+async def afetch():
+    pass
+
+
+class Client:
+    def close(self):
+        pass
+
+    async def send(self):
+        pass
@@ -0,0 +1,7 @@
+import os
+
+
+def f():
+    x = 1
+    # TODO: fix
+    return x
@@ -0,0 +1,26 @@
+def foo():
+    pass
+
+
+counter = 0
+counter = 1
+
+
+def foo(a):
+    pass
+
+
+class Bar:
+    @property
+    def value(self):
+        return 1
+
+    @value.setter
+    def value(self, value):
+        pass
+
+    def run(self):
+        pass
+
+    def run(self, fast=False):
+        pass
@@ -0,0 +1,6 @@
+class Root:
+    pass
+
+
+class Base(Root):
+    pass
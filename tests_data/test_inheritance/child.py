@@ -0,0 +1,10 @@
+from .base import Base
+import tests_data.test_inheritance.base as b
+
+
+class Child(Base):
+    pass
+
+
+class Other(b.Base, dict):
+    pass
@@ -0,0 +1,12 @@
+#!/usr/bin/env python
+"""Module docstring with a # that is not a comment."""
+
+# A comment
+import os  # trailing comment, still code
+
+
+def f():
+    # indented comment
+    s = "# not a comment"
+
+    return s
@@ -0,0 +1,11 @@
+def build(a, b, c, *args, d, e, **kwargs):
+    pass
+
+
+def small(a, b):
+    pass
+
+
+class Builder:
+    def configure(self, a, b, c, d, e):
+        pass
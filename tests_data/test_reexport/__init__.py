@@ -0,0 +1,2 @@
+from .impl import Foo
+from os import path
@@ -0,0 +1,6 @@
+#!/usr/bin/env python
+# -*- coding: utf-8 -*-
+# TODO: use a session
+import requests
+
+requests.get("https://example.com")
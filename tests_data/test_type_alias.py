@@ -0,0 +1,5 @@
+from typing import TypeAlias
+
+UserId: TypeAlias = int
+type Point = tuple[float, float]
+count: int = 0
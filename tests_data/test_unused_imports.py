@@ -0,0 +1,9 @@
+from tests_data.test_inheritance.base import Base, Root
+from .test_many_args import build
+import os
+
+__all__ = ["build"]
+
+
+class Child(Base):
+    pass